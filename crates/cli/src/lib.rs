@@ -54,7 +54,7 @@ pub async fn exec_subcommand(
         "energy" => energy::exec(config, args).await,
         "publish" => publish::exec(config, args).await,
         "delete" => delete::exec(config, args).await,
-        "logs" => logs::exec(config, args).await,
+        "logs" => return logs::exec(config, args).await,
         "sql" => sql::exec(config, args).await,
         "rename" => dns::exec(config, args).await,
         "generate" => generate::exec(config, args).await,
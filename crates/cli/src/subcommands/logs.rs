@@ -1,22 +1,39 @@
 use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ffi::OsString;
+use std::fs::File;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::{Child, Command, ExitCode, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 use crate::common_args;
 use crate::config::Config;
 use crate::util::{add_auth_header_opt, database_identity, get_auth_header};
+use anyhow::Context;
+use async_compression::futures::bufread::GzipDecoder;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use clap::parser::ValueSource;
 use clap::{Arg, ArgAction, ArgMatches};
-use futures::{AsyncBufReadExt, TryStreamExt};
+use futures::{AsyncBufRead, AsyncBufReadExt, FutureExt, TryStreamExt};
 use is_terminal::IsTerminal;
+use regex::Regex;
+use tabled::settings::{object::Columns, Alignment, Modify, Style};
 use termcolor::{Color, ColorSpec, WriteColor};
-use tokio::io::AsyncWriteExt;
 
 pub fn cli() -> clap::Command {
     clap::Command::new("logs")
         .about("Prints logs from a SpacetimeDB database")
         .arg(
             Arg::new("database")
-                .required(true)
-                .help("The name or identity of the database to print logs from"),
+                .required_unless_present_any(["print_bq_schema", "from_file"])
+                .num_args(1..)
+                .help("The name or identity of the database to print logs from")
+                .long_help("The name or identity of the database to print logs from. Given several, their logs are merged by timestamp, or in order of arrival with `--follow`, and each record in the `text` and `compact` formats is prefixed with the name of its database, padded to the longest."),
         )
         .arg(
             common_args::server()
@@ -26,9 +43,45 @@ pub fn cli() -> clap::Command {
             Arg::new("num_lines")
                 .long("num-lines")
                 .short('n')
+                .visible_alias("tail")
                 .value_parser(clap::value_parser!(u32))
+                .help("The number of lines to print from the end of the log of this database")
+                .long_help("The number of lines to print from the end of the log of this database, i.e., the most recent ones, like `tail -n`. If neither this nor `--head` is provided, all lines will be returned, or the last 10 when following. With `0`, no lines are printed, so `--num-lines 0 --follow` only prints the records logged from then on, like `tail -n 0 -f`."),
+        )
+        .arg(
+            Arg::new("head")
+                .long("head")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32))
+                .conflicts_with_all(["num_lines", "follow"])
                 .help("The number of lines to print from the start of the log of this database")
-                .long_help("The number of lines to print from the start of the log of this database. If no num lines is provided, all lines will be returned."),
+                .long_help("The number of lines to print from the start of the log of this database, i.e., the oldest ones, like `head -n`. Lines are counted before any filtering, as with `--num-lines`."),
+        )
+        .arg(
+            Arg::new("page_size")
+                .long("page-size")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .conflicts_with_all(["num_lines", "head", "follow", "fail_on", "group_by", "table_key", "pager"])
+                .help("Print the logs in pages of N lines, from the most recent ones backwards")
+                .long_help("Print the logs in pages of N lines, from the most recent ones backwards, each in chronological order. In a terminal, the next page is fetched once Enter is pressed, until `q` is entered or the start of the log is reached. Otherwise, only one page is printed. Either way, the `--page-token` of the next page, if any, is printed on stderr when stopping."),
+        )
+        .arg(
+            Arg::new("page_token")
+                .long("page-token")
+                .value_name("TOKEN")
+                .value_parser(clap::value_parser!(PageToken))
+                .requires("page_size")
+                .help("Start `--page-size` at the page named by TOKEN, as printed when the previous one was stopped at"),
+        )
+        .arg(
+            Arg::new("from_file")
+                .long("from-file")
+                .value_name("PATH")
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with_all(["database", "server", "num_lines", "follow", "page_size"])
+                .help("Read the logs from a file instead of the server")
+                .long_help("Read the logs from a file instead of the server, e.g., one written with `--format json`, and print them as usual. A PATH of `-` reads them from stdin."),
         )
         .arg(
             Arg::new("follow")
@@ -39,6 +92,22 @@ pub fn cli() -> clap::Command {
                 .help("A flag indicating whether or not to follow the logs")
                 .long_help("A flag that causes logs to not stop when end of the log file is reached, but rather to wait for additional data to be appended to the input."),
         )
+        .arg(
+            Arg::new("retry")
+                .long("retry")
+                .action(ArgAction::SetTrue)
+                .requires("follow")
+                .help("Reconnect when the connection drops while following the logs")
+                .long_help("Reconnect when the connection drops or the server fails while following the logs, waiting twice as long after each failed attempt, up to `--retry-max-delay`. After reconnecting, records that were already printed are skipped. Stops on other errors, e.g., if not authorized."),
+        )
+        .arg(
+            Arg::new("retry_max_delay")
+                .long("retry-max-delay")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64))
+                .requires("retry")
+                .help("The longest to wait between two attempts to reconnect with `--retry`, 30 seconds by default"),
+        )
         .arg(
             Arg::new("format")
                 .long("format")
@@ -47,11 +116,324 @@ pub fn cli() -> clap::Command {
                 .value_parser(clap::value_parser!(Format))
                 .help("Output format for the logs")
         )
+        .arg(
+            Arg::new("raw")
+                .long("raw")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["format", "group_by", "count", "table_key", "pager"])
+                .help("Print each line as received from the server, without parsing it")
+                .long_help("Print each line as received from the server, without parsing it, e.g. to see the logs of a server whose records this version can't read. The records are neither filtered nor formatted, so the options doing so are ignored, except for `--level`, which the server applies."),
+        )
+        .arg(
+            Arg::new("validate")
+                .long("validate")
+                .action(ArgAction::SetTrue)
+                .help("Report the lines that aren't valid records on stderr")
+                .long_help("Report each line that isn't a valid record on stderr, with its number among the lines read, the line itself and why it isn't valid, e.g. to debug the log protocol. The logs are printed as usual otherwise."),
+        )
+        .arg(
+            Arg::new("json_fields")
+                .long("json-fields")
+                .value_name("FIELDS")
+                .value_delimiter(',')
+                .value_parser(clap::value_parser!(JsonField))
+                .help("The comma-separated fields of the records to print in the `json` format")
+                .long_help("The comma-separated fields of the records to print in the `json` format, e.g. \"ts,level,message\". The records are normalized as in the `bq-json` format, with an RFC 3339 `timestamp`."),
+        )
+        .arg(
+            Arg::new("json_pretty")
+                .long("json-pretty")
+                .action(ArgAction::SetTrue)
+                .help("Indent each record in the `json` format")
+                .long_help("Indent each record in the `json` format, keeping the fields as received unless `--json-fields` is given. Lines that aren't valid JSON are printed as they are."),
+        )
+        .arg(
+            Arg::new("json_enrich")
+                .long("json-enrich")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("from_file")
+                .help("Add the database identity and server URL to each record in the `json` format")
+                .long_help("Add the fields `db`, the identity of the database, and `server`, the URL of the server, to each record in the `json` format, so that the records of several databases can be told apart once merged."),
+        )
+        .arg(
+            Arg::new("output_template")
+                .long("output-template")
+                .alias("template")
+                .value_parser(clap::value_parser!(Template))
+                .help("A custom template for each log line, e.g. \"{ts} [{level}] {file}:{line} {message}\"")
+                .long_help("A custom template for each log line, e.g. \"{ts} [{level}] {file}:{line} {message}\". Each `{field}` placeholder is replaced by that field of the record, or left empty if it has none, and `{{` and `}}` stand for literal braces. Available fields are `ts`, `level`, `target`, `file` (or `filename`), `line` (or `line_number`) and `message` (or `msg`), and unknown ones are rejected. Only the level is colored. Only applies to the `text` format."),
+        )
+        .arg(
+            Arg::new("no_trace")
+                .long("no-trace")
+                .action(ArgAction::SetTrue)
+                .help("Don't print the backtraces of records")
+                .long_help("Don't print the backtraces of records. Only applies to the `text` format."),
+        )
+        .arg(
+            Arg::new("demangle")
+                .long("demangle")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("no_trace")
+                .help("Demangle the Rust symbols of functions in backtraces")
+                .long_help("Demangle the Rust symbols of functions in backtraces, leaving other names as they are. Only applies to the `text` format."),
+        )
+        .arg(
+            Arg::new("show_target")
+                .long("show-target")
+                .action(ArgAction::SetTrue)
+                .help("Print the target of each record in brackets before its message")
+                .long_help("Print the target of each record, i.e. the module that logged it, in brackets before its message. Records without a target are printed as usual. Only applies to the `text` format."),
+        )
+        .arg(
+            Arg::new("level_style")
+                .long("level-style")
+                .value_name("STYLE")
+                .default_value("upper")
+                .value_parser(clap::value_parser!(LevelStyle))
+                .help("How to print the level of each record")
+                .long_help("How to print the level of each record: `upper`, e.g. \"ERROR\", `lower`, e.g. \"error\", `letter`, e.g. \"E\", or `bracketed`, e.g. \"[ERROR]\". The levels are padded to the widest of them, so that the records line up. Only applies to the `text` format."),
+        )
+        .arg(
+            Arg::new("humanize")
+                .long("humanize")
+                .action(ArgAction::SetTrue)
+                .help("Print sizes in messages in KiB, MiB, etc., rather than in bytes")
+                .long_help("Print sizes in messages, like \"1572864 bytes\", in KiB, MiB, etc., like \"1.5 MiB\", rather than in bytes. `--grep` matches the messages as printed. Only applies to the `text` and `compact` formats, so the `json` format keeps the messages as they are."),
+        )
+        .arg(
+            Arg::new("timezone")
+                .long("timezone")
+                .value_name("ZONE")
+                .value_parser(clap::value_parser!(TimeZone))
+                .help("The time zone to print timestamps in: `utc`, `local` or an offset like `+02:00`")
+                .long_help("The time zone to print timestamps in: `utc`, `local` or an offset like `+02:00`. Only applies to the `text` and `compact` formats."),
+        )
+        .arg(
+            Arg::new("time_format")
+                .long("time-format")
+                .value_name("FORMAT")
+                .value_parser(clap::value_parser!(TimeFormat))
+                .help("How to print timestamps: `rfc3339`, `millis` or a strftime format like \"%H:%M:%S%.3f\"")
+                .long_help("How to print timestamps: `rfc3339`, `millis` since the Unix epoch, or a strftime format like \"%H:%M:%S%.3f\". Defaults to \"%H:%M:%S\" for the `compact` format, to `rfc3339` if `--timezone` is given, and otherwise to the UTC rendering of earlier versions. Only applies to the `text` and `compact` formats."),
+        )
+        .arg(
+            Arg::new("pager")
+                .long("pager")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("")
+                .help("Pipe the logs through a pager")
+                .long_help("Pipe the logs through a pager. If no pager is given, `$PAGER` is used, falling back to `less -R`. Colors are only kept for pagers known to interpret them, e.g. `less -R`, unless overridden with `--color`. Only applies to the `text` and `compact` formats."),
+        )
+        .arg(
+            Arg::new("line_buffered")
+                .long("line-buffered")
+                .action(ArgAction::SetTrue)
+                .help("Flush the output after each record")
+                .long_help("Flush the output after each record, rather than once no more records have arrived yet, or at the latest every 100ms. Only applies to the `text` and `compact` formats."),
+        )
+        .arg(
+            Arg::new("dedup")
+                .long("dedup")
+                .value_name("KEY")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("message")
+                .value_parser(clap::value_parser!(DedupKey))
+                .help("Collapse consecutive records with the same message")
+                .long_help("Collapse consecutive records with the same message into the first of them, followed by `(repeated N times)` once a different record arrives or no more records have arrived yet, and at least every 5s while the repeats go on. With `--dedup=all`, records must also have the same level, filename and line to be collapsed. Only applies to the `text` and `compact` formats."),
+        )
+        .arg(
+            Arg::new("sample")
+                .long("sample")
+                .value_name("1/N")
+                .value_parser(clap::value_parser!(Sample))
+                .help("Only print every Nth record")
+                .long_help("Only print every Nth record, starting with the first, e.g. \"1/10\", to keep up with logs arriving faster than they can be printed. How many records were skipped is printed at least every second while skipping, and once no more records have arrived yet. Only applies to the `text` and `compact` formats."),
+        )
+        .arg(
+            Arg::new("max_rate")
+                .long("max-rate")
+                .value_name("RATE")
+                .value_parser(clap::value_parser!(Rate))
+                .conflicts_with("sample")
+                .help("Print at most this many records per second or minute, e.g. \"100/s\"")
+                .long_help("Print at most this many records per second or minute, e.g. \"100/s\" or \"600/m\", skipping the others, to keep up with logs arriving faster than they can be printed. How many records were skipped is printed as with `--sample`. Only applies to the `text` and `compact` formats."),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .value_name("PATH")
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with("pager")
+                .help("Append the logs to a file instead of printing them, or print them if `-`")
+                .long_help("Append the logs to the file at PATH, creating it if needed, instead of printing them. The logs are not colored unless `--color always` is given. A PATH of `-` prints them as usual."),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .default_value("auto")
+                .value_parser(clap::value_parser!(ColorMode))
+                .help("When to color the logs")
+                .long_help("When to color the logs. `auto` colors the logs when printing to a terminal or to a pager known to support colors. If not given, this is `never` if `$NO_COLOR` is set, or else `always` if `$CLICOLOR_FORCE` is set to anything but `0`, or else `auto`."),
+        )
+        .arg(
+            Arg::new("no_color")
+                .long("no-color")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("color")
+                .help("Never color the logs, the same as `--color never`"),
+        )
+        .arg(
+            Arg::new("color_scheme")
+                .long("color-scheme")
+                .value_name("SCHEME")
+                .value_parser(clap::value_parser!(ColorScheme))
+                .help("The colors of the levels, e.g. \"info=cyan,error=red+bold\"")
+                .long_help("The colors of the levels as a comma-separated list of `level=style`, e.g. \"info=cyan,error=red+bold\", where a style is a `+`-separated list of a color and the attributes `bold`, `dimmed`, `intense`, `italic` and `underline`. A color is a name like `red`, or a number from 0 to 255. Unlisted levels keep their default colors. Defaults to `$SPACETIME_LOG_COLORS`."),
+        )
+        .arg(
+            Arg::new("grep")
+                .long("grep")
+                .value_name("REGEX")
+                .value_parser(clap::value_parser!(Regex))
+                .help("Only print records whose message matches the regular expression")
+                .long_help("Only print records whose message matches the regular expression. Unlike piping to `grep`, this keeps each record together with its backtrace, and the colors of the `text` format."),
+        )
+        .arg(
+            Arg::new("grep_invert")
+                .long("grep-invert")
+                .action(ArgAction::SetTrue)
+                .requires("grep")
+                .help("Only print records whose message does not match `--grep`"),
+        )
+        .arg(
+            Arg::new("context")
+                .long("context")
+                .short('C')
+                .value_name("NUM")
+                .value_parser(clap::value_parser!(usize))
+                .requires("grep")
+                .help("Print NUM records of context, dimmed, around each record matching `--grep`")
+                .long_help("Print NUM records of context, dimmed, before and after each record matching `--grep`. Groups of records that are not adjacent are separated by `--`. Near the start of the stream, fewer than NUM records may precede a match."),
+        )
+        .arg(
+            Arg::new("level")
+                .long("level")
+                .value_name("LEVEL")
+                .value_parser(clap::value_parser!(LogLevel))
+                .help("Only print records of at least this level")
+                .long_help("Only print records of at least this level, in increasing order of severity: `trace`, `debug`, `info`, `warn`, `error` or `panic`."),
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .value_name("TIME")
+                .value_parser(clap::value_parser!(TimeArg))
+                .help("Only print records logged at or after TIME, e.g. \"2024-01-01T00:00:00Z\" or \"10m\" for ten minutes ago")
+                .long_help("Only print records logged at or after TIME, either in RFC 3339, e.g. \"2024-01-01T00:00:00Z\", or relative to now, as a number of seconds (`s`), minutes (`m`), hours (`h`), days (`d`) or weeks (`w`) ago, e.g. \"10m\". Records without a timestamp are printed unless `--drop-untimed`."),
+        )
+        .arg(
+            Arg::new("until")
+                .long("until")
+                .value_name("TIME")
+                .value_parser(clap::value_parser!(TimeArg))
+                .help("Only print records logged at or before TIME, in the format of `--since`"),
+        )
+        .arg(
+            Arg::new("drop_untimed")
+                .long("drop-untimed")
+                .action(ArgAction::SetTrue)
+                .help("Skip the records without a timestamp when filtering with `--since` or `--until`"),
+        )
+        .arg(
+            Arg::new("filter_file")
+                .long("filter-file")
+                .value_name("SUBSTR")
+                .help("Only print records whose filename contains SUBSTR, ignoring case unless `--case-sensitive`"),
+        )
+        .arg(
+            Arg::new("filter_target")
+                .long("filter-target")
+                .value_name("SUBSTR")
+                .help("Only print records whose target, e.g., module, contains SUBSTR, ignoring case unless `--case-sensitive`"),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(glob::Pattern))
+                .help("Only print records whose target matches GLOB, e.g., 'module::reducers::*'")
+                .long_help("Only print records whose target matches GLOB, e.g., 'module::reducers::*', where `*` matches any run of characters, including `::`. Can be given several times to print the records matching any of them. Records without a target are printed unless `--drop-untargeted`."),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(glob::Pattern))
+                .help("Skip the records whose target matches GLOB, e.g., '*::internal::*', even if they match `--include`"),
+        )
+        .arg(
+            Arg::new("drop_untargeted")
+                .long("drop-untargeted")
+                .action(ArgAction::SetTrue)
+                .help("Skip the records without a target when filtering with `--include` or `--exclude`"),
+        )
+        .arg(
+            Arg::new("case_sensitive")
+                .long("case-sensitive")
+                .action(ArgAction::SetTrue)
+                .help("Match `--filter-file`, `--filter-target`, `--include` and `--exclude` case-sensitively"),
+        )
+        .arg(
+            Arg::new("fail_on")
+                .long("fail-on")
+                .value_name("LEVEL")
+                .value_parser(clap::value_parser!(LogLevel))
+                .conflicts_with("follow")
+                .help("Fail if any record of at least this level was logged")
+                .long_help("Fail with a non-zero exit code after printing the logs if any record of at least this level was logged, regardless of the other filters. Cannot be used with `--follow`, as the logs never end."),
+        )
+        .arg(
+            Arg::new("group_by")
+                .long("group-by")
+                .value_parser(clap::value_parser!(GroupBy))
+                .conflicts_with("follow")
+                .help("Print the number of records per group instead of the records themselves")
+                .long_help("Print a table with the number of records per group, sorted by count, instead of the records themselves. Cannot be used with `--follow`."),
+        )
+        .arg(
+            Arg::new("count")
+                .long("count")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("group_by")
+                .help("Print the number of records of each level instead of the records themselves")
+                .long_help("Print a table with the number of records of each level, and their total, instead of the records themselves, e.g., with `--since` as a quick health check. When following, the table is printed again once no more records have arrived yet, and at least every second while they keep arriving, redrawn in place when printing to a terminal."),
+        )
+        .arg(
+            Arg::new("table_key")
+                .long("table-key")
+                .value_name("REGEX")
+                .value_parser(clap::value_parser!(Regex))
+                .conflicts_with_all(["group_by", "count", "pager"])
+                .help("Show a table of the latest message per key extracted from messages, updated in place")
+                .long_help("Show a table of the latest message and timestamp per key, updated in place as records arrive. The key is extracted from each message by the regular expression, using the capture group named `key`, or else the first capture group, or else the whole match. Records whose message does not match are not shown. Only applies to the `text` format, and only when printing to a terminal; otherwise, the logs are printed as usual."),
+        )
+        .arg(
+            Arg::new("print_bq_schema")
+                .long("print-bq-schema")
+                .action(ArgAction::SetTrue)
+                .help("Print the BigQuery table schema of the `bq-json` format and exit"),
+        )
         .arg(common_args::yes())
         .after_help("Run `spacetime help logs` for more detailed information.\n")
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, clap::ValueEnum, Clone, Copy)]
 pub enum LogLevel {
     Error,
     Warn,
@@ -61,21 +443,67 @@ pub enum LogLevel {
     Panic,
 }
 
+impl LogLevel {
+    /// Returns the severity of this level, from `Trace`, the lowest, to `Panic`, the highest.
+    fn severity(self) -> u8 {
+        match self {
+            Self::Trace => 0,
+            Self::Debug => 1,
+            Self::Info => 2,
+            Self::Warn => 3,
+            Self::Error => 4,
+            Self::Panic => 5,
+        }
+    }
+
+    /// Returns the three-letter label of this level, as printed by the `compact` format.
+    fn abbrev(self) -> &'static str {
+        match self {
+            Self::Trace => "TRC",
+            Self::Debug => "DBG",
+            Self::Info => "INF",
+            Self::Warn => "WRN",
+            Self::Error => "ERR",
+            Self::Panic => "PNC",
+        }
+    }
+}
+
+/// A record of the logs of a database, borrowing from the line it was parsed from where possible.
 #[serde_with::serde_as]
 #[derive(serde::Deserialize)]
-struct Record<'a> {
+pub struct Record<'a> {
     #[serde_as(as = "Option<serde_with::TimestampMicroSeconds>")]
-    ts: Option<chrono::DateTime<chrono::Utc>>, // TODO: remove Option once 0.9 has been out for a while
-    level: LogLevel,
+    pub ts: Option<chrono::DateTime<chrono::Utc>>, // TODO: remove Option once 0.9 has been out for a while
+    pub level: LogLevel,
     #[serde(borrow)]
-    #[allow(unused)] // TODO: format this somehow
-    target: Option<Cow<'a, str>>,
+    pub target: Option<Cow<'a, str>>,
     #[serde(borrow)]
-    filename: Option<Cow<'a, str>>,
-    line_number: Option<u32>,
+    pub filename: Option<Cow<'a, str>>,
+    pub line_number: Option<u32>,
     #[serde(borrow)]
-    message: Cow<'a, str>,
-    trace: Option<Vec<BacktraceFrame<'a>>>,
+    pub message: Cow<'a, str>,
+    pub trace: Option<Vec<BacktraceFrame<'a>>>,
+}
+
+/// A [`Record`] that owns its fields, as yielded by [`stream_logs`].
+pub type OwnedRecord = Record<'static>;
+
+impl Record<'_> {
+    /// Copies the fields borrowed from the line this record was parsed from.
+    pub fn into_owned(self) -> OwnedRecord {
+        Record {
+            ts: self.ts,
+            level: self.level,
+            target: self.target.map(|target| Cow::Owned(target.into_owned())),
+            filename: self.filename.map(|filename| Cow::Owned(filename.into_owned())),
+            line_number: self.line_number,
+            message: Cow::Owned(self.message.into_owned()),
+            trace: self
+                .trace
+                .map(|trace| trace.into_iter().map(BacktraceFrame::into_owned).collect()),
+        }
+    }
 }
 
 #[derive(serde::Deserialize)]
@@ -86,144 +514,3602 @@ pub struct BacktraceFrame<'a> {
     pub func_name: Option<Cow<'a, str>>,
 }
 
+impl BacktraceFrame<'_> {
+    /// Copies the fields borrowed from the line this frame was parsed from.
+    pub fn into_owned(self) -> BacktraceFrame<'static> {
+        BacktraceFrame {
+            module_name: self.module_name.map(|name| Cow::Owned(name.into_owned())),
+            func_name: self.func_name.map(|name| Cow::Owned(name.into_owned())),
+        }
+    }
+}
+
+/// What logs [`stream_logs`] requests, and how.
+#[derive(Clone, Default)]
+pub struct LogsOptions {
+    /// The nickname, host name or URL of the server hosting the database, or `None` for the default server.
+    pub server: Option<String>,
+    /// The number of lines to request from the end of the log,
+    /// or `None` for all of them, or for the last 10 if `follow`.
+    pub num_lines: Option<u32>,
+    /// Whether to keep waiting for new records once the existing ones were read.
+    pub follow: bool,
+    /// The lowest level of the records to yield.
+    pub min_level: Option<LogLevel>,
+    /// The longest delay between two attempts to reconnect when the stream drops, or `None` not to reconnect.
+    pub retry: Option<Duration>,
+    /// The number of records to read from the start of the log, if not all of them.
+    pub head: Option<u32>,
+    /// Whether to prompt to log in if needed.
+    pub interactive: bool,
+}
+
+/// A consumer of log records, e.g., a widget of a UI embedding the CLI, fed by [`sink_logs`].
+///
+/// The `text` and `compact` formats of `spacetime logs` are rendered by one, too.
+pub trait LogSink {
+    /// Consumes the next record.
+    fn record(&mut self, record: &Record<'_>) -> io::Result<()>;
+
+    /// Consumes the next line that isn't a record, e.g. from a newer server, which is ignored by default.
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        let _ = line;
+        Ok(())
+    }
+
+    /// Called when no more records have arrived yet, e.g., to flush those consumed so far.
+    fn idle(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once all the records were consumed.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Requests the logs of `database`, feeding its records to `sink` as they are read.
+///
+/// Like [`stream_logs`], this does no rendering, and only filters the records by level.
+pub async fn sink_logs(
+    config: &mut Config,
+    database: &str,
+    opts: &LogsOptions,
+    sink: &mut impl LogSink,
+) -> anyhow::Result<()> {
+    let mut logs = open_logs(config, database, opts, None, None).await?;
+    let mut line = String::new();
+    while logs.read_line(&mut line).await? {
+        match parse_record(&line) {
+            None => sink.line(&line)?,
+            // Servers that don't know `min_level` send the records of all levels.
+            Some(record)
+                if opts
+                    .min_level
+                    .is_none_or(|min| record.level.severity() >= min.severity()) =>
+            {
+                sink.record(&record)?
+            }
+            Some(_) => {}
+        }
+        if logs.is_idle() {
+            sink.idle()?;
+        }
+        line.clear();
+    }
+    sink.finish()?;
+    Ok(())
+}
+
+/// Requests the logs of `database`, yielding its records as they are read.
+///
+/// Unlike `spacetime logs`, this does no rendering, and only filters the records by level.
+pub async fn stream_logs(
+    config: &mut Config,
+    database: &str,
+    opts: &LogsOptions,
+) -> anyhow::Result<impl futures::Stream<Item = anyhow::Result<OwnedRecord>>> {
+    let logs = open_logs(config, database, opts, None, None).await?;
+    let min_level = opts.min_level;
+    Ok(futures::stream::try_unfold(
+        (logs, String::new()),
+        move |(mut logs, mut line)| async move {
+            let record = logs.read_record(&mut line, min_level).await;
+            record.map(|record| record.map(|record| (record, (logs, line))))
+        },
+    ))
+}
+
+/// Connects to the logs of `database` as described by `opts`, failing on records of at least `fail_on`,
+/// or requests them a page at a time if `paging` is given.
+async fn open_logs(
+    config: &mut Config,
+    database: &str,
+    opts: &LogsOptions,
+    fail_on: Option<LogLevel>,
+    paging: Option<Paging>,
+) -> anyhow::Result<LogStream> {
+    let server = opts.server.as_deref();
+    let auth_header = get_auth_header(config, false, server, opts.interactive).await?;
+
+    let database_identity = database_identity(config, database, server).await?;
+
+    let num_lines = if opts.follow && opts.num_lines.is_none() {
+        // We typically don't want logs from the very beginning if we're also following,
+        // but only the most recent ones, as the server sends the last `num_lines`.
+        Some(10)
+    } else {
+        opts.num_lines
+    };
+    let host_url = config.get_host_url(server)?;
+
+    let builder = reqwest::Client::new().get(format!("{host_url}/v1/database/{database_identity}/logs"));
+    let builder = add_auth_header_opt(builder, &auth_header);
+    let mut logs = match paging {
+        Some(paging) => LogStream::paged(builder, opts.min_level, paging).await?,
+        None => {
+            LogStream::connect(
+                builder,
+                num_lines,
+                opts.follow,
+                opts.min_level,
+                opts.retry,
+                fail_on,
+                opts.head,
+            )
+            .await?
+        }
+    };
+    logs.origin = Some(LogOrigin {
+        db: database_identity.to_string(),
+        server: host_url,
+    });
+    Ok(logs)
+}
+
+/// The query of `GET /v1/database/:name_or_identity/logs`.
+///
+/// `min_level` asks the server to only send the records of that level or above,
+/// named as `--level` accepts them, e.g. `warn`, and is left out of the query when `None`.
+/// Servers that don't know it ignore it and send all records,
+/// so the records are still filtered by level once received.
+///
+/// `cursor` asks the server for the `num_lines` lines preceding a position in the log,
+/// as named by the [`CURSOR_HEADER`] of an earlier response, and is left out of the query when `None`.
+/// Servers that know it name the position of the first line of each response in that header,
+/// leaving it out when no lines precede it.
+/// Servers that don't know it would send the most recent lines again, so it is only sent once
+/// a server returned that header; otherwise, `--page-size` requests ever more of the most recent lines,
+/// and only keeps the oldest of them.
 #[derive(serde::Serialize)]
 struct LogsParams {
     num_lines: Option<u32>,
     follow: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_level: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
 }
 
-#[derive(Clone, Copy, PartialEq)]
-pub enum Format {
-    Text,
-    Json,
+/// The header of the responses of servers that support the `cursor` of [`LogsParams`].
+const CURSOR_HEADER: &str = "spacetime-logs-cursor";
+
+/// The number of lines to request again when reconnecting with `--retry`,
+/// to resume near where the dropped stream left off.
+const RESUME_LINES: u32 = 100;
+
+/// The longest the `text` and `compact` formats keep records buffered, unless `--line-buffered`.
+/// They are also flushed whenever no more records have arrived yet.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The longest `--count` keeps the table stale while following.
+const COUNT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The longest `--dedup` keeps a run of repeated records unreported.
+const DEDUP_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The longest `--sample` and `--max-rate` keep the records they skipped unreported.
+const SKIP_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The delay before the first attempt to reconnect with `--retry`, doubled after each failed one.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A failure to request the logs.
+enum ConnectError {
+    /// E.g., a dropped connection or a server error, which is worth retrying.
+    Transient(anyhow::Error),
+    /// E.g., an authorization error.
+    Fatal(anyhow::Error),
 }
 
-impl clap::ValueEnum for Format {
-    fn value_variants<'a>() -> &'a [Self] {
-        &[Self::Text, Self::Json]
-    }
-    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+impl ConnectError {
+    fn into_inner(self) -> anyhow::Error {
         match self {
-            Self::Text => Some(clap::builder::PossibleValue::new("text").aliases(["default", "txt"])),
-            Self::Json => Some(clap::builder::PossibleValue::new("json")),
+            Self::Transient(err) | Self::Fatal(err) => err,
         }
     }
 }
 
-pub async fn exec(mut config: Config, args: &ArgMatches) -> Result<(), anyhow::Error> {
-    let server = args.get_one::<String>("server").map(|s| s.as_ref());
-    let force = args.get_flag("force");
-    let mut num_lines = args.get_one::<u32>("num_lines").copied();
-    let database = args.get_one::<String>("database").unwrap();
-    let follow = args.get_flag("follow");
-    let format = *args.get_one::<Format>("format").unwrap();
+/// The lines of the logs of a database, reconnecting when the stream drops if `retry` is set.
+struct LogStream {
+    /// The request for the logs, without the query, or `None` when reading them from a file.
+    request: Option<reqwest::RequestBuilder>,
+    follow: bool,
+    /// The `min_level` of [`LogsParams`].
+    min_level: Option<String>,
+    rdr: Pin<Box<dyn AsyncBufRead>>,
+    /// The longest delay between two attempts to reconnect, or `None` not to reconnect.
+    retry: Option<Duration>,
+    /// The `num_lines` of [`LogsParams`] first requested.
+    num_lines: Option<u32>,
+    /// The timestamp of the last line read.
+    last_ts: Option<chrono::DateTime<chrono::Utc>>,
+    /// The number of lines read in a row with the timestamp `last_ts`.
+    last_ts_lines: usize,
+    /// After reconnecting, the lines before this timestamp, and this many lines with it, were already read
+    /// and are skipped, so that those read since with the same timestamp aren't.
+    resume_after: Option<(chrono::DateTime<chrono::Utc>, usize)>,
+    /// The level of `--fail-on`, if given.
+    fail_on: Option<LogLevel>,
+    /// The number of records read of at least the level `fail_on`.
+    failures: u64,
+    /// The number of lines left to read with `--head`, if given.
+    head: Option<u32>,
+    /// Where the logs are requested from, or `None` when reading them from a file.
+    origin: Option<LogOrigin>,
+    /// The state of `--page-size`, if given.
+    paging: Option<Paging>,
+    /// The number of lines read so far, if they are checked to be records with `--validate`.
+    validated: Option<u64>,
+    /// The streams of several databases whose lines are read in turn instead, if given.
+    merge: Option<Merge>,
+    /// Resolves once Ctrl-C is pressed, ending the logs, if it's handled, see [`interrupted`].
+    interrupt: Option<Pin<Box<dyn std::future::Future<Output = ()>>>>,
+    /// Whether the logs ended as Ctrl-C was pressed.
+    interrupted: bool,
+}
 
-    let auth_header = get_auth_header(&mut config, false, server, !force).await?;
+/// The database and server of a [`LogStream`], added to each record by `--json-enrich`.
+struct LogOrigin {
+    /// The identity of the database, in hex.
+    db: String,
+    /// The URL of the server.
+    server: String,
+}
 
-    let database_identity = database_identity(&config, database, server).await?;
+impl LogStream {
+    /// Requests the logs, failing on any error, even if `retry` is set.
+    async fn connect(
+        request: reqwest::RequestBuilder,
+        num_lines: Option<u32>,
+        follow: bool,
+        min_level: Option<LogLevel>,
+        retry: Option<Duration>,
+        fail_on: Option<LogLevel>,
+        head: Option<u32>,
+    ) -> anyhow::Result<Self> {
+        // `--fail-on` counts records of any level, so those of its level must be sent too.
+        let min_level = match (min_level, fail_on) {
+            (Some(min), Some(fail_on)) if fail_on.severity() < min.severity() => Some(fail_on),
+            (min_level, _) => min_level,
+        };
+        let min_level = min_level.map(level_param);
+        let params = LogsParams {
+            num_lines,
+            follow,
+            min_level: min_level.clone(),
+            cursor: None,
+        };
+        let rdr = Self::send(&request, params).await.map_err(ConnectError::into_inner)?;
+        Ok(Self {
+            request: Some(request),
+            follow,
+            min_level,
+            rdr,
+            retry,
+            num_lines,
+            last_ts: None,
+            last_ts_lines: 0,
+            resume_after: None,
+            fail_on,
+            failures: 0,
+            head,
+            origin: None,
+            paging: None,
+            validated: None,
+            merge: None,
+            interrupt: None,
+            interrupted: false,
+        })
+    }
 
-    if follow && num_lines.is_none() {
-        // We typically don't want logs from the very beginning if we're also following.
-        num_lines = Some(10);
+    /// Requests the logs a page at a time, starting at the page named by the `next` token of `paging`,
+    /// or at the most recent lines.
+    async fn paged(
+        request: reqwest::RequestBuilder,
+        min_level: Option<LogLevel>,
+        mut paging: Paging,
+    ) -> anyhow::Result<Self> {
+        let min_level = min_level.map(level_param);
+        let token = paging.next.take();
+        paging.fetch(&request, min_level.clone(), token).await?;
+        Ok(Self {
+            request: Some(request),
+            follow: false,
+            min_level,
+            rdr: Box::pin(futures::io::empty()),
+            retry: None,
+            num_lines: None,
+            last_ts: None,
+            last_ts_lines: 0,
+            resume_after: None,
+            fail_on: None,
+            failures: 0,
+            head: None,
+            origin: None,
+            paging: Some(paging),
+            validated: None,
+            merge: None,
+            interrupt: None,
+            interrupted: false,
+        })
     }
-    let query_params = LogsParams { num_lines, follow };
 
-    let host_url = config.get_host_url(server)?;
+    /// Sends `request` with `params`, returning a reader of the lines of the response.
+    async fn send(
+        request: &reqwest::RequestBuilder,
+        params: LogsParams,
+    ) -> Result<Pin<Box<dyn AsyncBufRead>>, ConnectError> {
+        let res = Self::get(request, params).await?;
+        Ok(body_reader(res))
+    }
 
-    let builder = reqwest::Client::new().get(format!("{host_url}/v1/database/{database_identity}/logs"));
-    let builder = add_auth_header_opt(builder, &auth_header);
-    let mut res = builder.query(&query_params).send().await?;
-    let status = res.status();
+    /// Sends `request` with `params`, returning its response unless the status is an error.
+    async fn get(request: &reqwest::RequestBuilder, params: LogsParams) -> Result<reqwest::Response, ConnectError> {
+        // A `GET` request has no body, so it can always be cloned.
+        let request = (request.try_clone().unwrap())
+            .query(&params)
+            .header(reqwest::header::ACCEPT_ENCODING, "gzip");
+        let res = request.send().await.map_err(|e| ConnectError::Transient(e.into()))?;
+        let status = res.status();
+        if status.is_client_error() || status.is_server_error() {
+            let err = anyhow::anyhow!(res.text().await.unwrap_or_else(|_| status.to_string()));
+            return Err(if status.is_server_error() {
+                ConnectError::Transient(err)
+            } else {
+                ConnectError::Fatal(err)
+            });
+        }
+        Ok(res)
+    }
 
-    if status.is_client_error() || status.is_server_error() {
-        let err = res.text().await?;
-        anyhow::bail!(err)
+    /// Reads the logs from `rdr`, e.g., a file written with `--format json`, instead of requesting them.
+    fn from_reader(rdr: Pin<Box<dyn AsyncBufRead>>, fail_on: Option<LogLevel>, head: Option<u32>) -> Self {
+        Self {
+            request: None,
+            follow: false,
+            min_level: None,
+            rdr,
+            retry: None,
+            num_lines: None,
+            last_ts: None,
+            last_ts_lines: 0,
+            resume_after: None,
+            fail_on,
+            failures: 0,
+            head,
+            origin: None,
+            paging: None,
+            validated: None,
+            merge: None,
+            interrupt: None,
+            interrupted: false,
+        }
     }
 
-    if format == Format::Json {
-        let mut stdout = tokio::io::stdout();
-        while let Some(chunk) = res.chunk().await? {
-            stdout.write_all(&chunk).await?;
+    /// Reads the lines of `sources`, the logs of several databases, as one stream, see [`Merge`].
+    fn merged(sources: Vec<LogStream>, follow: bool, fail_on: Option<LogLevel>, head: Option<u32>) -> Self {
+        let sources = sources
+            .into_iter()
+            .map(|logs| MergeSource {
+                logs,
+                head: None,
+                done: false,
+            })
+            .collect();
+        Self {
+            merge: Some(Merge {
+                sources,
+                follow,
+                last: 0,
+            }),
+            ..Self::from_reader(Box::pin(futures::io::empty()), fail_on, head)
         }
-        return Ok(());
     }
 
-    let term_color = if std::io::stdout().is_terminal() {
-        termcolor::ColorChoice::Auto
-    } else {
-        termcolor::ColorChoice::Never
-    };
-    let out = termcolor::StandardStream::stdout(term_color);
-    let mut out = out.lock();
+    /// Returns the index of the database of the last line read, if the logs of several are merged.
+    fn source(&self) -> Option<usize> {
+        self.merge.as_ref().map(|merge| merge.last)
+    }
 
-    let mut rdr = res.bytes_stream().map_err(io::Error::other).into_async_read();
-    let mut line = String::new();
-    while rdr.read_line(&mut line).await? != 0 {
-        let record = serde_json::from_str::<Record<'_>>(&line)?;
+    /// Returns where the last line read was requested from, or `None` when reading the logs from a file.
+    fn origin(&self) -> Option<&LogOrigin> {
+        match &self.merge {
+            Some(merge) => merge.sources[merge.last].logs.origin(),
+            None => self.origin.as_ref(),
+        }
+    }
 
-        if let Some(ts) = record.ts {
-            out.set_color(ColorSpec::new().set_dimmed(true))?;
-            write!(out, "{ts:?} ")?;
+    /// Reads the next line into `line`, returning `false` at the end of the logs,
+    /// once `--head` lines were read, or once Ctrl-C was pressed, if it's handled.
+    async fn read_line(&mut self, line: &mut String) -> anyhow::Result<bool> {
+        if self.head == Some(0) || self.interrupted {
+            return Ok(false);
         }
-        let mut color = ColorSpec::new();
-        let level = match record.level {
-            LogLevel::Error => {
-                color.set_fg(Some(Color::Red));
-                "ERROR"
+        let read = match self.interrupt.take() {
+            None => self.read_next_line(line).await?,
+            // Only reading waits for Ctrl-C, so that a line is never interrupted while being printed.
+            Some(mut interrupt) => {
+                let read = tokio::select! {
+                    read = self.read_next_line(line) => Some(read),
+                    () = &mut interrupt => None,
+                };
+                match read {
+                    Some(read) => {
+                        self.interrupt = Some(interrupt);
+                        read?
+                    }
+                    None => {
+                        self.interrupted = true;
+                        false
+                    }
+                }
             }
-            LogLevel::Warn => {
-                color.set_fg(Some(Color::Yellow));
-                "WARN"
+        };
+        if let Some(head) = self.head.as_mut().filter(|_| read) {
+            *head -= 1;
+        }
+        if let Some(validated) = self.validated.as_mut().filter(|_| read) {
+            *validated += 1;
+            if let Err(e) = serde_json::from_str::<Record<'_>>(line) {
+                write_invalid_line(&mut io::stderr().lock(), *validated, line, &e)?;
             }
-            LogLevel::Info => {
-                color.set_fg(Some(Color::Blue));
-                "INFO"
+        }
+        Ok(read)
+    }
+
+    /// Reads the next record of at least `min_level` using `line` as the buffer,
+    /// returning `None` at the end of the logs.
+    ///
+    /// Lines that aren't records, e.g. from a newer server, are skipped.
+    async fn read_record(
+        &mut self,
+        line: &mut String,
+        min_level: Option<LogLevel>,
+    ) -> anyhow::Result<Option<OwnedRecord>> {
+        loop {
+            line.clear();
+            if !self.read_line(line).await? {
+                return Ok(None);
             }
-            LogLevel::Debug => {
-                color.set_dimmed(true).set_bold(true);
-                "DEBUG"
+            // Lines that aren't records are skipped, rather than ending the stream.
+            let Ok(record) = serde_json::from_str::<Record<'_>>(line) else {
+                continue;
+            };
+            // Servers that don't know `min_level` send the records of all levels.
+            if min_level.is_none_or(|min| record.level.severity() >= min.severity()) {
+                return Ok(Some(record.into_owned()));
             }
-            LogLevel::Trace => {
-                color.set_dimmed(true);
-                "TRACE"
+        }
+    }
+
+    /// Like [`LogStream::read_line`], but regardless of `--head`.
+    async fn read_next_line(&mut self, line: &mut String) -> anyhow::Result<bool> {
+        if let Some(paging) = &mut self.paging {
+            let request = self.request.as_ref().expect("only requested logs are paged");
+            return paging.read_line(request, self.min_level.clone(), line).await;
+        }
+        if let Some(merge) = &mut self.merge {
+            return merge.read_line(line).await;
+        }
+        loop {
+            match read_line_lossy(&mut self.rdr, line).await {
+                Ok(0) if self.retry.is_some() => {
+                    line.clear();
+                    self.reconnect().await?;
+                }
+                // Other errors, e.g., a corrupt gzip body, would only recur after reconnecting.
+                Err(e) if self.retry.is_some() && is_connection_error(&e) => {
+                    line.clear();
+                    self.reconnect().await?;
+                }
+                Ok(0) => return Ok(false),
+                Err(e) => return Err(e.into()),
+                Ok(_) if self.retry.is_none() && self.fail_on.is_none() => return Ok(true),
+                Ok(_) => {
+                    let record = serde_json::from_str::<Record<'_>>(line).ok();
+                    if self.skip_resumed(record.as_ref().and_then(|record| record.ts)) {
+                        line.clear();
+                        continue;
+                    }
+                    if let (Some(record), Some(min)) = (record, self.fail_on) {
+                        if record.level.severity() >= min.severity() {
+                            self.failures += 1;
+                        }
+                    }
+                    return Ok(true);
+                }
             }
-            LogLevel::Panic => {
-                color.set_fg(Some(Color::Red)).set_bold(true).set_intense(true);
-                "PANIC"
+        }
+    }
+
+    /// Returns whether a line with the timestamp `ts` was already read before reconnecting, and is to be skipped,
+    /// or else notes `ts` as the timestamp of the last line read.
+    ///
+    /// Several lines may have the same timestamp, so those with the last one are skipped by their number,
+    /// rather than all of them, which would lose those that arrived since.
+    fn skip_resumed(&mut self, ts: Option<chrono::DateTime<chrono::Utc>>) -> bool {
+        match (ts, &mut self.resume_after) {
+            (Some(ts), Some((after, _))) if ts < *after => return true,
+            (Some(ts), Some((after, lines))) if ts == *after && *lines > 0 => {
+                *lines -= 1;
+                return true;
             }
-        };
-        out.set_color(&color)?;
-        write!(out, "{level:>5}: ")?;
-        out.reset()?;
-        let dimmed = ColorSpec::new().set_dimmed(true).clone();
-        if let Some(filename) = record.filename {
-            out.set_color(&dimmed)?;
-            write!(out, "{filename}")?;
-            if let Some(line) = record.line_number {
-                write!(out, ":{line}")?;
+            _ => {}
+        }
+        self.resume_after = None;
+        match ts {
+            Some(ts) if self.last_ts == Some(ts) => self.last_ts_lines += 1,
+            Some(ts) => {
+                self.last_ts = Some(ts);
+                self.last_ts_lines = 1;
             }
-            out.reset()?;
+            None => {}
         }
-        writeln!(out, ": {}", record.message)?;
-        if let Some(trace) = &record.trace {
-            for frame in trace {
-                write!(out, "    in ")?;
-                if let Some(module) = &frame.module_name {
-                    out.set_color(&dimmed)?;
-                    write!(out, "{module}")?;
-                    out.reset()?;
-                    write!(out, " :: ")?;
-                }
-                if let Some(function) = &frame.func_name {
-                    out.set_color(&dimmed)?;
-                    writeln!(out, "{function}")?;
-                    out.reset()?;
+        false
+    }
+
+    /// Returns whether the next line has yet to arrive, i.e., whether reading it would wait.
+    fn is_idle(&mut self) -> bool {
+        if let Some(merge) = &mut self.merge {
+            return merge.is_idle();
+        }
+        match &self.paging {
+            // The next page may only be fetched after asking the user.
+            Some(paging) => paging.lines.is_empty(),
+            None => self.rdr.fill_buf().now_or_never().is_none(),
+        }
+    }
+
+    /// Fails if any record of at least the level `--fail-on` was read,
+    /// or exits as if terminated by `SIGINT` if Ctrl-C was pressed.
+    fn finish(self) -> anyhow::Result<ExitCode> {
+        if self.interrupted {
+            // The conventional exit code of a process terminated by `SIGINT`.
+            return Ok(ExitCode::from(130));
+        }
+        // Each database counts its own, as it reads its records.
+        let failures = match &self.merge {
+            Some(merge) => merge.sources.iter().map(|source| source.logs.failures).sum(),
+            None => self.failures,
+        };
+        match self.fail_on {
+            Some(min) if failures > 0 => anyhow::bail!(
+                "{} records of level {} or above were logged",
+                failures,
+                level_style(&min).0
+            ),
+            _ => Ok(ExitCode::SUCCESS),
+        }
+    }
+
+    /// Returns the number of lines to request again when reconnecting.
+    ///
+    /// Until a line with a timestamp was read, there's nowhere to resume from,
+    /// so the lines first requested are, e.g., none with `--num-lines 0`, rather than older ones.
+    fn resume_lines(&self) -> Option<u32> {
+        match self.last_ts {
+            Some(_) => Some(RESUME_LINES),
+            None => self.num_lines,
+        }
+    }
+
+    /// Reconnects, waiting longer after each failed attempt, until it succeeds or fails fatally.
+    async fn reconnect(&mut self) -> anyhow::Result<()> {
+        let max_delay = self.retry.unwrap_or_default();
+        let choice = if io::stderr().is_terminal() {
+            termcolor::ColorChoice::Auto
+        } else {
+            termcolor::ColorChoice::Never
+        };
+        let mut stderr = termcolor::StandardStream::stderr(choice);
+        let mut delay = INITIAL_RETRY_DELAY.min(max_delay);
+        loop {
+            write_dimmed(&mut stderr, |out| {
+                writeln!(out, "Connection lost, reconnecting in {}s...", delay.as_secs_f32())
+            })?;
+            tokio::time::sleep(delay).await;
+            let params = LogsParams {
+                num_lines: self.resume_lines(),
+                follow: self.follow,
+                min_level: self.min_level.clone(),
+                cursor: None,
+            };
+            let request = self.request.as_ref().expect("only requested logs are retried");
+            match Self::send(request, params).await {
+                Ok(rdr) => {
+                    self.rdr = rdr;
+                    self.resume_after = self.last_ts.map(|ts| (ts, self.last_ts_lines));
+                    return Ok(());
                 }
+                Err(ConnectError::Fatal(err)) => return Err(err),
+                Err(ConnectError::Transient(_)) => delay = (delay * 2).min(max_delay),
             }
         }
-
-        line.clear();
     }
+}
 
-    Ok(())
+/// The logs of several databases, read as one stream.
+///
+/// Without `follow`, the lines are read in order of their timestamps,
+/// as the lines of each database are already, by reading one line ahead of each.
+/// With it, they are read in order of arrival, as waiting for every database would hold back the others.
+struct Merge {
+    sources: Vec<MergeSource>,
+    follow: bool,
+    /// The index of the database of the last line read.
+    last: usize,
+}
+
+/// A database of a [`Merge`].
+struct MergeSource {
+    logs: LogStream,
+    /// The line read ahead, to compare its timestamp with the other databases'.
+    head: Option<String>,
+    /// Whether the end of its logs was reached.
+    done: bool,
+}
+
+impl Merge {
+    /// Reads the next line of any database into `line`, returning `false` once all of them ended.
+    async fn read_line(&mut self, line: &mut String) -> anyhow::Result<bool> {
+        if self.follow {
+            return self.read_arrived_line(line).await;
+        }
+        for source in self
+            .sources
+            .iter_mut()
+            .filter(|source| source.head.is_none() && !source.done)
+        {
+            let mut head = String::new();
+            if source.logs.read_line(&mut head).await? {
+                source.head = Some(head);
+            } else {
+                source.done = true;
+            }
+        }
+        // Lines that aren't records, or have no timestamp, go first, as they can't be ordered.
+        let earliest = (self.sources.iter().enumerate())
+            .filter_map(|(idx, source)| Some((idx, source.head.as_deref()?)))
+            .min_by_key(|(_, head)| parse_record(head).and_then(|record| record.ts))
+            .map(|(idx, _)| idx);
+        let Some(idx) = earliest else {
+            return Ok(false);
+        };
+        line.push_str(&self.sources[idx].head.take().unwrap());
+        self.last = idx;
+        Ok(true)
+    }
+
+    /// Reads the next line of whichever database sends one first into `line`.
+    async fn read_arrived_line(&mut self, line: &mut String) -> anyhow::Result<bool> {
+        loop {
+            let last = self.last;
+            let mut waiting = (self.sources.iter_mut().enumerate())
+                .filter(|(_, source)| !source.done)
+                .collect::<Vec<_>>();
+            if waiting.is_empty() {
+                return Ok(false);
+            }
+            // Start after the database read last, so that a busy one can't hold back the others.
+            let first = waiting.iter().position(|(idx, _)| *idx > last).unwrap_or(0);
+            waiting.rotate_left(first);
+            // Only wait for the lines to arrive, without reading them, so that none are lost to the others.
+            let fills = (waiting.into_iter()).map(|(idx, source)| source.logs.rdr.fill_buf().map(move |_| idx));
+            let (idx, _, _) = futures::future::select_all(fills).await;
+            let source = &mut self.sources[idx];
+            if source.logs.read_line(line).await? {
+                self.last = idx;
+                return Ok(true);
+            }
+            source.done = true;
+        }
+    }
+
+    /// Returns whether reading the next line would wait.
+    fn is_idle(&mut self) -> bool {
+        if self.follow {
+            (self.sources.iter_mut()).all(|source| source.done || source.logs.is_idle())
+        } else {
+            // Every database yet to end needs a line ahead before the earliest can be read.
+            (self.sources.iter_mut()).any(|source| !source.done && source.head.is_none() && source.logs.is_idle())
+        }
+    }
+}
+
+/// Returns the name of `level` as sent in the `min_level` of [`LogsParams`].
+fn level_param(level: LogLevel) -> String {
+    clap::ValueEnum::to_possible_value(&level)
+        .unwrap()
+        .get_name()
+        .to_owned()
+}
+
+/// The page of `--page-size` being read, and where the next one starts.
+struct Paging {
+    page_size: u32,
+    /// The lines of the current page that were not read yet.
+    lines: VecDeque<String>,
+    /// Where the next, older page starts, or `None` at the start of the log.
+    next: Option<PageToken>,
+    /// Whether to ask on the terminal before fetching the next page, rather than stopping.
+    interactive: bool,
+}
+
+impl Paging {
+    /// Prepares to read pages of `page_size` lines, starting at `start` or at the most recent lines.
+    fn new(page_size: u32, start: Option<PageToken>, interactive: bool) -> Self {
+        Self {
+            page_size,
+            lines: VecDeque::new(),
+            next: start,
+            interactive,
+        }
+    }
+
+    /// Reads the next line of the current page into `line`, fetching the next page if wanted once it was read,
+    /// or else printing its token to resume from with `--page-token`.
+    async fn read_line(
+        &mut self,
+        request: &reqwest::RequestBuilder,
+        min_level: Option<String>,
+        line: &mut String,
+    ) -> anyhow::Result<bool> {
+        loop {
+            if let Some(next) = self.lines.pop_front() {
+                line.push_str(&next);
+                return Ok(true);
+            }
+            let Some(token) = self.next.take() else {
+                return Ok(false);
+            };
+            if !self.interactive || !prompt_next_page()? {
+                eprintln!("More logs with `--page-token {token}`");
+                return Ok(false);
+            }
+            self.fetch(request, min_level.clone(), Some(token)).await?;
+        }
+    }
+
+    /// Replaces the current page with the one at `token`, or with the most recent lines if `None`.
+    async fn fetch(
+        &mut self,
+        request: &reqwest::RequestBuilder,
+        min_level: Option<String>,
+        token: Option<PageToken>,
+    ) -> anyhow::Result<()> {
+        // Without a cursor, the lines that were already paged through are requested again.
+        let (offset, cursor) = match token {
+            Some(PageToken::Cursor(cursor)) => (0, Some(cursor)),
+            Some(PageToken::Offset(offset)) => (offset, None),
+            None => (0, None),
+        };
+        let by_cursor = cursor.is_some();
+        let num_lines = offset.saturating_add(self.page_size);
+        let params = LogsParams {
+            num_lines: Some(num_lines),
+            follow: false,
+            min_level,
+            cursor,
+        };
+        let res = LogStream::get(request, params)
+            .await
+            .map_err(ConnectError::into_inner)?;
+        let next_cursor = (res.headers().get(CURSOR_HEADER))
+            .and_then(|cursor| cursor.to_str().ok())
+            .map(str::to_owned);
+        let mut rdr = body_reader(res);
+
+        // Keep the oldest lines, only counting the more recent ones, so that at most a page is in memory.
+        self.lines.clear();
+        let mut rest = 0u32;
+        let mut line = String::new();
+        while read_line_lossy(&mut rdr, &mut line).await? != 0 {
+            if self.lines.len() < self.page_size as usize {
+                self.lines.push_back(std::mem::take(&mut line));
+            } else {
+                rest += 1;
+                line.clear();
+            }
+        }
+        let total = self.lines.len() as u32 + rest;
+        // Near the start of the log, fewer lines than a page precede those already paged through.
+        self.lines.truncate(total.saturating_sub(offset) as usize);
+
+        self.next = match next_cursor {
+            Some(cursor) => Some(PageToken::Cursor(cursor)),
+            // A server that knows `cursor` leaves it out at the start of the log.
+            None if by_cursor => None,
+            None => (total >= num_lines).then_some(PageToken::Offset(num_lines)),
+        };
+        Ok(())
+    }
+}
+
+/// Reads the bytes of `rdr` up to and including the next newline, appending them to `line`,
+/// returning the number of bytes read, which is 0 at the end of `rdr`.
+///
+/// Like [`AsyncBufReadExt::read_line`], only complete lines are read,
+/// regardless of how characters are split across the chunks of the response,
+/// but invalid UTF-8 is replaced with `U+FFFD` rather than failing, so that one such line doesn't end the logs.
+async fn read_line_lossy(rdr: &mut (impl AsyncBufRead + Unpin), line: &mut String) -> io::Result<usize> {
+    let mut bytes = std::mem::take(line).into_bytes();
+    let res = rdr.read_until(b'\n', &mut bytes).await;
+    // Keep what was read even on an error, as `read_line` would.
+    *line = String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+    res
+}
+
+/// Returns a reader of the body of `res`, decompressing it if the server gzipped it.
+///
+/// A server that doesn't support `Accept-Encoding: gzip` sends the body as is, which is read unchanged.
+fn body_reader(res: reqwest::Response) -> Pin<Box<dyn AsyncBufRead>> {
+    let gzip = (res.headers().get(reqwest::header::CONTENT_ENCODING))
+        .is_some_and(|encoding| encoding.as_bytes().eq_ignore_ascii_case(b"gzip"));
+    // The body only fails to arrive if the connection does, e.g., when it drops or times out.
+    let body = (res.bytes_stream())
+        .map_err(|e| io::Error::new(io::ErrorKind::ConnectionAborted, e))
+        .into_async_read();
+    decode_body(body, gzip)
+}
+
+/// Returns whether `err`, from reading the body of a response, means that the connection was lost,
+/// so that it's worth reconnecting with `--retry`, rather than, e.g., that the body is corrupt.
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// Wraps `rdr` in a gzip decoder if `gzip`, so that the lines of the body are read rather than its compressed bytes.
+fn decode_body(rdr: impl AsyncBufRead + 'static, gzip: bool) -> Pin<Box<dyn AsyncBufRead>> {
+    if gzip {
+        // A server may compress each flush of a followed stream as its own member.
+        let mut decoder = GzipDecoder::new(rdr);
+        decoder.multiple_members(true);
+        Box::pin(futures::io::BufReader::new(decoder))
+    } else {
+        Box::pin(rdr)
+    }
+}
+
+/// Reports, for `--validate`, that the `number`th line read, `line`, isn't a record, as of `err`.
+fn write_invalid_line(out: &mut impl Write, number: u64, line: &str, err: &serde_json::Error) -> io::Result<()> {
+    writeln!(out, "line {number} is not a valid record: {err}")?;
+    writeln!(out, "    {}", line.trim_end_matches(['\r', '\n']))
+}
+
+/// Replaces the sizes in bytes in `message`, e.g., `1572864 bytes`, with sizes in binary units, e.g., `1.5 MiB`,
+/// for `--humanize`. Sizes under a KiB are kept as they are.
+fn humanize_sizes(message: Cow<'_, str>) -> Cow<'_, str> {
+    static BYTES: std::sync::LazyLock<Regex> = std::sync::LazyLock::new(|| Regex::new(r"\b(\d+) bytes\b").unwrap());
+    if !BYTES.is_match(&message) {
+        return message;
+    }
+    let humanized = BYTES.replace_all(&message, |caps: &regex::Captures<'_>| match caps[1].parse::<u64>() {
+        Ok(bytes) if bytes >= 1024 => human_size(bytes),
+        _ => caps[0].to_owned(),
+    });
+    humanized.into_owned().into()
+}
+
+/// Returns `bytes` in the largest binary unit it's at least one of, with one decimal, e.g., `1.5 MiB`.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Parses `line` as a record, or returns `None` if it isn't one, e.g., as it's from a newer server.
+fn parse_record(line: &str) -> Option<Record<'_>> {
+    serde_json::from_str(line).ok()
+}
+
+/// Writes `line` as it was received, ending it with a newline if it isn't already, e.g., at the end of the logs.
+fn write_raw_line(out: &mut (impl Write + ?Sized), line: &str) -> io::Result<()> {
+    out.write_all(line.as_bytes())?;
+    if !line.ends_with('\n') {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Asks on the terminal whether to fetch the next page of `--page-size`.
+fn prompt_next_page() -> io::Result<bool> {
+    let mut stderr = io::stderr().lock();
+    write!(stderr, "-- Press Enter for older logs, or q to quit --")?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    // Stop at the end of stdin too, as no answer will come.
+    let read = io::stdin().read_line(&mut answer)?;
+    Ok(read > 0 && !answer.trim().eq_ignore_ascii_case("q"))
+}
+
+/// Where a page of `--page-size` starts, as given to `--page-token`.
+#[derive(Clone, PartialEq, Debug)]
+enum PageToken {
+    /// A position in the log returned by a server that supports the `cursor` of [`LogsParams`].
+    Cursor(String),
+    /// The number of the most recent lines that were already paged through, with servers that don't.
+    Offset(u32),
+}
+
+impl FromStr for PageToken {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("cursor", cursor)) if !cursor.is_empty() => Ok(Self::Cursor(cursor.to_owned())),
+            Some(("offset", offset)) => Ok(Self::Offset(
+                offset
+                    .parse()
+                    .with_context(|| format!("invalid offset in page token `{s}`"))?,
+            )),
+            _ => anyhow::bail!("invalid page token `{s}`, expected one printed by `--page-size`"),
+        }
+    }
+}
+
+impl std::fmt::Display for PageToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cursor(cursor) => write!(f, "cursor:{cursor}"),
+            Self::Offset(offset) => write!(f, "offset:{offset}"),
+        }
+    }
+}
+
+/// A user-provided template for log lines, parsed once.
+#[derive(Clone)]
+struct Template {
+    segments: Vec<TemplateSegment>,
+}
+
+#[derive(Clone)]
+enum TemplateSegment {
+    Literal(String),
+    Field(TemplateField),
+}
+
+#[derive(Clone, Copy)]
+enum TemplateField {
+    Ts,
+    Level,
+    Target,
+    File,
+    Line,
+    Message,
+}
+
+impl FromStr for TemplateField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "ts" => Self::Ts,
+            "level" => Self::Level,
+            "target" => Self::Target,
+            // The names of the fields of the records are accepted too.
+            "file" | "filename" => Self::File,
+            "line" | "line_number" => Self::Line,
+            "message" | "msg" => Self::Message,
+            _ => anyhow::bail!(
+                "unknown placeholder `{{{s}}}` in template, expected one of `{{ts}}`, `{{level}}`, `{{target}}`, `{{file}}`, `{{line}}`, or `{{message}}`"
+            ),
+        })
+    }
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(mut s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        while let Some(idx) = s.find(['{', '}']) {
+            literal.push_str(&s[..idx]);
+            let brace = s.as_bytes()[idx];
+            s = &s[idx + 1..];
+            // Doubled braces are literal ones, e.g., `{{` for `{`.
+            if s.as_bytes().first() == Some(&brace) {
+                literal.push(char::from(brace));
+                s = &s[1..];
+                continue;
+            }
+            // A lone `}` is literal too, as it was before `}}` was accepted.
+            if brace == b'}' {
+                literal.push('}');
+                continue;
+            }
+            let Some(len) = s.find('}') else {
+                anyhow::bail!("unclosed `{{` in template, use `{{{{` for a literal `{{`");
+            };
+            if !literal.is_empty() {
+                segments.push(TemplateSegment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(TemplateSegment::Field(s[..len].parse()?));
+            s = &s[len + 1..];
+        }
+        literal.push_str(s);
+        if !literal.is_empty() {
+            segments.push(TemplateSegment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+}
+
+impl Template {
+    /// Writes `record` to `out` according to this template, coloring only the level.
+    fn write(&self, out: &mut impl WriteColor, style: &TextStyle<'_>, record: &Record<'_>) -> io::Result<()> {
+        for segment in &self.segments {
+            match segment {
+                TemplateSegment::Literal(lit) => out.write_all(lit.as_bytes())?,
+                TemplateSegment::Field(TemplateField::Ts) => {
+                    if let Some(ts) = record.ts {
+                        write!(out, "{}", style.time.format(ts))?;
+                    }
+                }
+                TemplateSegment::Field(TemplateField::Level) => {
+                    let level = style.level.label(record.level);
+                    out.set_color(style.colors.get(record.level))?;
+                    write!(out, "{level}")?;
+                    out.reset()?;
+                }
+                TemplateSegment::Field(TemplateField::Target) => {
+                    if let Some(target) = &record.target {
+                        write!(out, "{target}")?;
+                    }
+                }
+                TemplateSegment::Field(TemplateField::File) => {
+                    if let Some(filename) = &record.filename {
+                        write!(out, "{filename}")?;
+                    }
+                }
+                TemplateSegment::Field(TemplateField::Line) => {
+                    if let Some(line) = record.line_number {
+                        write!(out, "{line}")?;
+                    }
+                }
+                TemplateSegment::Field(TemplateField::Message) => write!(out, "{}", record.message)?,
+            }
+        }
+        writeln!(out)
+    }
+}
+
+/// A point in time given to `--since` or `--until`.
+#[derive(Clone, Copy)]
+struct TimeArg(DateTime<Utc>);
+
+impl FromStr for TimeArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(ts) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self(ts.with_timezone(&Utc)));
+        }
+        let invalid = || anyhow::anyhow!("invalid time `{s}`, expected RFC 3339 or a relative time like `10m`");
+        let (n, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+        let n = n.parse().map_err(|_| invalid())?;
+        let ago = match unit {
+            "s" => chrono::TimeDelta::try_seconds(n),
+            "m" => chrono::TimeDelta::try_minutes(n),
+            "h" => chrono::TimeDelta::try_hours(n),
+            "d" => chrono::TimeDelta::try_days(n),
+            "w" => chrono::TimeDelta::try_weeks(n),
+            _ => None,
+        };
+        let ts = ago
+            .and_then(|ago| Utc::now().checked_sub_signed(ago))
+            .ok_or_else(invalid)?;
+        Ok(Self(ts))
+    }
+}
+
+/// The time zone to print timestamps in, see `--timezone`.
+#[derive(Clone, Copy)]
+enum TimeZone {
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl FromStr for TimeZone {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "utc" | "UTC" => Self::Utc,
+            "local" => Self::Local,
+            _ => Self::Fixed(s.parse().map_err(|_| {
+                anyhow::anyhow!("unknown time zone `{s}`, expected `utc`, `local` or an offset like `+02:00`")
+            })?),
+        })
+    }
+}
+
+/// How to print timestamps, see `--time-format`.
+#[derive(Clone)]
+enum TimeFormat {
+    Rfc3339,
+    /// Milliseconds since the Unix epoch.
+    Millis,
+    /// A validated strftime format.
+    Strftime(String),
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "rfc3339" => Self::Rfc3339,
+            "millis" => Self::Millis,
+            _ if StrftimeItems::new(s).any(|item| item == Item::Error) => {
+                anyhow::bail!("invalid time format `{s}`, expected `rfc3339`, `millis` or a strftime format")
+            }
+            _ => Self::Strftime(s.to_owned()),
+        })
+    }
+}
+
+/// How to print the timestamps of records, per `--timezone` and `--time-format`.
+#[derive(Clone, Default)]
+struct TimeStyle {
+    zone: Option<TimeZone>,
+    format: Option<TimeFormat>,
+}
+
+impl TimeStyle {
+    /// Returns `ts` formatted in this style.
+    fn format(&self, ts: DateTime<Utc>) -> String {
+        if self.zone.is_none() && self.format.is_none() {
+            // What was printed before these options existed.
+            return format!("{ts:?}");
+        }
+        match self.zone.unwrap_or(TimeZone::Utc) {
+            TimeZone::Utc => self.format_in(ts),
+            TimeZone::Local => self.format_in(ts.with_timezone(&Local)),
+            TimeZone::Fixed(offset) => self.format_in(ts.with_timezone(&offset)),
+        }
+    }
+
+    fn format_in<Tz: chrono::TimeZone>(&self, ts: DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        match self.format.as_ref().unwrap_or(&TimeFormat::Rfc3339) {
+            TimeFormat::Rfc3339 => ts.to_rfc3339(),
+            TimeFormat::Millis => ts.timestamp_millis().to_string(),
+            TimeFormat::Strftime(format) => ts.format(format).to_string(),
+        }
+    }
+}
+
+/// Returns the label and color used to display `level`.
+fn level_style(level: &LogLevel) -> (&'static str, ColorSpec) {
+    let mut color = ColorSpec::new();
+    let label = match level {
+        LogLevel::Error => {
+            color.set_fg(Some(Color::Red));
+            "ERROR"
+        }
+        LogLevel::Warn => {
+            color.set_fg(Some(Color::Yellow));
+            "WARN"
+        }
+        LogLevel::Info => {
+            color.set_fg(Some(Color::Blue));
+            "INFO"
+        }
+        LogLevel::Debug => {
+            color.set_dimmed(true).set_bold(true);
+            "DEBUG"
+        }
+        LogLevel::Trace => {
+            color.set_dimmed(true);
+            "TRACE"
+        }
+        LogLevel::Panic => {
+            color.set_fg(Some(Color::Red)).set_bold(true).set_intense(true);
+            "PANIC"
+        }
+    };
+    (label, color)
+}
+
+/// The colors of the levels in the `text` format, see `--color-scheme`.
+#[derive(Clone)]
+struct ColorScheme {
+    /// The colors by [`LogLevel::severity`].
+    colors: [ColorSpec; 6],
+}
+
+impl Default for ColorScheme {
+    fn default() -> Self {
+        let levels = [
+            LogLevel::Trace,
+            LogLevel::Debug,
+            LogLevel::Info,
+            LogLevel::Warn,
+            LogLevel::Error,
+            LogLevel::Panic,
+        ];
+        Self {
+            colors: levels.map(|level| level_style(&level).1),
+        }
+    }
+}
+
+impl ColorScheme {
+    fn get(&self, level: LogLevel) -> &ColorSpec {
+        &self.colors[level.severity() as usize]
+    }
+}
+
+impl FromStr for ColorScheme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut scheme = Self::default();
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (level, style) = entry
+                .split_once('=')
+                .with_context(|| format!("expected `level=style` in color scheme, found `{entry}`"))?;
+            let level = <LogLevel as clap::ValueEnum>::from_str(level.trim(), true)
+                .map_err(|_| anyhow::anyhow!("unknown level `{level}` in color scheme"))?;
+            let mut color = ColorSpec::new();
+            for part in style.split('+').map(str::trim) {
+                match part {
+                    "bold" => color.set_bold(true),
+                    "dimmed" => color.set_dimmed(true),
+                    "intense" => color.set_intense(true),
+                    "italic" => color.set_italic(true),
+                    "underline" => color.set_underline(true),
+                    _ => color
+                        .set_fg(Some(part.parse().with_context(|| {
+                            format!("unknown color or attribute `{part}` in color scheme")
+                        })?)),
+                };
+            }
+            scheme.colors[level.severity() as usize] = color;
+        }
+        Ok(scheme)
+    }
+}
+
+/// How to render records in the `text` format.
+struct TextStyle<'a> {
+    template: Option<&'a Template>,
+    time: TimeStyle,
+    colors: ColorScheme,
+    /// Whether to print backtraces, unless `--no-trace`.
+    trace: bool,
+    /// Whether to demangle the functions in backtraces, per `--demangle`.
+    demangle: bool,
+    /// Whether to print each record on a single line, per `--format compact`.
+    compact: bool,
+    /// Whether to print the targets of records, per `--show-target`.
+    target: bool,
+    /// How to print the levels of records, per `--level-style`.
+    level: LevelStyle,
+    /// The names of the databases to prefix records with, if the logs of several are merged.
+    sources: Option<SourceLabels>,
+}
+
+/// The names of the databases whose logs are merged, prefixed to their records in the `text` format.
+struct SourceLabels {
+    /// The name of each database as given, in the order of [`LogStream::source`].
+    names: Vec<String>,
+    /// The width of the longest name, which the others are padded to, so that the records line up.
+    width: usize,
+    /// The index of the database of the record being printed.
+    current: Cell<usize>,
+}
+
+impl SourceLabels {
+    /// The colors of the names, given to the databases in turn.
+    const COLORS: [Color; 5] = [Color::Cyan, Color::Yellow, Color::Green, Color::Magenta, Color::Blue];
+
+    fn new(names: Vec<String>) -> Self {
+        let width = names.iter().map(|name| name.chars().count()).max().unwrap_or_default();
+        Self {
+            names,
+            width,
+            current: Cell::new(0),
+        }
+    }
+
+    /// Writes the name of the current database, e.g., `db1   | `, returning its width.
+    fn write(&self, out: &mut impl WriteColor) -> io::Result<usize> {
+        let idx = self.current.get();
+        out.set_color(ColorSpec::new().set_fg(Some(Self::COLORS[idx % Self::COLORS.len()])))?;
+        write!(out, "{:1$}", self.names[idx], self.width)?;
+        out.reset()?;
+        write!(out, " | ")?;
+        Ok(self.width + 3)
+    }
+}
+
+/// How to print the levels of records in the `text` format, see `--level-style`.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Default)]
+enum LevelStyle {
+    #[default]
+    Upper,
+    Lower,
+    Letter,
+    Bracketed,
+}
+
+impl LevelStyle {
+    /// Returns the label of `level` in this style, e.g., `[ERROR]` if `Bracketed`.
+    fn label(self, level: LogLevel) -> Cow<'static, str> {
+        let (label, _) = level_style(&level);
+        match self {
+            Self::Upper => label.into(),
+            Self::Lower => label.to_lowercase().into(),
+            Self::Letter => label[..1].into(),
+            Self::Bracketed => format!("[{label}]").into(),
+        }
+    }
+
+    /// Returns the width of the widest label in this style, which the labels are padded to.
+    fn width(self) -> usize {
+        let levels = [
+            LogLevel::Trace,
+            LogLevel::Debug,
+            LogLevel::Info,
+            LogLevel::Warn,
+            LogLevel::Error,
+            LogLevel::Panic,
+        ];
+        let widest = levels.map(|level| level_style(&level).0.len()).into_iter().max();
+        match self {
+            Self::Upper | Self::Lower => widest.unwrap_or_default(),
+            Self::Letter => 1,
+            Self::Bracketed => widest.unwrap_or_default() + 2,
+        }
+    }
+}
+
+/// Returns `name` demangled if it's a mangled Rust symbol, without the hash.
+fn demangle(name: &str) -> Cow<'_, str> {
+    match rustc_demangle::try_demangle(name) {
+        Ok(demangled) => format!("{demangled:#}").into(),
+        Err(_) => name.into(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    /// Newline-delimited JSON matching BigQuery's expectations, see [`BqRecord`].
+    BqJson,
+    /// Comma-separated values with a header row, see [`write_csv_record`].
+    Csv,
+    /// One line per record, see [`write_compact_record`].
+    Compact,
+}
+
+impl clap::ValueEnum for Format {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Text, Self::Json, Self::BqJson, Self::Csv, Self::Compact]
+    }
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        match self {
+            Self::Text => Some(clap::builder::PossibleValue::new("text").aliases(["default", "txt"])),
+            Self::Json => Some(clap::builder::PossibleValue::new("json")),
+            Self::BqJson => Some(clap::builder::PossibleValue::new("bq-json").alias("bigquery")),
+            Self::Csv => Some(clap::builder::PossibleValue::new("csv")),
+            Self::Compact => Some(clap::builder::PossibleValue::new("compact")),
+        }
+    }
+}
+
+/// A normalized [`Record`] for loading into BigQuery, as described by [`bq_schema`].
+#[derive(serde::Serialize)]
+struct BqRecord<'a> {
+    /// The timestamp in RFC 3339, which BigQuery recognizes as a `TIMESTAMP`.
+    timestamp: Option<String>,
+    level: &'static str,
+    target: Option<&'a str>,
+    filename: Option<&'a str>,
+    line_number: Option<u32>,
+    message: &'a str,
+    /// An empty list rather than `null` when absent, as BigQuery requires for repeated fields.
+    trace: Vec<BqFrame<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct BqFrame<'a> {
+    module_name: Option<&'a str>,
+    func_name: Option<&'a str>,
+}
+
+impl<'a> From<&'a Record<'_>> for BqRecord<'a> {
+    fn from(record: &'a Record<'_>) -> Self {
+        Self {
+            timestamp: record
+                .ts
+                .map(|ts| ts.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)),
+            level: level_style(&record.level).0,
+            target: record.target.as_deref(),
+            filename: record.filename.as_deref(),
+            line_number: record.line_number,
+            message: &record.message,
+            trace: record
+                .trace
+                .iter()
+                .flatten()
+                .map(|frame| BqFrame {
+                    module_name: frame.module_name.as_deref(),
+                    func_name: frame.func_name.as_deref(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A field of [`BqRecord`], to print with `--json-fields`.
+#[derive(Clone, Copy)]
+enum JsonField {
+    Timestamp,
+    Level,
+    Target,
+    Filename,
+    LineNumber,
+    Message,
+    Trace,
+}
+
+impl JsonField {
+    /// Returns the name of this field in the serialized [`BqRecord`].
+    fn key(self) -> &'static str {
+        match self {
+            Self::Timestamp => "timestamp",
+            Self::Level => "level",
+            Self::Target => "target",
+            Self::Filename => "filename",
+            Self::LineNumber => "line_number",
+            Self::Message => "message",
+            Self::Trace => "trace",
+        }
+    }
+}
+
+impl clap::ValueEnum for JsonField {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Self::Timestamp,
+            Self::Level,
+            Self::Target,
+            Self::Filename,
+            Self::LineNumber,
+            Self::Message,
+            Self::Trace,
+        ]
+    }
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        let value = clap::builder::PossibleValue::new(self.key());
+        Some(match self {
+            Self::Timestamp => value.alias("ts"),
+            Self::Filename => value.alias("file"),
+            Self::LineNumber => value.alias("line"),
+            _ => value,
+        })
+    }
+}
+
+/// Writes `record` on a line of the `json` format, normalized as a [`BqRecord`],
+/// with only `fields` if given, and otherwise like [`write_json_value`].
+fn write_json_record(
+    out: &mut impl Write,
+    record: &Record<'_>,
+    fields: Option<&[JsonField]>,
+    pretty: bool,
+    origin: Option<&LogOrigin>,
+) -> anyhow::Result<()> {
+    let mut value = serde_json::to_value(BqRecord::from(record))?;
+    if let (Some(fields), Some(object)) = (fields, value.as_object_mut()) {
+        object.retain(|key, _| fields.iter().any(|field| field.key() == key));
+    }
+    write_json_value(out, value, pretty, origin)
+}
+
+/// Writes `line` like [`write_json_value`], keeping the order of its keys, or as it is if it isn't valid JSON.
+fn write_json_line(out: &mut impl Write, line: &str, pretty: bool, origin: Option<&LogOrigin>) -> anyhow::Result<()> {
+    match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(value) => write_json_value(out, value, pretty, origin),
+        Err(_) => Ok(out.write_all(line.as_bytes())?),
+    }
+}
+
+/// Writes `value` on a line of its own, indented if `pretty`,
+/// adding the fields `db` and `server` of `origin`, if given, to it if it's an object.
+fn write_json_value(
+    out: &mut impl Write,
+    mut value: serde_json::Value,
+    pretty: bool,
+    origin: Option<&LogOrigin>,
+) -> anyhow::Result<()> {
+    if let (Some(origin), Some(object)) = (origin, value.as_object_mut()) {
+        object.insert("db".to_owned(), origin.db.clone().into());
+        object.insert("server".to_owned(), origin.server.clone().into());
+    }
+    if pretty {
+        serde_json::to_writer_pretty(&mut *out, &value)?;
+    } else {
+        serde_json::to_writer(&mut *out, &value)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// The header row of the `csv` format, naming the columns written by [`write_csv_record`].
+const CSV_HEADER: &str = "timestamp,level,filename,line,message";
+
+/// Writes `record` as a row of the `csv` format to `out`, omitting the backtrace.
+///
+/// Rows end in `\r\n`, as RFC 4180 specifies.
+fn write_csv_record(out: &mut impl Write, record: &Record<'_>) -> io::Result<()> {
+    let ts = record
+        .ts
+        .map(|ts| ts.to_rfc3339_opts(chrono::SecondsFormat::Micros, true))
+        .unwrap_or_default();
+    let level = level_style(&record.level).0;
+    let filename = record.filename.as_deref().unwrap_or_default();
+    let line = record.line_number.map(|line| line.to_string()).unwrap_or_default();
+    let message = csv_field(&record.message);
+    write!(out, "{ts},{level},{},{line},{message}\r\n", csv_field(filename))
+}
+
+/// Quotes `field` as RFC 4180 requires if it contains a comma, a quote or a line break.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+/// Returns the BigQuery table schema of [`BqRecord`].
+fn bq_schema() -> serde_json::Value {
+    serde_json::json!([
+        { "name": "timestamp", "type": "TIMESTAMP", "mode": "NULLABLE" },
+        { "name": "level", "type": "STRING", "mode": "REQUIRED" },
+        { "name": "target", "type": "STRING", "mode": "NULLABLE" },
+        { "name": "filename", "type": "STRING", "mode": "NULLABLE" },
+        { "name": "line_number", "type": "INTEGER", "mode": "NULLABLE" },
+        { "name": "message", "type": "STRING", "mode": "REQUIRED" },
+        {
+            "name": "trace",
+            "type": "RECORD",
+            "mode": "REPEATED",
+            "fields": [
+                { "name": "module_name", "type": "STRING", "mode": "NULLABLE" },
+                { "name": "func_name", "type": "STRING", "mode": "NULLABLE" }
+            ]
+        }
+    ])
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// Returns the mode asked for by `$NO_COLOR` or else `$CLICOLOR_FORCE`, if set and not empty, or else `Auto`,
+    /// looking the variables up with `lookup`, e.g., `std::env::var_os`.
+    fn from_vars(lookup: impl Fn(&str) -> Option<OsString>) -> Self {
+        let var = |name| lookup(name).filter(|value| !value.is_empty());
+        if var("NO_COLOR").is_some() {
+            Self::Never
+        } else if var("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+            Self::Always
+        } else {
+            Self::Auto
+        }
+    }
+}
+
+/// A substring to filter records by with `--filter-file` or `--filter-target`.
+struct Substring {
+    /// The substring, lowercased unless `case_sensitive`.
+    needle: String,
+    case_sensitive: bool,
+}
+
+impl Substring {
+    fn new(needle: &str, case_sensitive: bool) -> Self {
+        let needle = if case_sensitive {
+            needle.to_owned()
+        } else {
+            needle.to_lowercase()
+        };
+        Self { needle, case_sensitive }
+    }
+
+    /// Returns whether `haystack` is present and contains the substring.
+    fn is_in(&self, haystack: Option<&str>) -> bool {
+        haystack.is_some_and(|haystack| {
+            if self.case_sensitive {
+                haystack.contains(&self.needle)
+            } else {
+                haystack.to_lowercase().contains(&self.needle)
+            }
+        })
+    }
+}
+
+/// The globs of `--include` and `--exclude` to filter records by target.
+struct TargetGlobs {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+    options: glob::MatchOptions,
+    /// Whether records without a target pass, i.e., unless `--drop-untargeted`.
+    untargeted: bool,
+}
+
+impl TargetGlobs {
+    fn new(include: Vec<glob::Pattern>, exclude: Vec<glob::Pattern>, case_sensitive: bool, untargeted: bool) -> Self {
+        let options = glob::MatchOptions {
+            case_sensitive,
+            require_literal_separator: false,
+            require_literal_leading_dot: false,
+        };
+        Self {
+            include,
+            exclude,
+            options,
+            untargeted,
+        }
+    }
+
+    /// Returns whether `target` matches any of the includes, if there are any, and none of the excludes.
+    fn is_match(&self, target: Option<&str>) -> bool {
+        let Some(target) = target else {
+            return self.untargeted;
+        };
+        let any = |globs: &[glob::Pattern]| globs.iter().any(|glob| glob.matches_with(target, self.options));
+        (self.include.is_empty() || any(&self.include)) && !any(&self.exclude)
+    }
+}
+
+/// The regular expression of `--grep`, inverted by `--grep-invert`.
+#[derive(Clone, Copy)]
+struct Pattern<'a> {
+    regex: &'a Regex,
+    invert: bool,
+}
+
+impl Pattern<'_> {
+    /// Returns whether `message` matches, i.e., doesn't match the regex if inverted.
+    fn is_match(self, message: &str) -> bool {
+        self.regex.is_match(message) != self.invert
+    }
+}
+
+/// Filters records by `--grep`, keeping `--context` records around each match.
+struct Grep<'a> {
+    pattern: Pattern<'a>,
+    /// The number of records to print before and after each match.
+    context: usize,
+    /// The rendered records preceding the current one, at most `context`.
+    before: VecDeque<Vec<u8>>,
+    /// The number of records still to print after the last match.
+    after: usize,
+    /// Whether a record was skipped since the last printed one.
+    gap: bool,
+    /// Whether any record was printed yet.
+    printed: bool,
+}
+
+impl<'a> Grep<'a> {
+    fn new(pattern: Pattern<'a>, context: usize) -> Self {
+        Self {
+            pattern,
+            context,
+            before: VecDeque::with_capacity(context),
+            after: 0,
+            gap: false,
+            printed: false,
+        }
+    }
+
+    /// Writes `record` to `out` if it matches or is within the context of a match.
+    fn write(&mut self, out: &mut impl WriteColor, style: &TextStyle<'_>, record: &Record<'_>) -> io::Result<()> {
+        if self.pattern.is_match(&record.message) {
+            if self.printed && self.gap && self.context > 0 {
+                write_dimmed(out, |out| writeln!(out, "--"))?;
+            }
+            for before in self.before.drain(..) {
+                out.write_all(&before)?;
+            }
+            write_record(out, style, record)?;
+            self.after = self.context;
+            self.gap = false;
+            self.printed = true;
+        } else if self.after > 0 {
+            write_dimmed(out, |out| write_record(out, style, record))?;
+            self.after -= 1;
+        } else if self.context == 0 {
+            self.gap = true;
+        } else {
+            if self.before.len() == self.context {
+                self.before.pop_front();
+                self.gap = true;
+            }
+            // Render the record now, as it borrows from the line buffer.
+            let mut buf = if out.supports_color() {
+                termcolor::Buffer::ansi()
+            } else {
+                termcolor::Buffer::no_color()
+            };
+            write_dimmed(&mut buf, |out| write_record(out, style, record))?;
+            self.before.push_back(buf.into_inner());
+        }
+        Ok(())
+    }
+}
+
+/// What records must have in common to be collapsed by `--dedup`.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum DedupKey {
+    Message,
+    All,
+}
+
+/// The identity of the last record printed by [`Dedup`].
+struct DedupEntry {
+    severity: u8,
+    filename: Option<String>,
+    line_number: Option<u32>,
+    message: String,
+}
+
+/// Collapses runs of consecutive records with the same [`DedupKey`], for `--dedup`.
+struct Dedup {
+    key: DedupKey,
+    /// The last record printed.
+    last: Option<DedupEntry>,
+    /// The number of records repeating `last` that were not printed nor reported yet.
+    repeats: u64,
+    /// When the repeats were last reported.
+    reported: Instant,
+}
+
+impl Dedup {
+    fn new(key: DedupKey) -> Self {
+        Self {
+            key,
+            last: None,
+            repeats: 0,
+            reported: Instant::now(),
+        }
+    }
+
+    /// Returns whether `record` repeats the last record printed, counting it if so,
+    /// and otherwise remembers it as the last record printed.
+    fn is_repeat(&mut self, record: &Record<'_>) -> bool {
+        let repeat = self.last.as_ref().is_some_and(|last| {
+            last.message == record.message
+                && (self.key == DedupKey::Message
+                    || (last.severity == record.level.severity()
+                        && last.filename.as_deref() == record.filename.as_deref()
+                        && last.line_number == record.line_number))
+        });
+        if repeat {
+            self.repeats += 1;
+        } else {
+            self.last = Some(DedupEntry {
+                severity: record.level.severity(),
+                filename: record.filename.as_deref().map(str::to_owned),
+                line_number: record.line_number,
+                message: record.message.to_string(),
+            });
+        }
+        repeat
+    }
+
+    /// Writes how often the last record printed was repeated since it or the last report, if at all.
+    fn report(&mut self, out: &mut impl WriteColor) -> io::Result<()> {
+        self.reported = Instant::now();
+        if self.repeats == 0 {
+            return Ok(());
+        }
+        let repeats = std::mem::take(&mut self.repeats);
+        let times = if repeats == 1 { "time" } else { "times" };
+        write_dimmed(out, |out| writeln!(out, "(repeated {repeats} {times})"))
+    }
+}
+
+/// How often `--sample` prints a record.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Sample(u64);
+
+impl FromStr for Sample {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let n = s.strip_prefix("1/").unwrap_or(s);
+        match n.parse() {
+            Ok(n) if n > 0 => Ok(Self(n)),
+            _ => anyhow::bail!("invalid sample `{s}`, expected `1/N` with N at least 1, e.g. `1/10`"),
+        }
+    }
+}
+
+/// The most records `--max-rate` prints per period.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Rate {
+    count: u64,
+    per: Duration,
+}
+
+impl FromStr for Rate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, per) = s.split_once('/').unwrap_or((s, "s"));
+        let per = match per {
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_secs(60),
+            _ => anyhow::bail!("invalid rate `{s}`, expected a count per second or minute, e.g. `100/s` or `600/m`"),
+        };
+        match count.parse() {
+            Ok(count) if count > 0 => Ok(Self { count, per }),
+            _ => anyhow::bail!("invalid rate `{s}`, expected a count of at least 1, e.g. `100/s`"),
+        }
+    }
+}
+
+/// Which records [`Throttle`] skips.
+#[derive(Clone, Copy)]
+enum ThrottleLimit {
+    Sample(Sample),
+    MaxRate(Rate),
+}
+
+/// Skips records per `--sample` or `--max-rate`, counting them to report.
+struct Throttle {
+    limit: ThrottleLimit,
+    /// The number of records seen with `--sample`, or in the current period with `--max-rate`.
+    seen: u64,
+    /// When the current period of `--max-rate` started.
+    period: Instant,
+    /// The number of records skipped that were not reported yet.
+    skipped: u64,
+    /// When the skipped records were last reported.
+    reported: Instant,
+}
+
+impl Throttle {
+    fn new(limit: ThrottleLimit) -> Self {
+        let now = Instant::now();
+        Self {
+            limit,
+            seen: 0,
+            period: now,
+            skipped: 0,
+            reported: now,
+        }
+    }
+
+    /// Returns whether to skip a record arriving at `now`, counting it if so.
+    fn skips(&mut self, now: Instant) -> bool {
+        let skip = match self.limit {
+            ThrottleLimit::Sample(Sample(n)) => {
+                self.seen += 1;
+                (self.seen - 1) % n != 0
+            }
+            ThrottleLimit::MaxRate(Rate { count, per }) => {
+                if now.duration_since(self.period) >= per {
+                    self.period = now;
+                    self.seen = 0;
+                }
+                self.seen += 1;
+                self.seen > count
+            }
+        };
+        self.skipped += u64::from(skip);
+        skip
+    }
+
+    /// Writes how many records were skipped since the last report, if any.
+    fn report(&mut self, out: &mut impl WriteColor) -> io::Result<()> {
+        self.reported = Instant::now();
+        if self.skipped == 0 {
+            return Ok(());
+        }
+        let skipped = std::mem::take(&mut self.skipped);
+        let records = if skipped == 1 { "record" } else { "records" };
+        write_dimmed(out, |out| writeln!(out, "(skipped {skipped} {records})"))
+    }
+}
+
+/// Runs `write` with everything it writes to `out` dimmed.
+fn write_dimmed<W: WriteColor>(
+    out: &mut W,
+    write: impl FnOnce(&mut Dimmed<&mut W>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut dimmed = Dimmed(out);
+    dimmed.reset()?;
+    write(&mut dimmed)?;
+    dimmed.0.reset()
+}
+
+/// A writer that dims everything written to it, keeping other colors.
+struct Dimmed<W>(W);
+
+impl<W: Write> Write for Dimmed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: WriteColor> WriteColor for Dimmed<W> {
+    fn supports_color(&self) -> bool {
+        self.0.supports_color()
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        self.0.set_color(spec.clone().set_dimmed(true))
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.0.reset()?;
+        self.0.set_color(ColorSpec::new().set_dimmed(true))
+    }
+}
+
+/// A table of the latest record per key extracted by `--table-key`, redrawn in place.
+struct LiveTable<'a> {
+    regex: &'a Regex,
+    time: &'a TimeStyle,
+    /// The timestamp and first line of the message of the latest record per key.
+    rows: BTreeMap<String, (Option<chrono::DateTime<chrono::Utc>>, String)>,
+    /// The number of lines of the table last drawn, to move the cursor back over.
+    drawn_lines: usize,
+}
+
+impl<'a> LiveTable<'a> {
+    fn new(regex: &'a Regex, time: &'a TimeStyle) -> Self {
+        Self {
+            regex,
+            time,
+            rows: BTreeMap::new(),
+            drawn_lines: 0,
+        }
+    }
+
+    /// Updates the row of the key extracted from `record`, returning whether there was one.
+    fn update(&mut self, record: &Record<'_>) -> bool {
+        let Some(captures) = self.regex.captures(&record.message) else {
+            return false;
+        };
+        let key = captures
+            .name("key")
+            .or_else(|| captures.get(1))
+            .unwrap_or_else(|| captures.get(0).unwrap());
+        let message = record.message.lines().next().unwrap_or_default();
+        self.rows
+            .insert(key.as_str().to_owned(), (record.ts, message.to_owned()));
+        true
+    }
+
+    /// Draws the table to `out`, over the previously drawn one.
+    fn redraw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let mut builder = tabled::builder::Builder::default();
+        builder.set_header(["key", "ts", "message"]);
+        for (key, (ts, message)) in &self.rows {
+            let ts = ts.map(|ts| self.time.format(ts)).unwrap_or_default();
+            builder.push_record([key.clone(), ts, message.clone()]);
+        }
+        let mut table = builder.build();
+        table.with(Style::psql());
+        redraw_in_place(out, &table.to_string(), &mut self.drawn_lines)
+    }
+}
+
+/// Draws `table` to `out` over the `drawn_lines` lines drawn last, updating them.
+fn redraw_in_place<W: Write + ?Sized>(out: &mut W, table: &str, drawn_lines: &mut usize) -> io::Result<()> {
+    if *drawn_lines > 0 {
+        // Move the cursor up to the start of the old table and clear everything below.
+        write!(out, "\x1b[{drawn_lines}A\x1b[J")?;
+    }
+    writeln!(out, "{table}")?;
+    out.flush()?;
+    *drawn_lines = table.lines().count();
+    Ok(())
+}
+
+/// The number of records of each level, for `--count`.
+#[derive(Default)]
+struct LevelCounts([u64; 6]);
+
+impl LevelCounts {
+    fn add(&mut self, level: LogLevel) {
+        self.0[level.severity() as usize] += 1;
+    }
+
+    /// Renders the number of records of each level, in increasing order of severity, and their total.
+    fn table(&self) -> tabled::Table {
+        let mut builder = tabled::builder::Builder::default();
+        builder.set_header(["level", "count"]);
+        let levels = [
+            LogLevel::Trace,
+            LogLevel::Debug,
+            LogLevel::Info,
+            LogLevel::Warn,
+            LogLevel::Error,
+            LogLevel::Panic,
+        ];
+        for level in levels {
+            let count = self.0[level.severity() as usize];
+            builder.push_record([level_style(&level).0.to_owned(), count.to_string()]);
+        }
+        builder.push_record(["total".to_owned(), self.0.iter().sum::<u64>().to_string()]);
+        let mut table = builder.build();
+        table
+            .with(Style::psql())
+            .with(Modify::new(Columns::last()).with(Alignment::right()));
+        table
+    }
+}
+
+/// A field of a [`Record`] to group records by with `--group-by`.
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum GroupBy {
+    Level,
+    File,
+    Target,
+}
+
+impl GroupBy {
+    /// Returns the table header for this field.
+    fn header(self) -> &'static str {
+        match self {
+            Self::Level => "level",
+            Self::File => "file",
+            Self::Target => "target",
+        }
+    }
+
+    /// Returns the group `record` belongs to.
+    fn key<'r>(self, record: &'r Record<'_>) -> &'r str {
+        let key = match self {
+            Self::Level => Some(level_style(&record.level).0),
+            Self::File => record.filename.as_deref(),
+            Self::Target => record.target.as_deref(),
+        };
+        key.unwrap_or("<unknown>")
+    }
+}
+
+/// Renders the number of records in each group, most common first.
+fn group_by_table(group_by: GroupBy, counts: HashMap<String, u64>) -> tabled::Table {
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|(a_key, a_count), (b_key, b_count)| b_count.cmp(a_count).then_with(|| a_key.cmp(b_key)));
+
+    let mut builder = tabled::builder::Builder::default();
+    builder.set_header([group_by.header(), "count"]);
+    for (key, count) in counts {
+        builder.push_record([key, count.to_string()]);
+    }
+    let mut table = builder.build();
+    table
+        .with(Style::psql())
+        .with(Modify::new(Columns::last()).with(Alignment::right()));
+    table
+}
+
+/// The pager used by `--pager` when neither a pager nor `$PAGER` is given.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Returns whether the pager `cmd` is known to interpret ANSI color sequences.
+///
+/// This is a heuristic; unknown pagers are assumed not to support colors,
+/// as raw escape sequences are worse than no colors at all.
+fn pager_supports_color(cmd: &str) -> bool {
+    let mut words = cmd.split_whitespace();
+    let Some(program) = words.next() else {
+        return false;
+    };
+    let program = Path::new(program)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    match program {
+        "less" => {
+            let is_raw_flag = |arg: &str| match arg.strip_prefix("--") {
+                Some(long) => long.eq_ignore_ascii_case("raw-control-chars"),
+                None => arg.starts_with('-') && arg.contains(['R', 'r']),
+            };
+            words.any(is_raw_flag) || std::env::var("LESS").is_ok_and(|less| less.contains(['R', 'r']))
+        }
+        "moar" | "ov" | "bat" => true,
+        _ => false,
+    }
+}
+
+/// Spawns the pager `cmd` with a piped stdin.
+fn spawn_pager(cmd: &str) -> anyhow::Result<Child> {
+    let mut words = cmd.split_whitespace();
+    let program = words.next().context("the pager command is empty")?;
+    Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to spawn pager `{cmd}`"))
+}
+
+pub async fn exec(mut config: Config, args: &ArgMatches) -> Result<ExitCode, anyhow::Error> {
+    if args.get_flag("print_bq_schema") {
+        println!("{}", serde_json::to_string_pretty(&bq_schema())?);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let databases = (args.get_many::<String>("database"))
+        .map(|databases| databases.collect::<Vec<_>>())
+        .unwrap_or_default();
+    let follow = args.get_flag("follow");
+    let format = *args.get_one::<Format>("format").unwrap();
+    let pager = args.get_one::<String>("pager");
+    let colors = match args.get_one::<ColorScheme>("color_scheme") {
+        Some(colors) => colors.clone(),
+        None => match std::env::var("SPACETIME_LOG_COLORS") {
+            Ok(colors) => colors.parse().context("Invalid `$SPACETIME_LOG_COLORS`")?,
+            Err(_) => ColorScheme::default(),
+        },
+    };
+    let compact = format == Format::Compact;
+    let style = TextStyle {
+        template: args.get_one::<Template>("output_template"),
+        time: TimeStyle {
+            zone: args.get_one::<TimeZone>("timezone").copied(),
+            format: (args.get_one::<TimeFormat>("time_format").cloned())
+                .or_else(|| compact.then(|| TimeFormat::Strftime(COMPACT_TIME_FORMAT.to_owned()))),
+        },
+        colors,
+        trace: !args.get_flag("no_trace"),
+        demangle: args.get_flag("demangle"),
+        compact,
+        target: args.get_flag("show_target"),
+        level: *args.get_one::<LevelStyle>("level_style").unwrap(),
+        sources: (databases.len() > 1).then(|| SourceLabels::new(databases.iter().map(|&db| db.clone()).collect())),
+    };
+    let color = if args.get_flag("no_color") {
+        ColorMode::Never
+    } else if args.value_source("color") == Some(ValueSource::CommandLine) {
+        *args.get_one::<ColorMode>("color").unwrap()
+    } else {
+        ColorMode::from_vars(|name| std::env::var_os(name))
+    };
+    let group_by = args.get_one::<GroupBy>("group_by").copied();
+    let grep = args.get_one::<Regex>("grep").map(|regex| Pattern {
+        regex,
+        invert: args.get_flag("grep_invert"),
+    });
+    let table_key = args.get_one::<Regex>("table_key");
+    let context = args.get_one::<usize>("context").copied().unwrap_or(0);
+    let retry = args.get_flag("retry").then(|| {
+        let secs = args.get_one::<u64>("retry_max_delay").copied().unwrap_or(30);
+        Duration::from_secs(secs)
+    });
+    let min_level = args.get_one::<LogLevel>("level").copied();
+    let case_sensitive = args.get_flag("case_sensitive");
+    let substring = |id: &str| args.get_one::<String>(id).map(|s| Substring::new(s, case_sensitive));
+    let (filter_file, filter_target) = (substring("filter_file"), substring("filter_target"));
+    let globs = |id: &str| {
+        args.get_many::<glob::Pattern>(id)
+            .map(|globs| globs.cloned().collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    let (include, exclude) = (globs("include"), globs("exclude"));
+    let target_globs = (!include.is_empty() || !exclude.is_empty())
+        .then(|| TargetGlobs::new(include, exclude, case_sensitive, !args.get_flag("drop_untargeted")));
+    let since = args.get_one::<TimeArg>("since").map(|since| since.0);
+    let until = args.get_one::<TimeArg>("until").map(|until| until.0);
+    let drop_untimed = args.get_flag("drop_untimed");
+    let in_range = |ts: Option<DateTime<Utc>>| match ts {
+        Some(ts) => since.is_none_or(|since| ts >= since) && until.is_none_or(|until| ts <= until),
+        None => !drop_untimed || (since.is_none() && until.is_none()),
+    };
+    // Whether `record` passes the filters other than `--grep`, which is applied with `--context`.
+    let selected = |record: &Record<'_>| {
+        in_range(record.ts)
+            && min_level.is_none_or(|min| record.level.severity() >= min.severity())
+            && filter_file
+                .as_ref()
+                .is_none_or(|file| file.is_in(record.filename.as_deref()))
+            && filter_target
+                .as_ref()
+                .is_none_or(|target| target.is_in(record.target.as_deref()))
+            && target_globs
+                .as_ref()
+                .is_none_or(|globs| globs.is_match(record.target.as_deref()))
+    };
+    let keep = |record: &Record<'_>| selected(record) && grep.is_none_or(|grep| grep.is_match(&record.message));
+
+    // Open the file before anything else, to fail fast if it can't be written.
+    let file = args
+        .get_one::<PathBuf>("output")
+        .filter(|path| path.as_os_str() != "-")
+        .map(|path| {
+            File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Could not open `{}` for writing", path.display()))
+        })
+        .transpose()?;
+    // The output of the formats other than `text`.
+    let plain_out = || -> Box<dyn Write + '_> {
+        match &file {
+            Some(file) => Box::new(file),
+            None => Box::new(io::stdout().lock()),
+        }
+    };
+
+    let fail_on = args.get_one::<LogLevel>("fail_on").copied();
+    let head = args.get_one::<u32>("head").copied();
+    let mut logs = if let Some(path) = args.get_one::<PathBuf>("from_file") {
+        let rdr: Box<dyn io::BufRead> = if path.as_os_str() == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            let file = File::open(path).with_context(|| format!("Could not open `{}`", path.display()))?;
+            Box::new(io::BufReader::new(file))
+        };
+        LogStream::from_reader(Box::pin(futures::io::AllowStdIo::new(rdr)), fail_on, head)
+    } else {
+        let opts = LogsOptions {
+            server: args.get_one::<String>("server").cloned(),
+            num_lines: args.get_one::<u32>("num_lines").copied(),
+            follow,
+            min_level,
+            retry,
+            head,
+            interactive: !args.get_flag("force"),
+        };
+        let paging = args.get_one::<u32>("page_size").map(|&page_size| {
+            let interactive = io::stdin().is_terminal() && io::stderr().is_terminal();
+            Paging::new(page_size, args.get_one::<PageToken>("page_token").cloned(), interactive)
+        });
+        if let [database] = databases[..] {
+            open_logs(&mut config, database, &opts, fail_on, paging).await?
+        } else {
+            anyhow::ensure!(
+                paging.is_none(),
+                "`--page-size` can only be used with a single database"
+            );
+            // `--head` counts the lines of all of them together.
+            let opts = LogsOptions { head: None, ..opts };
+            let mut sources = Vec::with_capacity(databases.len());
+            for database in &databases {
+                sources.push(open_logs(&mut config, database, &opts, fail_on, None).await?);
+            }
+            LogStream::merged(sources, follow, fail_on, head)
+        }
+    };
+    if args.get_flag("validate") {
+        logs.validated = Some(0);
+    }
+    // Only `--follow` runs until interrupted, so only it handles Ctrl-C, rather than being killed,
+    // and every format then still prints, and flushes, what it has read.
+    if follow {
+        logs.interrupt = Some(Box::pin(interrupted()));
+    }
+
+    if args.get_flag("raw") {
+        let mut out = plain_out();
+        let mut line = String::new();
+        while logs.read_line(&mut line).await? {
+            write_raw_line(&mut out, &line)?;
+            line.clear();
+        }
+        out.flush()?;
+        return logs.finish();
+    }
+
+    // The lines that aren't records, e.g. from a newer server, are skipped by the summaries and the tables,
+    // and printed as they are by the `text` and `compact` formats, rather than ending the stream.
+    if let Some(group_by) = group_by {
+        let mut counts = HashMap::<String, u64>::new();
+        let mut line = String::new();
+        while logs.read_line(&mut line).await? {
+            if let Some(record) = parse_record(&line).filter(keep) {
+                *counts.entry(group_by.key(&record).to_owned()).or_default() += 1;
+            }
+            line.clear();
+        }
+        writeln!(plain_out(), "{}", group_by_table(group_by, counts))?;
+        return logs.finish();
+    }
+
+    if args.get_flag("count") {
+        let mut counts = LevelCounts::default();
+        let mut out = plain_out();
+        // When following, the table is printed as records arrive, rather than only at the end.
+        let in_place = file.is_none() && io::stdout().is_terminal();
+        let mut drawn_lines = 0;
+        let mut draw = |out: &mut dyn Write, counts: &LevelCounts| {
+            let table = counts.table().to_string();
+            if in_place {
+                redraw_in_place(out, &table, &mut drawn_lines)
+            } else {
+                writeln!(out, "{table}").and_then(|()| out.flush())
+            }
+        };
+        let (mut changed, mut last_draw) = (false, None::<Instant>);
+        let mut line = String::new();
+        while logs.read_line(&mut line).await? {
+            if let Some(record) = parse_record(&line).filter(keep) {
+                counts.add(record.level);
+                changed = true;
+            }
+            line.clear();
+            // Redraw once no more records have arrived yet, or at the latest every `COUNT_INTERVAL`.
+            let due = last_draw.is_none_or(|last| last.elapsed() >= COUNT_INTERVAL);
+            if follow && changed && (due || logs.is_idle()) {
+                draw(&mut out, &counts)?;
+                (changed, last_draw) = (false, Some(Instant::now()));
+            }
+        }
+        if changed || last_draw.is_none() {
+            draw(&mut out, &counts)?;
+        }
+        return logs.finish();
+    }
+
+    if let Some(table_key) =
+        table_key.filter(|_| format == Format::Text && file.is_none() && io::stdout().is_terminal())
+    {
+        let mut table = LiveTable::new(table_key, &style.time);
+        let mut stdout = io::stdout().lock();
+        let mut line = String::new();
+        while logs.read_line(&mut line).await? {
+            if parse_record(&line)
+                .filter(keep)
+                .is_some_and(|record| table.update(&record))
+            {
+                table.redraw(&mut stdout)?;
+            }
+            line.clear();
+        }
+        stdout.flush()?;
+        return logs.finish();
+    }
+
+    if format == Format::BqJson {
+        let mut out = plain_out();
+        let mut line = String::new();
+        while logs.read_line(&mut line).await? {
+            if let Some(record) = parse_record(&line).filter(keep) {
+                serde_json::to_writer(&mut out, &BqRecord::from(&record))?;
+                writeln!(out)?;
+            }
+            line.clear();
+        }
+        out.flush()?;
+        return logs.finish();
+    }
+
+    if format == Format::Csv {
+        let mut out = plain_out();
+        write!(out, "{CSV_HEADER}\r\n")?;
+        let mut line = String::new();
+        while logs.read_line(&mut line).await? {
+            if let Some(record) = parse_record(&line).filter(keep) {
+                write_csv_record(&mut out, &record)?;
+            }
+            line.clear();
+        }
+        out.flush()?;
+        return logs.finish();
+    }
+
+    if format == Format::Json {
+        let fields = args
+            .get_many::<JsonField>("json_fields")
+            .map(|fields| fields.copied().collect::<Vec<_>>());
+        let pretty = args.get_flag("json_pretty");
+        let enrich = args.get_flag("json_enrich");
+        // Unless normalizing, filter line by line, if at all, but still print each kept line as received.
+        let normalized = fields.is_some();
+        let filtered = since.is_some()
+            || until.is_some()
+            || min_level.is_some()
+            || filter_file.is_some()
+            || filter_target.is_some()
+            || target_globs.is_some()
+            || grep.is_some();
+        let mut out = plain_out();
+        let mut line = String::new();
+        while logs.read_line(&mut line).await? {
+            // Lines that aren't records are printed as they are, rather than ending the stream.
+            let record = (filtered || normalized)
+                .then(|| serde_json::from_str::<Record<'_>>(&line).ok())
+                .flatten();
+            // The database of each line, as the logs of several may be merged.
+            let origin = logs.origin().filter(|_| enrich);
+            match record {
+                Some(record) if !keep(&record) => {}
+                Some(record) if normalized => write_json_record(&mut out, &record, fields.as_deref(), pretty, origin)?,
+                _ if pretty || origin.is_some() => write_json_line(&mut out, &line, pretty, origin)?,
+                _ => out.write_all(line.as_bytes())?,
+            }
+            line.clear();
+        }
+        out.flush()?;
+        return logs.finish();
+    }
+
+    let mut pager = match pager {
+        Some(cmd) if cmd.is_empty() => {
+            let cmd = std::env::var("PAGER").ok().filter(|cmd| !cmd.trim().is_empty());
+            Some(cmd.unwrap_or_else(|| DEFAULT_PAGER.to_owned()))
+        }
+        cmd => cmd.cloned(),
+    }
+    .map(|cmd| spawn_pager(&cmd).map(|child| (cmd, child)))
+    .transpose()?;
+    let mut out: Box<dyn WriteColor> = match (&mut pager, &file) {
+        (Some((cmd, child)), _) => {
+            let stdin = child.stdin.take().unwrap();
+            let colored = match color {
+                ColorMode::Auto => pager_supports_color(cmd),
+                ColorMode::Always => true,
+                ColorMode::Never => false,
+            };
+            let stdin = io::BufWriter::new(stdin);
+            if colored {
+                Box::new(termcolor::Ansi::new(stdin))
+            } else {
+                Box::new(termcolor::NoColor::new(stdin))
+            }
+        }
+        (None, Some(file)) => {
+            let file = io::BufWriter::new(file);
+            if color == ColorMode::Always {
+                Box::new(termcolor::Ansi::new(file))
+            } else {
+                Box::new(termcolor::NoColor::new(file))
+            }
+        }
+        (None, None) => {
+            let term_color = match color {
+                ColorMode::Auto if std::io::stdout().is_terminal() => termcolor::ColorChoice::Auto,
+                ColorMode::Auto | ColorMode::Never => termcolor::ColorChoice::Never,
+                ColorMode::Always => termcolor::ColorChoice::Always,
+            };
+            Box::new(termcolor::BufferedStandardStream::stdout(term_color))
+        }
+    };
+
+    let humanize = args.get_flag("humanize");
+    let mut sink = TerminalSink {
+        out,
+        style: &style,
+        grep: grep.map(|pattern| Grep::new(pattern, context)),
+        dedup: args.get_one::<DedupKey>("dedup").copied().map(Dedup::new),
+        throttle: match (args.get_one::<Sample>("sample"), args.get_one::<Rate>("max_rate")) {
+            (Some(&sample), _) => Some(Throttle::new(ThrottleLimit::Sample(sample))),
+            (None, Some(&rate)) => Some(Throttle::new(ThrottleLimit::MaxRate(rate))),
+            (None, None) => None,
+        },
+        line_buffered: args.get_flag("line_buffered"),
+        last_flush: Instant::now(),
+    };
+    // Shown until the first lines are printed to the terminal, or throughout when writing to a file.
+    let mut progress = (!follow
+        && pager.is_none()
+        && args.get_one::<u32>("page_size").is_none()
+        && io::stdout().is_terminal()
+        && io::stderr().is_terminal())
+    .then(Progress::new);
+    let mut line = String::new();
+    while logs.read_line(&mut line).await? {
+        if let (Some(sources), Some(source)) = (&style.sources, logs.source()) {
+            sources.current.set(source);
+        }
+        let record = parse_record(&line);
+        if let Some(progress) = &mut progress {
+            progress.add(&line, record.is_some());
+        }
+        // Clear the spinner before printing to the terminal, rather than interleaving it with the lines.
+        if file.is_none() && record.as_ref().is_none_or(selected) {
+            progress = None;
+        }
+        let res = match record {
+            None => sink.line(&line),
+            Some(mut record) => {
+                if humanize {
+                    record.message = humanize_sizes(record.message);
+                }
+                if selected(&record) {
+                    sink.record(&record)
+                } else {
+                    Ok(())
+                }
+            }
+        };
+        // Still flush the records before, as the next ones may be a while, e.g. at the end of a page.
+        let res = res.and_then(|()| if logs.is_idle() { sink.idle() } else { Ok(()) });
+        match res {
+            // The pager was closed, so there's no one left to read the logs.
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe && pager.is_some() => break,
+            res => res?,
+        }
+
+        line.clear();
+    }
+
+    drop(progress);
+    match sink.finish() {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe && pager.is_some() => {}
+        res => res?,
+    }
+    drop(sink);
+    if let Some((_, mut child)) = pager {
+        child.wait()?;
+    }
+    logs.finish()
+}
+
+/// The [`LogSink`] of the `text` and `compact` formats, writing the records to a terminal, a pager, or a file.
+struct TerminalSink<'a, W> {
+    out: W,
+    style: &'a TextStyle<'a>,
+    grep: Option<Grep<'a>>,
+    dedup: Option<Dedup>,
+    throttle: Option<Throttle>,
+    /// Whether to flush after each record, per `--line-buffered`.
+    line_buffered: bool,
+    last_flush: Instant,
+}
+
+impl<W: WriteColor> TerminalSink<'_, W> {
+    fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.out.flush()
+    }
+
+    /// Flushes if `--line-buffered`, or if the records were buffered for long enough.
+    fn flush_if_due(&mut self) -> io::Result<()> {
+        if self.line_buffered || self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the runs of `--dedup` and the skips of `--sample` and `--max-rate` not reported yet.
+    fn report(&mut self) -> io::Result<()> {
+        if let Some(dedup) = &mut self.dedup {
+            dedup.report(&mut self.out)?;
+        }
+        if let Some(throttle) = &mut self.throttle {
+            throttle.report(&mut self.out)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: WriteColor> LogSink for TerminalSink<'_, W> {
+    fn record(&mut self, record: &Record<'_>) -> io::Result<()> {
+        if let Some(throttle) = &mut self.throttle {
+            let skip = throttle.skips(Instant::now());
+            // Report as skipping goes on, so that it's clear that records are missing.
+            if throttle.reported.elapsed() >= SKIP_REPORT_INTERVAL {
+                throttle.report(&mut self.out)?;
+                if skip {
+                    self.flush()?;
+                }
+            }
+            if skip {
+                return Ok(());
+            }
+        }
+        if let Some(dedup) = &mut self.dedup {
+            if dedup.is_repeat(record) {
+                // Report long runs as they go on, so that e.g. `--follow` shows progress.
+                if dedup.reported.elapsed() >= DEDUP_REPORT_INTERVAL {
+                    dedup.report(&mut self.out)?;
+                    self.flush()?;
+                }
+                return Ok(());
+            }
+            dedup.report(&mut self.out)?;
+        }
+        match &mut self.grep {
+            Some(grep) => grep.write(&mut self.out, self.style, record)?,
+            None => write_record(&mut self.out, self.style, record)?,
+        }
+        self.flush_if_due()
+    }
+
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        if let Some(dedup) = &mut self.dedup {
+            dedup.report(&mut self.out)?;
+        }
+        if let Some(sources) = &self.style.sources {
+            sources.write(&mut self.out)?;
+        }
+        write_raw_line(&mut self.out, line)?;
+        self.flush_if_due()
+    }
+
+    fn idle(&mut self) -> io::Result<()> {
+        // Report the runs and skips so far too, as they might otherwise be held back for a while.
+        self.report()?;
+        self.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.report()?;
+        // Reset the colors too, in case of an interrupt in the middle of a record.
+        self.out.reset()?;
+        self.out.flush()
+    }
+}
+
+/// The spinner on stderr showing how much of the logs has been received so far, while pulling them without `--follow`.
+///
+/// It's cleared when dropped.
+struct Progress {
+    spinner: indicatif::ProgressBar,
+    records: u64,
+    bytes: u64,
+}
+
+impl Progress {
+    fn new() -> Self {
+        let spinner = indicatif::ProgressBar::new_spinner();
+        spinner.enable_steady_tick(Duration::from_millis(60));
+        spinner.set_message("Fetching logs...");
+        Self {
+            spinner,
+            records: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Counts the received `line`, which is a record if `is_record`.
+    fn add(&mut self, line: &str, is_record: bool) {
+        self.records += u64::from(is_record);
+        self.bytes += line.len() as u64;
+        self.spinner.set_message(self.message());
+    }
+
+    fn message(&self) -> String {
+        let records = if self.records == 1 { "record" } else { "records" };
+        format!("Received {} {records} ({})", self.records, human_size(self.bytes))
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.spinner.finish_and_clear();
+    }
+}
+
+/// Resolves once Ctrl-C is pressed, or never if it can't be listened for.
+async fn interrupted() {
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending().await
+    }
+}
+
+/// Writes `record` to `out` in `style`.
+fn write_record<W: WriteColor>(out: &mut W, style: &TextStyle<'_>, record: &Record<'_>) -> io::Result<()> {
+    let prefix = match &style.sources {
+        Some(sources) => sources.write(out)?,
+        None => 0,
+    };
+    if style.compact {
+        return write_compact_record(out, style, record);
+    }
+    let dimmed = ColorSpec::new().set_dimmed(true).clone();
+
+    if let Some(template) = style.template {
+        template.write(out, style, record)?;
+    } else {
+        // The column the message starts at, to line up its later lines under the first.
+        let mut column = prefix;
+        if let Some(ts) = record.ts {
+            let ts = style.time.format(ts);
+            out.set_color(&dimmed)?;
+            write!(out, "{ts} ")?;
+            column += ts.chars().count() + 1;
+        }
+        let (level, width) = (style.level.label(record.level), style.level.width());
+        out.set_color(style.colors.get(record.level))?;
+        if style.level == LevelStyle::Bracketed {
+            // Brackets read as a separator already, and are padded after, so that they stay at the start.
+            write!(out, "{level}")?;
+            out.reset()?;
+            write!(out, "{:1$} ", "", width - level.len())?;
+            column += width + 1;
+        } else {
+            write!(out, "{level:>width$}: ")?;
+            out.reset()?;
+            column += width + 2;
+        }
+        if let Some(filename) = &record.filename {
+            let location = match record.line_number {
+                Some(line) => format!("{filename}:{line}"),
+                None => filename.to_string(),
+            };
+            out.set_color(&dimmed)?;
+            write!(out, "{location}")?;
+            out.reset()?;
+            column += location.chars().count();
+        }
+        write!(out, ": ")?;
+        column += 2;
+        if let Some(target) = record.target.as_ref().filter(|_| style.target) {
+            out.set_color(&dimmed)?;
+            write!(out, "[{target}]")?;
+            out.reset()?;
+            write!(out, " ")?;
+            column += target.chars().count() + 3;
+        }
+        write_hanging(out, &record.message, column)?;
+    }
+    if let Some(trace) = record.trace.as_ref().filter(|_| style.trace) {
+        // Numbered per record, from the innermost frame.
+        let write_name = |out: &mut W, name: &str| {
+            out.set_color(&dimmed)?;
+            write!(out, "{name}")?;
+            out.reset()
+        };
+        for (idx, frame) in trace.iter().enumerate() {
+            let function = (frame.func_name.as_deref()).map(|function| {
+                if style.demangle {
+                    demangle(function)
+                } else {
+                    function.into()
+                }
+            });
+            write!(out, "    #{idx} in ")?;
+            match (frame.module_name.as_deref(), function.as_deref()) {
+                (Some(module), Some(function)) => {
+                    write_name(out, module)?;
+                    write!(out, " :: ")?;
+                    write_name(out, function)?;
+                }
+                (Some(name), None) | (None, Some(name)) => write_name(out, name)?,
+                (None, None) => write!(out, "<unknown>")?,
+            }
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `message` and a line break to `out`, indenting each line after the first by `indent` columns,
+/// so that they line up under the first one.
+fn write_hanging(out: &mut impl Write, message: &str, indent: usize) -> io::Result<()> {
+    let mut lines = message.lines();
+    writeln!(out, "{}", lines.next().unwrap_or_default())?;
+    for line in lines {
+        writeln!(out, "{:indent$}{line}", "")?;
+    }
+    Ok(())
+}
+
+/// The default time format of the `compact` format.
+const COMPACT_TIME_FORMAT: &str = "%H:%M:%S";
+
+/// Writes `record` on a single line as `HH:MM:SS LVL message`,
+/// escaping the line breaks of its message and leaving out its backtrace.
+/// The time is left out for records without a timestamp.
+fn write_compact_record(out: &mut impl WriteColor, style: &TextStyle<'_>, record: &Record<'_>) -> io::Result<()> {
+    if let Some(ts) = record.ts {
+        out.set_color(ColorSpec::new().set_dimmed(true))?;
+        write!(out, "{} ", style.time.format(ts))?;
+    }
+    out.set_color(style.colors.get(record.level))?;
+    write!(out, "{}", record.level.abbrev())?;
+    out.reset()?;
+    writeln!(out, " {}", escape_line_breaks(&record.message))
+}
+
+/// Escapes the line breaks in `s` as `\n` and `\r`.
+fn escape_line_breaks(s: &str) -> Cow<'_, str> {
+    if s.contains(['\r', '\n']) {
+        s.replace('\r', "\\r").replace('\n', "\\n").into()
+    } else {
+        s.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `text` style of `spacetime logs` without any flags.
+    fn test_style() -> TextStyle<'static> {
+        TextStyle {
+            template: None,
+            time: TimeStyle::default(),
+            colors: ColorScheme::default(),
+            trace: true,
+            demangle: false,
+            compact: false,
+            target: false,
+            level: LevelStyle::Upper,
+            sources: None,
+        }
+    }
+
+    #[test]
+    fn pager_color_detection() {
+        assert!(pager_supports_color("less -R"));
+        assert!(pager_supports_color("less -FRX"));
+        assert!(pager_supports_color("/usr/bin/less --RAW-CONTROL-CHARS"));
+        assert!(pager_supports_color("moar"));
+        assert!(!pager_supports_color("more"));
+        assert!(!pager_supports_color("most -s"));
+        assert!(!pager_supports_color(""));
+    }
+
+    #[test]
+    fn bq_record_matches_schema() {
+        let line = r#"{"ts":1700000000000000,"level":"Warn","target":"db","filename":"lib.rs","line_number":7,"message":"hi","trace":[{"module_name":"m","func_name":"f"}]}"#;
+        let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+        let bq = serde_json::to_value(BqRecord::from(&record)).unwrap();
+        assert_eq!(bq["timestamp"], "2023-11-14T22:13:20.000000Z");
+        assert_eq!(bq["level"], "WARN");
+        assert_eq!(bq["trace"][0]["func_name"], "f");
+
+        let schema = bq_schema();
+        let names = |fields: &serde_json::Value| {
+            fields
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|field| field["name"].as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let keys = |value: &serde_json::Value| value.as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+        assert_eq!(names(&schema), keys(&bq));
+        assert_eq!(names(&schema[6]["fields"]), keys(&bq["trace"][0]));
+    }
+
+    #[test]
+    fn csv_record_quotes_fields() {
+        let line = r#"{"ts":1700000000000000,"level":"Info","filename":"a,b.rs","line_number":7,"message":"say \"hi\",\nbye"}"#;
+        let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+        let mut out = Vec::new();
+        write_csv_record(&mut out, &record).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2023-11-14T22:13:20.000000Z,INFO,\"a,b.rs\",7,\"say \"\"hi\"\",\nbye\"\r\n"
+        );
+
+        let line = r#"{"ts":null,"level":"Error","message":"plain"}"#;
+        let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+        let mut out = Vec::new();
+        write_csv_record(&mut out, &record).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), ",ERROR,,,plain\r\n");
+    }
+
+    #[test]
+    fn level_severity_order() {
+        let levels = [
+            LogLevel::Trace,
+            LogLevel::Debug,
+            LogLevel::Info,
+            LogLevel::Warn,
+            LogLevel::Error,
+            LogLevel::Panic,
+        ];
+        assert!(levels.windows(2).all(|w| w[0].severity() < w[1].severity()));
+    }
+
+    #[test]
+    fn grep_invert() {
+        let regex = Regex::new("^conn").unwrap();
+        let pattern = |invert| Pattern { regex: &regex, invert };
+        assert!(pattern(false).is_match("connected"));
+        assert!(!pattern(false).is_match("disconnected"));
+        assert!(!pattern(true).is_match("connected"));
+        assert!(pattern(true).is_match("disconnected"));
+    }
+
+    #[test]
+    fn time_style() {
+        let ts = DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap();
+        let style = |zone: Option<&str>, format: Option<&str>| TimeStyle {
+            zone: zone.map(|zone| zone.parse().unwrap()),
+            format: format.map(|format| format.parse().unwrap()),
+        };
+        assert_eq!(style(None, None).format(ts), format!("{ts:?}"));
+        assert_eq!(style(Some("utc"), None).format(ts), "2023-11-14T22:13:20.123456+00:00");
+        assert_eq!(
+            style(Some("+02:00"), None).format(ts),
+            "2023-11-15T00:13:20.123456+02:00"
+        );
+        assert_eq!(style(Some("-05:30"), Some("millis")).format(ts), "1700000000123");
+        assert_eq!(style(None, Some("%H:%M:%S%.3f %z")).format(ts), "22:13:20.123 +0000");
+        assert!("%Q".parse::<TimeFormat>().is_err());
+        assert!("mars".parse::<TimeZone>().is_err());
+    }
+
+    #[test]
+    fn substring_case() {
+        assert!(Substring::new("Auth", false).is_in(Some("src/auth.rs")));
+        assert!(!Substring::new("Auth", true).is_in(Some("src/auth.rs")));
+        assert!(Substring::new("auth", true).is_in(Some("src/auth.rs")));
+        assert!(!Substring::new("auth", false).is_in(None));
+    }
+
+    #[test]
+    fn target_globs() {
+        let globs = |include: &[&str], exclude: &[&str], untargeted| {
+            let patterns = |globs: &[&str]| globs.iter().map(|glob| glob::Pattern::new(glob).unwrap()).collect();
+            TargetGlobs::new(patterns(include), patterns(exclude), false, untargeted)
+        };
+        let filter = globs(&["module::reducers::*", "auth"], &["*::internal::*"], true);
+        assert!(filter.is_match(Some("module::reducers::send")));
+        assert!(filter.is_match(Some("module::reducers::chat::send")));
+        assert!(filter.is_match(Some("Auth")));
+        assert!(!filter.is_match(Some("module::reducers::internal::gc")));
+        assert!(!filter.is_match(Some("module::tables")));
+        assert!(filter.is_match(None));
+
+        let exclude_only = globs(&[], &["*::internal::*"], false);
+        assert!(exclude_only.is_match(Some("module::tables")));
+        assert!(!exclude_only.is_match(Some("module::internal::gc")));
+        assert!(!exclude_only.is_match(None));
+    }
+
+    #[test]
+    fn progress_message() {
+        let mut progress = Progress::new();
+        progress.add(&"x".repeat(1024), true);
+        assert_eq!(progress.message(), "Received 1 record (1.0 KiB)");
+        progress.add(&"x".repeat(512), false);
+        progress.add(&"x".repeat(512), true);
+        assert_eq!(progress.message(), "Received 2 records (2.0 KiB)");
+    }
+
+    #[test]
+    fn color_scheme() {
+        let scheme = "info = cyan, error=red+bold,warn=208".parse::<ColorScheme>().unwrap();
+        assert_eq!(scheme.get(LogLevel::Info), ColorSpec::new().set_fg(Some(Color::Cyan)));
+        assert_eq!(
+            scheme.get(LogLevel::Error),
+            ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true)
+        );
+        assert_eq!(
+            scheme.get(LogLevel::Warn),
+            ColorSpec::new().set_fg(Some(Color::Ansi256(208)))
+        );
+        assert_eq!(scheme.get(LogLevel::Panic), &level_style(&LogLevel::Panic).1);
+        assert!("info".parse::<ColorScheme>().is_err());
+        assert!("loud=red".parse::<ColorScheme>().is_err());
+        assert!("info=blurple".parse::<ColorScheme>().is_err());
+    }
+
+    #[test]
+    fn time_arg() {
+        let ts = |s: &str| s.parse::<TimeArg>().map(|ts| ts.0);
+        assert_eq!(
+            ts("2024-01-01T02:00:00+02:00").unwrap(),
+            DateTime::from_timestamp(1_704_067_200, 0).unwrap()
+        );
+        let ago = Utc::now() - ts("10m").unwrap();
+        assert!(ago >= chrono::TimeDelta::minutes(10) && ago < chrono::TimeDelta::minutes(11));
+        assert!(ts("10").is_err());
+        assert!(ts("m").is_err());
+        assert!(ts("10y").is_err());
+        assert!(ts("yesterday").is_err());
+    }
+
+    #[test]
+    fn trace_frames() {
+        let line = r#"{"ts":null,"level":"Error","filename":"lib.rs","line_number":7,"message":"oops","trace":[
+            {"module_name":"m","func_name":"_ZN4core3fmt5write17h1234567890abcdefE"},
+            {"module_name":"m","func_name":null},
+            {"module_name":null,"func_name":"f"},
+            {"module_name":null,"func_name":null}
+        ]}"#;
+        let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+        let render = |trace, demangle| {
+            let style = TextStyle {
+                trace,
+                demangle,
+                ..test_style()
+            };
+            let mut out = termcolor::NoColor::new(Vec::new());
+            write_record(&mut out, &style, &record).unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+        assert_eq!(
+            render(true, true),
+            "ERROR: lib.rs:7: oops
+    #0 in m :: core::fmt::write
+    #1 in m
+    #2 in f
+    #3 in <unknown>
+"
+        );
+        assert!(render(true, false).contains("#0 in m :: _ZN4core3fmt5write17h1234567890abcdefE\n"));
+        assert_eq!(render(false, false), "ERROR: lib.rs:7: oops\n");
+    }
+
+    #[test]
+    fn show_target() {
+        let render = |line: &str, target| {
+            let style = TextStyle { target, ..test_style() };
+            let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+            let mut out = termcolor::NoColor::new(Vec::new());
+            write_record(&mut out, &style, &record).unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+        let line = r#"{"ts":null,"level":"Info","target":"m::db","filename":"lib.rs","line_number":7,"message":"hi","trace":null}"#;
+        assert_eq!(render(line, true), " INFO: lib.rs:7: [m::db] hi\n");
+        assert_eq!(render(line, false), " INFO: lib.rs:7: hi\n");
+        let line = r#"{"ts":null,"level":"Info","target":null,"filename":"lib.rs","line_number":7,"message":"hi","trace":null}"#;
+        assert_eq!(render(line, true), " INFO: lib.rs:7: hi\n");
+    }
+
+    #[test]
+    fn multi_line_message() {
+        let style = TextStyle {
+            target: true,
+            ..test_style()
+        };
+        let line = r#"{"ts":null,"level":"Error","target":"m","filename":"lib.rs","line_number":7,"message":"failed:\n  cause\r\nend","trace":null}"#;
+        let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+        let mut out = termcolor::NoColor::new(Vec::new());
+        write_record(&mut out, &style, &record).unwrap();
+        assert_eq!(
+            String::from_utf8(out.into_inner()).unwrap(),
+            "ERROR: lib.rs:7: [m] failed:\n                       cause\n                     end\n"
+        );
+
+        // The colors are reset before the message, so the indentation is never colored.
+        let mut out = termcolor::Ansi::new(Vec::new());
+        write_record(&mut out, &style, &record).unwrap();
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        let (first, rest) = out.split_once('\n').unwrap();
+        assert!(first.ends_with("\x1b[0m failed:"));
+        assert_eq!(rest, "                       cause\n                     end\n");
+    }
+
+    #[test]
+    fn level_styles() {
+        let line =
+            r#"{"ts":null,"level":"Warn","filename":"lib.rs","line_number":7,"message":"low\ndisk","trace":null}"#;
+        let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+        let render = |level| {
+            let style = TextStyle { level, ..test_style() };
+            let mut out = termcolor::NoColor::new(Vec::new());
+            write_record(&mut out, &style, &record).unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+        assert_eq!(
+            render(LevelStyle::Upper),
+            " WARN: lib.rs:7: low\n                 disk\n"
+        );
+        assert_eq!(
+            render(LevelStyle::Lower),
+            " warn: lib.rs:7: low\n                 disk\n"
+        );
+        assert_eq!(render(LevelStyle::Letter), "W: lib.rs:7: low\n             disk\n");
+        assert_eq!(
+            render(LevelStyle::Bracketed),
+            "[WARN]  lib.rs:7: low\n                  disk\n"
+        );
+    }
+
+    #[test]
+    fn source_labels() {
+        let style = TextStyle {
+            sources: Some(SourceLabels::new(vec!["chat".to_owned(), "inventory".to_owned()])),
+            ..test_style()
+        };
+        let line =
+            r#"{"ts":null,"level":"Info","filename":"lib.rs","line_number":7,"message":"hi\nthere","trace":null}"#;
+        let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+        let render = |source| {
+            style.sources.as_ref().unwrap().current.set(source);
+            let mut out = termcolor::NoColor::new(Vec::new());
+            write_record(&mut out, &style, &record).unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+        assert_eq!(
+            render(0),
+            "chat      |  INFO: lib.rs:7: hi\n                             there\n"
+        );
+        assert_eq!(
+            render(1),
+            "inventory |  INFO: lib.rs:7: hi\n                             there\n"
+        );
+    }
+
+    #[test]
+    fn merged_by_timestamp() {
+        let record = |ts| format!(r#"{{"ts":{ts},"level":"Info","message":"{ts}","trace":null}}"#);
+        let source = |lines: Vec<String>| {
+            let rdr = futures::io::Cursor::new(lines.join("\n").into_bytes());
+            LogStream::from_reader(Box::pin(rdr), None, None)
+        };
+        let sources = vec![
+            source(vec![record(1), record(3)]),
+            source(vec![record(2), "not json".to_owned(), record(4)]),
+        ];
+        let mut logs = LogStream::merged(sources, false, None, None);
+        let mut read = Vec::new();
+        let mut line = String::new();
+        while futures::executor::block_on(logs.read_line(&mut line)).unwrap() {
+            read.push((logs.source().unwrap(), line.trim_end().to_owned()));
+            line.clear();
+        }
+        // Each database's lines stay in order, and those without a timestamp can't wait for the others.
+        assert_eq!(
+            read,
+            [
+                (0, record(1)),
+                (1, record(2)),
+                (1, "not json".to_owned()),
+                (0, record(3)),
+                (1, record(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn compact_record() {
+        let style = TextStyle {
+            time: TimeStyle {
+                zone: None,
+                format: Some(TimeFormat::Strftime(COMPACT_TIME_FORMAT.to_owned())),
+            },
+            compact: true,
+            ..test_style()
+        };
+        let render = |line: &str| {
+            let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+            let mut out = termcolor::NoColor::new(Vec::new());
+            write_record(&mut out, &style, &record).unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+        assert_eq!(
+            render(
+                r#"{"ts":1700000000000000,"level":"Warn","filename":"lib.rs","line_number":7,"message":"a\r\nb","trace":[{"module_name":"m","func_name":"f"}]}"#
+            ),
+            "22:13:20 WRN a\\r\\nb\n"
+        );
+        assert_eq!(
+            render(r#"{"ts":null,"level":"Info","filename":null,"line_number":null,"message":"hi","trace":null}"#),
+            "INF hi\n"
+        );
+    }
+
+    #[test]
+    fn dedup_records() {
+        let dedup = |key, lines: &[&str]| {
+            let mut dedup = Dedup::new(key);
+            let mut out = termcolor::NoColor::new(Vec::new());
+            for line in lines {
+                let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+                if !dedup.is_repeat(&record) {
+                    dedup.report(&mut out).unwrap();
+                    writeln!(out, "{}", record.message).unwrap();
+                }
+            }
+            dedup.report(&mut out).unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+        let a = r#"{"ts":null,"level":"Error","filename":"lib.rs","line_number":7,"message":"a","trace":null}"#;
+        let a_warn = r#"{"ts":null,"level":"Warn","filename":"lib.rs","line_number":7,"message":"a","trace":null}"#;
+        let b = r#"{"ts":null,"level":"Error","filename":"lib.rs","line_number":7,"message":"b","trace":null}"#;
+        assert_eq!(
+            dedup(DedupKey::Message, &[a, a, a, b, a_warn, a]),
+            "a\n(repeated 2 times)\nb\na\n(repeated 1 time)\n"
+        );
+        assert_eq!(dedup(DedupKey::All, &[a, a, a_warn, a]), "a\n(repeated 1 time)\na\na\n");
+    }
+
+    #[test]
+    fn throttle_records() {
+        assert_eq!("1/3".parse::<Sample>().unwrap(), Sample(3));
+        assert_eq!("3".parse::<Sample>().unwrap(), Sample(3));
+        assert!("1/0".parse::<Sample>().is_err());
+        assert!("2/3".parse::<Sample>().is_err());
+        let minute = Rate {
+            count: 600,
+            per: Duration::from_secs(60),
+        };
+        assert_eq!("600/m".parse::<Rate>().unwrap(), minute);
+        assert_eq!("100".parse::<Rate>().unwrap().per, Duration::from_secs(1));
+        assert!("100/h".parse::<Rate>().is_err());
+        assert!("0/s".parse::<Rate>().is_err());
+
+        let start = Instant::now();
+        let kept = |limit, times: &[u64]| {
+            let mut throttle = Throttle::new(limit);
+            throttle.period = start;
+            let kept = (times.iter())
+                .filter(|&&ms| !throttle.skips(start + Duration::from_millis(ms)))
+                .count();
+            (kept, throttle.skipped)
+        };
+        assert_eq!(kept(ThrottleLimit::Sample(Sample(3)), &[0; 7]), (3, 4));
+        let rate = ThrottleLimit::MaxRate(Rate {
+            count: 2,
+            per: Duration::from_secs(1),
+        });
+        assert_eq!(kept(rate, &[0, 10, 20, 30, 1000, 1010, 1020]), (4, 3));
+
+        let mut throttle = Throttle::new(ThrottleLimit::Sample(Sample(2)));
+        let mut out = termcolor::NoColor::new(Vec::new());
+        throttle.report(&mut out).unwrap();
+        for _ in 0..5 {
+            throttle.skips(start);
+        }
+        throttle.report(&mut out).unwrap();
+        throttle.skips(start);
+        throttle.report(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out.into_inner()).unwrap(),
+            "(skipped 2 records)\n(skipped 1 record)\n"
+        );
+    }
+
+    #[test]
+    fn level_counts() {
+        let mut counts = LevelCounts::default();
+        for level in [LogLevel::Error, LogLevel::Info, LogLevel::Error] {
+            counts.add(level);
+        }
+        let table = counts.table().to_string();
+        let rows = (table.lines())
+            .filter_map(|line| {
+                let (level, count) = line.split_once('|')?;
+                Some((level.trim().to_owned(), count.trim().to_owned()))
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            ("level", "count"),
+            ("TRACE", "0"),
+            ("DEBUG", "0"),
+            ("INFO", "1"),
+            ("WARN", "0"),
+            ("ERROR", "2"),
+            ("PANIC", "0"),
+            ("total", "3"),
+        ];
+        assert_eq!(
+            rows,
+            expected.map(|(level, count)| (level.to_owned(), count.to_owned()))
+        );
+    }
+
+    #[test]
+    fn record_into_owned() {
+        let line = String::from(
+            r#"{"ts":null,"level":"Warn","target":"t","filename":"lib.rs","line_number":7,"message":"hi","trace":[{"module_name":"m","func_name":null}]}"#,
+        );
+        let record = serde_json::from_str::<Record<'_>>(&line).unwrap().into_owned();
+        drop(line);
+        assert_eq!(record.target.as_deref(), Some("t"));
+        assert_eq!(record.filename.as_deref(), Some("lib.rs"));
+        assert_eq!(record.message, "hi");
+        let trace = record.trace.unwrap();
+        assert_eq!(trace[0].module_name.as_deref(), Some("m"));
+        assert!(trace[0].func_name.is_none());
+    }
+
+    #[test]
+    fn color_env() {
+        let from_vars = |vars: &[(&str, &str)]| {
+            let vars = vars.iter().copied().collect::<HashMap<_, _>>();
+            ColorMode::from_vars(|name| vars.get(name).map(OsString::from))
+        };
+        assert!(from_vars(&[]) == ColorMode::Auto);
+        assert!(from_vars(&[("CLICOLOR_FORCE", "0")]) == ColorMode::Auto);
+        assert!(from_vars(&[("NO_COLOR", "")]) == ColorMode::Auto);
+        assert!(from_vars(&[("CLICOLOR_FORCE", "1")]) == ColorMode::Always);
+        let color = from_vars(&[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]);
+        assert!(color == ColorMode::Never);
+
+        let style = test_style();
+        let mut out = match color {
+            ColorMode::Always => termcolor::Buffer::ansi(),
+            ColorMode::Auto | ColorMode::Never => termcolor::Buffer::no_color(),
+        };
+        let line = r#"{"ts":null,"level":"Error","filename":null,"line_number":null,"message":"hi","trace":null}"#;
+        write_record(&mut out, &style, &serde_json::from_str(line).unwrap()).unwrap();
+        assert!(!out.as_slice().contains(&b'\x1b'));
+    }
+
+    #[test]
+    fn json_record_fields() {
+        let line = r#"{"ts":1700000000000000,"level":"Warn","target":"t","filename":"lib.rs","line_number":7,"message":"hi","trace":null}"#;
+        let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+        let render = |fields: Option<&[JsonField]>, pretty| {
+            let mut out = Vec::new();
+            write_json_record(&mut out, &record, fields, pretty, None).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            render(None, false),
+            r#"{"timestamp":"2023-11-14T22:13:20.000000Z","level":"WARN","target":"t","filename":"lib.rs","line_number":7,"message":"hi","trace":[]}"#.to_owned() + "\n"
+        );
+        let fields = [JsonField::Message, JsonField::Timestamp];
+        assert_eq!(
+            render(Some(&fields), false),
+            r#"{"timestamp":"2023-11-14T22:13:20.000000Z","message":"hi"}"#.to_owned() + "\n"
+        );
+        assert_eq!(
+            render(Some(&fields), true),
+            "{\n  \"timestamp\": \"2023-11-14T22:13:20.000000Z\",\n  \"message\": \"hi\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let render = |line: &str, pretty, origin| {
+            let mut out = Vec::new();
+            write_json_line(&mut out, line, pretty, origin).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let line = "{\"ts\":null,\"level\":\"Info\",\"message\":\"hi\"}\n";
+        assert_eq!(
+            render(line, true, None),
+            "{\n  \"ts\": null,\n  \"level\": \"Info\",\n  \"message\": \"hi\"\n}\n"
+        );
+        assert_eq!(render("not json\n", true, None), "not json\n");
+
+        let origin = LogOrigin {
+            db: "c200".to_owned(),
+            server: "http://localhost:3000".to_owned(),
+        };
+        assert_eq!(
+            render(line, false, Some(&origin)),
+            "{\"ts\":null,\"level\":\"Info\",\"message\":\"hi\",\"db\":\"c200\",\"server\":\"http://localhost:3000\"}\n"
+        );
+        assert_eq!(render("not json\n", false, Some(&origin)), "not json\n");
+    }
+
+    #[test]
+    fn gzip_body() {
+        use flate2::write::GzEncoder;
+
+        let body = "{\"ts\":null,\"level\":\"Info\",\"message\":\"hi\"}\nplain text\n";
+        let read_lines =
+            |rdr: Pin<Box<dyn AsyncBufRead>>| futures::executor::block_on(rdr.lines().try_collect::<Vec<_>>()).unwrap();
+        let expected = ["{\"ts\":null,\"level\":\"Info\",\"message\":\"hi\"}", "plain text"];
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(body.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(
+            read_lines(decode_body(futures::io::Cursor::new(compressed), true)),
+            expected
+        );
+        // An uncompressed response is read as is.
+        assert_eq!(read_lines(decode_body(futures::io::Cursor::new(body), false)), expected);
+
+        // A corrupt body isn't mistaken for a lost connection, which `--retry` would reconnect after.
+        let corrupt = decode_body(futures::io::Cursor::new(body), true);
+        let err = futures::executor::block_on(corrupt.lines().try_collect::<Vec<_>>()).unwrap_err();
+        assert!(!is_connection_error(&err));
+    }
+
+    #[test]
+    fn output_template() {
+        let style = TextStyle {
+            trace: false,
+            ..test_style()
+        };
+        let line = r#"{"ts":null,"level":"Warn","target":"db","filename":"lib.rs","line_number":7,"message":"low disk","trace":null}"#;
+        let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+        let render = |template: &str| {
+            let template = template.parse::<Template>().unwrap();
+            let mut out = termcolor::NoColor::new(Vec::new());
+            template.write(&mut out, &style, &record).unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+        assert_eq!(render("{level} {file}:{line} {message}"), "WARN lib.rs:7 low disk\n");
+        assert_eq!(
+            render("{{{target}}} {filename}:{line_number} {msg}"),
+            "{db} lib.rs:7 low disk\n"
+        );
+        assert_eq!(render("}} {ts}|"), "} |\n");
+        assert_eq!(render("a } b"), "a } b\n");
+
+        let err = |template: &str| template.parse::<Template>().err().unwrap().to_string();
+        assert!(err("{lvl}").starts_with("unknown placeholder `{lvl}`"));
+        assert!(err("{level").starts_with("unclosed `{`"));
+    }
+
+    #[test]
+    fn terminal_sink() {
+        let style = TextStyle {
+            compact: true,
+            ..test_style()
+        };
+        let mut sink = TerminalSink {
+            out: termcolor::NoColor::new(Vec::new()),
+            style: &style,
+            grep: None,
+            dedup: Some(Dedup::new(DedupKey::Message)),
+            throttle: None,
+            line_buffered: false,
+            last_flush: Instant::now(),
+        };
+        let line = r#"{"ts":null,"level":"Warn","message":"low disk","trace":null}"#;
+        let record = serde_json::from_str::<Record<'_>>(line).unwrap();
+        for _ in 0..3 {
+            sink.record(&record).unwrap();
+        }
+        // The run is reported once no more records arrived, rather than only at the end.
+        sink.idle().unwrap();
+        sink.line("not json").unwrap();
+        sink.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(sink.out.into_inner()).unwrap(),
+            "WRN low disk\n(repeated 2 times)\nnot json\n"
+        );
+    }
+
+    #[test]
+    fn resume_lines() {
+        let mut logs = LogStream::from_reader(Box::pin(futures::io::empty()), None, None);
+        logs.num_lines = Some(0);
+        // Nothing was read yet, so reconnecting with `--num-lines 0` still requests no older lines.
+        assert_eq!(logs.resume_lines(), Some(0));
+        logs.last_ts = Some(Utc::now());
+        assert_eq!(logs.resume_lines(), Some(RESUME_LINES));
+    }
+
+    #[test]
+    fn interrupted_logs() {
+        let pending = futures::stream::pending::<io::Result<Vec<u8>>>().into_async_read();
+        let mut logs = LogStream::from_reader(Box::pin(pending), None, None);
+        logs.interrupt = Some(Box::pin(std::future::ready(())));
+        let mut line = String::new();
+        assert!(!futures::executor::block_on(logs.read_line(&mut line)).unwrap());
+        // The logs stay ended, rather than waiting for the next line again.
+        assert!(!futures::executor::block_on(logs.read_line(&mut line)).unwrap());
+        assert_eq!(logs.finish().unwrap(), ExitCode::from(130));
+    }
+
+    #[test]
+    fn skip_resumed() {
+        let mut logs = LogStream::from_reader(Box::pin(futures::io::empty()), None, None);
+        let ts = |secs| DateTime::from_timestamp(secs, 0);
+        for secs in [1, 2, 2] {
+            assert!(!logs.skip_resumed(ts(secs)));
+        }
+        assert_eq!((logs.last_ts, logs.last_ts_lines), (ts(2), 2));
+
+        // Reconnecting sends the lines read again, and then a new one with the same timestamp.
+        logs.resume_after = logs.last_ts.map(|ts| (ts, logs.last_ts_lines));
+        assert!(logs.skip_resumed(ts(1)));
+        assert!(logs.skip_resumed(ts(2)));
+        assert!(logs.skip_resumed(ts(2)));
+        assert!(!logs.skip_resumed(ts(2)));
+        assert_eq!((logs.last_ts, logs.last_ts_lines), (ts(2), 3));
+        assert!(!logs.skip_resumed(ts(3)));
+        assert_eq!((logs.last_ts, logs.last_ts_lines), (ts(3), 1));
+
+        // A line without a timestamp ends the lines read again, as there's no telling whether it was.
+        logs.resume_after = logs.last_ts.map(|ts| (ts, logs.last_ts_lines));
+        assert!(!logs.skip_resumed(None));
+        assert!(!logs.skip_resumed(ts(3)));
+        assert_eq!((logs.last_ts, logs.last_ts_lines), (ts(3), 2));
+    }
+
+    #[test]
+    fn raw_lines() {
+        let raw = |line: &str| {
+            let mut out = Vec::new();
+            write_raw_line(&mut out, line).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(raw("{\"level\":\"Unknown\"}\n"), "{\"level\":\"Unknown\"}\n");
+        assert_eq!(raw("no newline"), "no newline\n");
+
+        assert!(parse_record("{\"ts\":null,\"level\":\"Info\",\"message\":\"hi\"}\n").is_some());
+        assert!(parse_record("{\"level\":\"Unknown\",\"message\":\"hi\"}\n").is_none());
+        assert!(parse_record("not json\n").is_none());
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let line = "{\"ts\":null,\"level\":\"Unknown\",\"message\":\"hi\"}\n";
+        let err = serde_json::from_str::<Record<'_>>(line).err().unwrap();
+        let mut out = Vec::new();
+        write_invalid_line(&mut out, 3, line, &err).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("line 3 is not a valid record: {err}\n    {}\n", line.trim_end())
+        );
+        assert!(err.to_string().contains("Unknown"));
+    }
+
+    #[test]
+    fn lines_split_across_chunks() {
+        // The `é` of the first line is split between the first two chunks.
+        let chunks = [&b"h\xc3"[..], b"\xa9llo\nbad \xff", b"byte\n", b"end"];
+        let stream = futures::stream::iter(chunks.map(Ok::<_, io::Error>));
+        let mut rdr = stream.into_async_read();
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        while futures::executor::block_on(read_line_lossy(&mut rdr, &mut line)).unwrap() != 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+        assert_eq!(lines, ["héllo\n", "bad \u{fffd}byte\n", "end"]);
+    }
+
+    #[test]
+    fn humanized_sizes() {
+        let humanize = |message: &str| humanize_sizes(message.into()).into_owned();
+        assert_eq!(
+            humanize("sent 1572864 bytes, then 512 bytes, of 3221225472 bytes"),
+            "sent 1.5 MiB, then 512 bytes, of 3.0 GiB"
+        );
+        assert_eq!(humanize("1024 bytes"), "1.0 KiB");
+        assert_eq!(
+            humanize("no sizes, 2048bytes, 99999999999999999999 bytes"),
+            "no sizes, 2048bytes, 99999999999999999999 bytes"
+        );
+        assert_eq!(human_size(u64::MAX), "16.0 EiB");
+    }
+
+    #[test]
+    fn page_tokens() {
+        for token in [PageToken::Cursor("a:b".to_owned()), PageToken::Offset(20)] {
+            assert_eq!(token.to_string().parse::<PageToken>().unwrap(), token);
+        }
+        assert_eq!("offset:3".parse::<PageToken>().unwrap(), PageToken::Offset(3));
+        assert!("offset:x".parse::<PageToken>().is_err());
+        assert!("cursor:".parse::<PageToken>().is_err());
+        assert!("20".parse::<PageToken>().is_err());
+    }
 }
@@ -202,6 +202,7 @@ fn build_table<E>(
     );
 
     let ty = Typespace::EMPTY.with_type(schema);
+    let opts = satn::PsqlOptions::default();
     for row in rows {
         let row = row?;
         builder.push_record(ty.with_values(&row).enumerate().map(|(idx, value)| {
@@ -209,6 +210,7 @@ fn build_table<E>(
                 tuple: ty.ty(),
                 field: &ty.ty().elements[idx],
                 idx,
+                opts: &opts,
             };
 
             satn::PsqlWrapper { ty, value }.to_string()
@@ -0,0 +1,45 @@
+//! Benchmarks for formatting arrays of primitives as SATN,
+//! comparing the fast path of `Serializer::__serialize_primitive_array`
+//! to formatting the same numbers element by element.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use spacetimedb_sats::satn::{Satn, SatnFmt};
+use spacetimedb_sats::ser::{Serialize, Serializer};
+
+/// A `u32` serialized like any other array element, i.e., without the fast path.
+struct Elementwise(u32);
+
+impl Serialize for Elementwise {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.0)
+    }
+}
+
+fn bench_u32_array(c: &mut Criterion) {
+    let mut group = c.benchmark_group("satn_u32_array");
+    for len in [16, 1024, 65536] {
+        let nums = (0..len).map(|i| i * 7919).collect::<Vec<u32>>();
+        let elementwise = nums.iter().copied().map(Elementwise).collect::<Vec<_>>();
+        assert_eq!(nums.to_satn(), elementwise.to_satn());
+
+        group.throughput(Throughput::Elements(len.into()));
+        let fmt = SatnFmt::new();
+        let mut buf = String::new();
+        group.bench_with_input(BenchmarkId::new("primitive", len), &nums, |b, nums| {
+            b.iter(|| {
+                buf.clear();
+                fmt.format_into(black_box(nums), &mut buf);
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("elementwise", len), &elementwise, |b, elementwise| {
+            b.iter(|| {
+                buf.clear();
+                fmt.format_into(black_box(elementwise), &mut buf);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_u32_array);
+criterion_main!(benches);
@@ -1,3 +1,5 @@
+extern crate alloc;
+
 pub mod algebraic_type;
 mod algebraic_type_ref;
 pub mod algebraic_value;
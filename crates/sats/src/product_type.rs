@@ -16,6 +16,42 @@ pub const TIMESTAMP_TAG: &str = "__timestamp_micros_since_unix_epoch__";
 /// The tag used inside the special `TimeDuration` product type.
 pub const TIME_DURATION_TAG: &str = "__time_duration_micros__";
 
+/// A special known product type, identified by the tag of its only field.
+///
+/// See [`ProductType::special_tag`] and [`ProductType::special_type`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpecialType {
+    /// `spacetimedb_lib::Identity`, tagged [`IDENTITY_TAG`].
+    Identity,
+    /// `spacetimedb_lib::ConnectionId`, tagged [`CONNECTION_ID_TAG`].
+    ConnectionId,
+    /// [`crate::timestamp::Timestamp`], tagged [`TIMESTAMP_TAG`].
+    Timestamp,
+    /// [`crate::time_duration::TimeDuration`], tagged [`TIME_DURATION_TAG`].
+    TimeDuration,
+}
+
+impl SpecialType {
+    /// Returns the tag of the only field of this special type.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Self::Identity => IDENTITY_TAG,
+            Self::ConnectionId => CONNECTION_ID_TAG,
+            Self::Timestamp => TIMESTAMP_TAG,
+            Self::TimeDuration => TIME_DURATION_TAG,
+        }
+    }
+
+    /// Returns whether `ty` is the type of the only field of this special type.
+    fn is_field_type(self, ty: &AlgebraicType) -> bool {
+        match self {
+            Self::Identity => ty.is_u256(),
+            Self::ConnectionId => ty.is_u128(),
+            Self::Timestamp | Self::TimeDuration => ty.is_i64(),
+        }
+    }
+}
+
 /// A structural product type  of the factors given by `elements`.
 ///
 /// This is also known as `struct` and `tuple` in many languages,
@@ -149,16 +185,39 @@ impl ProductType {
         tag_name == TIME_DURATION_TAG
     }
 
+    /// Returns the special type `tag_name` is the tag of, if any.
+    pub fn special_tag(tag_name: &str) -> Option<SpecialType> {
+        Some(match tag_name {
+            IDENTITY_TAG => SpecialType::Identity,
+            CONNECTION_ID_TAG => SpecialType::ConnectionId,
+            TIMESTAMP_TAG => SpecialType::Timestamp,
+            TIME_DURATION_TAG => SpecialType::TimeDuration,
+            _ => return None,
+        })
+    }
+
+    /// Returns which special known type this is, if any.
+    /// Does not follow `Ref`s.
+    pub fn special_type(&self) -> Option<SpecialType> {
+        match &*self.elements {
+            [ProductTypeElement {
+                name: Some(name),
+                algebraic_type,
+            }] => Self::special_tag(name).filter(|special| special.is_field_type(algebraic_type)),
+            _ => None,
+        }
+    }
+
     /// Returns whether this is a special known `tag`,
     /// currently `Address`, `Identity`, `Timestamp` or `TimeDuration`.
     pub fn is_special_tag(tag_name: &str) -> bool {
-        [IDENTITY_TAG, CONNECTION_ID_TAG, TIMESTAMP_TAG, TIME_DURATION_TAG].contains(&tag_name)
+        Self::special_tag(tag_name).is_some()
     }
 
     /// Returns whether this is a special known type, currently `ConnectionId` or `Identity`.
     /// Does not follow `Ref`s.
     pub fn is_special(&self) -> bool {
-        self.is_identity() || self.is_connection_id() || self.is_timestamp() || self.is_time_duration()
+        self.special_type().is_some()
     }
 
     /// Returns whether this is a unit type, that is, has no elements.
@@ -315,3 +374,35 @@ where
 }
 
 impl<'a, I> ExactSizeIterator for ElementValuesWithType<'a, I> where I: ExactSizeIterator<Item = &'a AlgebraicValue> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn special_tags_round_trip() {
+        for special in [
+            SpecialType::Identity,
+            SpecialType::ConnectionId,
+            SpecialType::Timestamp,
+            SpecialType::TimeDuration,
+        ] {
+            assert_eq!(ProductType::special_tag(special.tag()), Some(special));
+        }
+        assert_eq!(ProductType::special_tag("__not_special__"), None);
+    }
+
+    #[test]
+    fn special_type_checks_the_field_type() {
+        let special = |ty: AlgebraicType| ty.into_product().unwrap().special_type();
+        assert_eq!(special(AlgebraicType::identity()), Some(SpecialType::Identity));
+        assert_eq!(special(AlgebraicType::connection_id()), Some(SpecialType::ConnectionId));
+        assert_eq!(special(AlgebraicType::timestamp()), Some(SpecialType::Timestamp));
+        assert_eq!(special(AlgebraicType::time_duration()), Some(SpecialType::TimeDuration));
+        assert_eq!(
+            special(AlgebraicType::product([(TIMESTAMP_TAG, AlgebraicType::U8)])),
+            None
+        );
+        assert_eq!(special(AlgebraicType::unit()), None);
+    }
+}
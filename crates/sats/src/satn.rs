@@ -1,44 +1,257 @@
+use crate::algebraic_type::fmt::fmt_algebraic_type;
+use crate::algebraic_value::ser::value_serialize;
+use crate::bsatn::{self, DecodeError};
 use crate::de::DeserializeSeed;
+use crate::product_type::SpecialType;
 use crate::time_duration::TimeDuration;
 use crate::timestamp::Timestamp;
-use crate::{i256, u256, AlgebraicValue, WithTypespace};
-use crate::{ser, ProductType, ProductTypeElement};
+use crate::{i256, u256, AlgebraicType, AlgebraicValue, SumType, SumValue, Typespace, WithTypespace};
+use crate::{ser, ProductType, ProductTypeElement, ProductValue};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, SecondsFormat};
 use core::fmt;
 use core::fmt::Write as _;
-use derive_more::{From, Into};
+use std::borrow::Cow;
+use std::io;
+use std::sync::Arc;
+
+pub mod fmt_util;
+pub mod json;
+mod parse;
+
+pub use fmt_util::{display_width, PrettySep};
+use fmt_util::{EntryWrapper, Layout, Writer};
+pub use parse::{from_satn, SatnParseError};
 
 /// An extension trait for [`Serialize`](ser::Serialize) providing formatting methods.
+///
+/// To customize the output, use a [`SatnFmt`].
 pub trait Satn: ser::Serialize {
     /// Formats the value using the SATN data format into the formatter `f`.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Writer::with(f, |f| self.serialize(SatnFormatter { f }))?;
-        Ok(())
+        self.fmt_with(f, &SatnOptions::default())
+    }
+
+    /// Formats the value using the SATN data format into the formatter `f`,
+    /// customized by `opts`.
+    fn fmt_with(&self, f: &mut fmt::Formatter, opts: &SatnOptions) -> fmt::Result {
+        // We use `alternate`, i.e., the `#` flag to let the user trigger pretty printing.
+        let pretty = f.alternate();
+        Ok(SatnFmt::new().options(opts.clone()).pretty(pretty).write_to(self, f)?)
     }
 
     /// Formats the value using the postgres SATN(PsqlFormatter { f }, /* PsqlType */) formatter `f`.
     fn fmt_psql(&self, f: &mut fmt::Formatter, ty: &PsqlType<'_>) -> fmt::Result {
-        Writer::with(f, |f| {
-            self.serialize(PsqlFormatter {
-                fmt: SatnFormatter { f },
-                ty,
-            })
+        let layout = f.alternate().then_some(Layout::DEFAULT);
+        write_limited(f, ty.opts.satn.max_bytes, layout, |f| {
+            let fmt = SatnFormatter::new(f, &ty.opts.satn);
+            serialize_psql(self, fmt, ty)
         })?;
         Ok(())
     }
 
     /// Formats the value using the SATN data format into the returned `String`.
+    ///
+    /// This is `SatnFmt::new().format(self)`.
     fn to_satn(&self) -> String {
-        Wrapper::from_ref(self).to_string()
+        SatnFmt::new().format(self)
+    }
+
+    /// Formats the value using the SATN data format into the returned `String`,
+    /// customized by `opts`.
+    ///
+    /// This is `SatnFmt::new().options(opts.clone()).format(self)`.
+    fn to_satn_with(&self, opts: &SatnOptions) -> String {
+        SatnFmt::new().options(opts.clone()).format(self)
+    }
+
+    /// Formats the value using the SATN data format into the returned `String`,
+    /// returning an error if formatting fails, unlike [`Satn::to_satn`], which panics.
+    ///
+    /// This is `SatnFmt::new().try_format(self)`.
+    fn try_to_satn(&self) -> Result<String, SatnError> {
+        SatnFmt::new().try_format(self)
+    }
+
+    /// Formats the value using the SATN data format into the returned `String`,
+    /// customized by `opts`, returning an error if formatting fails, unlike [`Satn::to_satn_with`].
+    ///
+    /// This is `SatnFmt::new().options(opts.clone()).try_format(self)`.
+    fn try_to_satn_with(&self, opts: &SatnOptions) -> Result<String, SatnError> {
+        SatnFmt::new().options(opts.clone()).try_format(self)
+    }
+
+    /// Formats the value using the SATN data format, appending it to `buf`.
+    ///
+    /// This is `SatnFmt::new().format_into(self, buf)`.
+    fn write_satn_into(&self, buf: &mut String) {
+        SatnFmt::new().format_into(self, buf)
+    }
+
+    /// Pretty prints the value using the SATN data format, appending it to `buf`.
+    ///
+    /// This is `SatnFmt::new().pretty(true).format_into(self, buf)`.
+    fn write_satn_pretty_into(&self, buf: &mut String) {
+        SatnFmt::new().pretty(true).format_into(self, buf)
     }
 
     /// Pretty prints the value using the SATN data format into the returned `String`.
+    ///
+    /// This is `SatnFmt::new().pretty(true).format(self)`.
     fn to_satn_pretty(&self) -> String {
-        format!("{:#}", Wrapper::from_ref(self))
+        SatnFmt::new().pretty(true).format(self)
+    }
+
+    /// Pretty prints the value using the SATN data format into the returned `String`,
+    /// customized by `opts`.
+    ///
+    /// This is `SatnFmt::new().pretty_options(opts.clone()).format(self)`.
+    fn to_satn_pretty_with(&self, opts: &SatnPrettyOptions) -> String {
+        SatnFmt::new().pretty_options(opts.clone()).format(self)
+    }
+
+    /// Pretty prints the value using the SATN data format into the returned `String`,
+    /// keeping each product and array on one line if it fits within `max_width` columns.
+    ///
+    /// This is `SatnFmt::new().max_line_width(max_width).format(self)`.
+    /// With `usize::MAX`, the whole value is on one line.
+    fn to_satn_pretty_width(&self, max_width: usize) -> String {
+        SatnFmt::new().max_line_width(max_width).format(self)
+    }
+
+    /// Pretty prints the value using the SATN data format into the returned `String`,
+    /// colored with ANSI escape codes for reading in a terminal if [`supports_color`].
+    ///
+    /// This is `SatnFmt::new().pretty(true).color(supports_color()).format(self)`.
+    fn to_satn_colored(&self) -> String {
+        SatnFmt::new().pretty(true).color(supports_color()).format(self)
+    }
+
+    /// Formats the value using the SATN data format into the returned `String`,
+    /// in a form intended for snapshot testing, e.g., with `insta`.
+    ///
+    /// This is `SatnFmt::snapshot().format(self)`, see [`SatnFmt::snapshot`] for the guarantees of the output.
+    fn to_satn_snapshot(&self) -> String {
+        SatnFmt::snapshot().format(self)
+    }
+
+    /// Formats the value using the SATN data format into the returned `String`,
+    /// in a canonical form intended for hashing, e.g., to detect changes to rows.
+    ///
+    /// This is `SatnFmt::canonical().format(self)`, see [`SatnFmt::canonical`] for the guarantees of the output.
+    fn to_satn_canonical(&self) -> String {
+        SatnFmt::canonical().format(self)
+    }
+
+    /// Formats the value using the SATN data format into the returned `String`,
+    /// with the fields of every product sorted by name.
+    ///
+    /// This is `SatnFmt::new().field_order(FieldOrder::ByName).try_format(self)`, see [`SatnFmt::field_order`].
+    fn to_satn_by_name(&self) -> Result<String, SatnError> {
+        SatnFmt::new().field_order(FieldOrder::ByName).try_format(self)
+    }
+
+    /// Returns the length in bytes of [`Satn::to_satn`], without allocating the `String`.
+    ///
+    /// This is `SatnFmt::new().formatted_len(self)`.
+    fn satn_len(&self) -> usize {
+        SatnFmt::new().formatted_len(self)
+    }
+
+    /// Returns the length in bytes of [`Satn::to_satn_pretty`], without allocating the `String`.
+    ///
+    /// This is `SatnFmt::new().pretty(true).formatted_len(self)`.
+    fn satn_pretty_len(&self) -> usize {
+        SatnFmt::new().pretty(true).formatted_len(self)
+    }
+
+    /// Formats the value as JSON into the formatter `f`,
+    /// pretty printing it when the `#` flag is used.
+    ///
+    /// This is `fmt::Display::fmt(&json::Json(self), f)`, see [`json`] for how values map to JSON.
+    fn fmt_json(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&json::Json(self), f)
+    }
+
+    /// Formats the value as JSON into the returned `String`.
+    ///
+    /// This is [`json::to_string`], see [`json`] for how values map to JSON.
+    fn to_satn_json(&self) -> String {
+        json::to_string(self)
+    }
+
+    /// Pretty prints the value as JSON into the returned `String`.
+    ///
+    /// This is [`json::to_string_pretty`].
+    fn to_satn_json_pretty(&self) -> String {
+        json::to_string_pretty(self)
     }
 }
 
 impl<T: ser::Serialize + ?Sized> Satn for T {}
 
+impl WithTypespace<'_, AlgebraicType> {
+    /// Formats `value` of this type using the SATN data format into the returned `String`,
+    /// annotating each product element and sum variant with its type, e.g., `(name: U32 = 5)`.
+    ///
+    /// This is intended for diagnosing schema mismatches, and the output isn't valid SATN.
+    pub fn to_satn_annotated(&self, value: &AlgebraicValue) -> String {
+        SatnFmt::new().annotate_types(true).format_typed(*self, value)
+    }
+
+    /// Formats `value` of this type using the SATN data format into the returned `String`,
+    /// customized by `opts`, including the options that need the type.
+    ///
+    /// This is `SatnFmt::new().options(opts.clone()).try_format_typed(*self, value)`.
+    pub fn to_satn_typed_with(&self, value: &AlgebraicValue, opts: &SatnOptions) -> Result<String, SatnError> {
+        SatnFmt::new().options(opts.clone()).try_format_typed(*self, value)
+    }
+
+    /// Pretty prints `value` of this type using the SATN data format into the returned `String`,
+    /// customized by `opts`, including the options that need the type.
+    ///
+    /// This is `SatnFmt::new().pretty_options(opts.clone()).try_format_typed(*self, value)`.
+    pub fn to_satn_typed_pretty_with(
+        &self,
+        value: &AlgebraicValue,
+        opts: &SatnPrettyOptions,
+    ) -> Result<String, SatnError> {
+        SatnFmt::new()
+            .pretty_options(opts.clone())
+            .try_format_typed(*self, value)
+    }
+}
+
+impl AlgebraicValue {
+    /// Formats this value using the SATN data format into the returned `String`,
+    /// taking the names of fields and variants from its type `ty`, whose references are resolved in `typespace`.
+    ///
+    /// A value alone has no names, so [`Satn::to_satn`] prints the fields of a product by position.
+    /// Unlike [`WithTypespace::to_satn_annotated`], this doesn't annotate the types.
+    pub fn to_satn_typed(&self, ty: &AlgebraicType, typespace: &Typespace) -> String {
+        typespace.with_type(ty).with_value(self).to_satn()
+    }
+}
+
+/// Decodes `bytes`, encoded in BSATN, as a value of `ty` and formats it using the SATN data format,
+/// e.g., to inspect a payload as it was sent on the wire.
+///
+/// Fails if `bytes` isn't exactly the encoding of one such value,
+/// e.g., if it's truncated, or has bytes left over after the value.
+pub fn decode_bsatn_to_satn(ty: WithTypespace<'_, AlgebraicType>, bytes: &[u8]) -> Result<String, DecodeError> {
+    let mut rest = bytes;
+    let value = ty.deserialize(bsatn::Deserializer::new(&mut rest))?;
+    if !rest.is_empty() {
+        let expected = bytes.len() - rest.len();
+        return Err(DecodeError::InvalidLen {
+            expected,
+            given: bytes.len(),
+        });
+    }
+    Ok(ty.with_value(&value).to_satn())
+}
+
 /// A wrapper around a `T: Satn`
 /// providing `Display` and `Debug` implementations
 /// that uses the SATN formatting for `T`.
@@ -66,176 +279,852 @@ impl<T: Satn + ?Sized> fmt::Debug for Wrapper<T> {
     }
 }
 
-/// A wrapper around a `T: Satn`
-/// providing `Display` and `Debug` implementations
-/// that uses postgres SATN formatting for `T`.
-pub struct PsqlWrapper<'a, T: ?Sized> {
-    pub ty: PsqlType<'a>,
-    pub value: T,
+/// A wrapper around a `&T: Satn` providing a [`serde::Serialize`] implementation
+/// that serializes the SATN formatting of `T` as a string,
+/// e.g., to embed it in a `serde_json` document.
+///
+/// The text is handed to [`collect_str`](serde::Serializer::collect_str),
+/// so serializers writing strings straight to their output, like `serde_json::to_writer`,
+/// don't render it into an intermediate `String`.
+#[cfg(feature = "serde")]
+pub struct SatnString<'a, T: ?Sized>(pub &'a T);
+
+#[cfg(feature = "serde")]
+impl<T: Satn + ?Sized> serde::Serialize for SatnString<'_, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(Wrapper::from_ref(self.0))
+    }
 }
 
-impl<T: ?Sized> PsqlWrapper<'_, T> {
-    /// Converts `&T` to `&PsqlWrapper<T>`.
-    pub fn from_ref(t: &T) -> &Self {
-        // SAFETY: `repr(transparent)` turns the ABI of `T`
-        // into the same as `Self` so we can also cast `&T` to `&Self`.
-        unsafe { &*(t as *const T as *const Self) }
-    }
+/// Implements [`Debug`](fmt::Debug) for each of the given types using the SATN formatting,
+/// like [`Wrapper`], pretty printing with `{:#?}`.
+///
+/// The types need only implement [`Serialize`](ser::Serialize).
+///
+/// ```ignore
+/// spacetimedb_sats::satn_debug!(MyRow, MyEvent);
+/// ```
+#[macro_export]
+macro_rules! satn_debug {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl ::core::fmt::Debug for $ty {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    $crate::satn::Satn::fmt(self, f)
+                }
+            }
+        )+
+    };
 }
 
-impl<T: Satn + ?Sized> fmt::Display for PsqlWrapper<'_, T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.value.fmt_psql(f, &self.ty)
+/// Options controlling the pretty SATN output.
+#[derive(Debug, Clone)]
+pub struct SatnPrettyOptions {
+    /// The indentation per nesting level, e.g., `"  "` or `"\t"`.
+    /// Defaults to four spaces.
+    pub indent: Cow<'static, str>,
+    /// The maximum width of lines, or `None`, the default, to put every element on its own line.
+    ///
+    /// When set, products and arrays are kept on one line if they fit within the width,
+    /// e.g., `(x = 1, y = 2)`, and only broken across lines otherwise.
+    /// Each nested product and array is laid out independently, like `rustfmt` does.
+    pub max_line_width: Option<usize>,
+    /// Whether to only separate the entries of products and arrays,
+    /// rather than also ending the last one with a separator, e.g., `,`, as SATN allows.
+    ///
+    /// This is `false` by default, keeping the output of earlier versions,
+    /// but makes it readable by strict parsers that reject trailing separators.
+    pub strict: bool,
+    /// How to separate the elements of arrays put on their own lines, with commas by default.
+    ///
+    /// The fields of products are always separated by commas.
+    pub array_sep: PrettySep,
+    /// The options shared with the non-pretty SATN output.
+    pub satn: SatnOptions,
+}
+
+impl Default for SatnPrettyOptions {
+    fn default() -> Self {
+        Self {
+            indent: Cow::Borrowed(Layout::DEFAULT.indent),
+            max_line_width: None,
+            strict: false,
+            array_sep: PrettySep::Comma,
+            satn: SatnOptions::default(),
+        }
     }
 }
 
-impl<T: Satn + ?Sized> fmt::Debug for PsqlWrapper<'_, T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.value.fmt_psql(f, &self.ty)
+impl SatnPrettyOptions {
+    /// Returns the layout of the pretty printed output, e.g., for [`Writer::with`].
+    pub fn layout(&self) -> Layout<'_> {
+        Layout {
+            indent: &self.indent,
+            max_line_width: self.max_line_width,
+            strict: self.strict,
+            array_sep: self.array_sep,
+        }
     }
 }
 
-/// Wraps a writer for formatting lists separated by `SEP` into it.
-struct EntryWrapper<'a, 'f, const SEP: char> {
-    /// The writer we're formatting into.
-    fmt: Writer<'a, 'f>,
-    /// Whether there were any fields.
-    /// Initially `false` and then `true` after calling [`.entry(..)`](EntryWrapper::entry).
-    has_fields: bool,
+/// A builder of SATN output, e.g.,
+/// `SatnFmt::new().indent("  ").max_depth(32).bytes(ByteEncoding::Base64).format(&value)`.
+///
+/// This sets the fields of [`SatnPrettyOptions`] and [`SatnOptions`] one at a time,
+/// leaving the rest at their defaults, so that [`Satn::to_satn`] is `SatnFmt::new().format(value)`.
+/// The output is on one line unless [`SatnFmt::pretty`] is set,
+/// which setting any of the options of the pretty output does too, e.g., [`SatnFmt::indent`].
+#[derive(Debug, Clone, Default)]
+pub struct SatnFmt {
+    opts: SatnPrettyOptions,
+    pretty: bool,
 }
 
-impl<'a, 'f, const SEP: char> EntryWrapper<'a, 'f, SEP> {
-    /// Constructs the entry wrapper using the writer `fmt`.
-    fn new(fmt: Writer<'a, 'f>) -> Self {
-        Self { fmt, has_fields: false }
+impl SatnFmt {
+    /// Returns a builder of the output of [`Satn::to_satn`].
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    /// Formats another entry in the larger structure.
+    /// Returns a builder of output in a form intended for snapshot testing, e.g., with `insta`,
+    /// i.e., with [`SatnOptions::snapshot`].
     ///
-    /// The formatting for the element / entry itself is provided by the function `entry`.
-    fn entry(&mut self, entry: impl FnOnce(Writer) -> fmt::Result) -> fmt::Result {
-        let res = (|| match &mut self.fmt {
-            Writer::Pretty(f) => {
-                if !self.has_fields {
-                    f.write_char('\n')?;
-                }
-                f.state.indent += 1;
-                entry(Writer::Pretty(f.as_mut()))?;
-                f.write_char(SEP)?;
-                f.write_char('\n')?;
-                f.state.indent -= 1;
-                Ok(())
-            }
-            Writer::Normal(f) => {
-                if self.has_fields {
-                    f.write_char(SEP)?;
-                    f.write_char(' ')?;
-                }
-                entry(Writer::Normal(f))
-            }
-        })();
-        self.has_fields = true;
-        res
+    /// The output of this is a contract, and changing it is a breaking change.
+    /// Unlike the output of [`Satn::to_satn`], it doesn't change as the defaults do,
+    /// and for any given value, it is guaranteed to:
+    /// - be deterministic across platforms, as nothing depends on locale or timezone,
+    /// - format finite floats in their shortest round-trip representation, without an exponent,
+    /// - format non-finite floats as `NaN`, `inf`, and `-inf`,
+    /// - format byte arrays in lowercase hex, e.g., `0x0102`,
+    /// - format special types, e.g., identities and timestamps, as the products they are,
+    /// - label unnamed fields by their index from `0`, and print fields in declaration order,
+    /// - elide nothing, i.e., every element of every array and product is included,
+    /// - be on one line, with elements separated by `, `.
+    pub fn snapshot() -> Self {
+        Self::new().options(SatnOptions::snapshot())
+    }
+
+    /// Returns a builder of output in a canonical form intended for hashing, e.g., to detect changes to rows,
+    /// i.e., with [`SatnOptions::canonical`].
+    ///
+    /// The output of this is a contract, and changing it is a breaking change.
+    /// For any given value, it is guaranteed to:
+    /// - be deterministic across platforms, as nothing depends on locale or timezone,
+    ///   and across Rust versions, as far as the shortest round-trip formatting of floats is,
+    /// - format finite floats in their shortest round-trip representation, without an exponent,
+    /// - format non-finite floats as `NaN`, `inf`, and `-inf`, regardless of the sign or payload of a `NaN`,
+    /// - format byte arrays in lowercase hex, e.g., `0xbeef`,
+    /// - format special types, e.g., identities and timestamps, as the products they are,
+    ///   rather than as hex or dates,
+    /// - elide nothing, i.e., every element of every array and product is included,
+    /// - contain no whitespace other than in strings, e.g., `(a=1,b=[2.5,NaN])`.
+    ///
+    /// The fields of products are in declaration order, so reordering them changes the output.
+    pub fn canonical() -> Self {
+        Self::new().options(SatnOptions::canonical())
+    }
+
+    /// Sets all the options shared with the non-pretty output at once, e.g., to [`SatnOptions::canonical`].
+    pub fn options(mut self, opts: SatnOptions) -> Self {
+        self.opts.satn = opts;
+        self
+    }
+
+    /// Pretty prints the output, setting all its options at once.
+    pub fn pretty_options(mut self, opts: SatnPrettyOptions) -> Self {
+        self.opts = opts;
+        self.pretty(true)
+    }
+
+    /// Sets whether to pretty print the output, like [`Satn::to_satn_pretty`].
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Pretty prints the output, indented by `indent` per nesting level, see [`SatnPrettyOptions::indent`].
+    pub fn indent(mut self, indent: impl Into<Cow<'static, str>>) -> Self {
+        self.opts.indent = indent.into();
+        self.pretty(true)
+    }
+
+    /// Pretty prints the output, keeping what fits within `max_width` columns on one line,
+    /// see [`SatnPrettyOptions::max_line_width`].
+    pub fn max_line_width(mut self, max_width: usize) -> Self {
+        self.opts.max_line_width = Some(max_width);
+        self.pretty(true)
+    }
+
+    /// Pretty prints the output without trailing separators, see [`SatnPrettyOptions::strict`].
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.opts.strict = strict;
+        self.pretty(true)
+    }
+
+    /// Pretty prints the output, separating array elements by `sep`, see [`SatnPrettyOptions::array_sep`].
+    pub fn array_sep(mut self, sep: PrettySep) -> Self {
+        self.opts.array_sep = sep;
+        self.pretty(true)
+    }
+
+    /// See [`SatnOptions::float`].
+    pub fn float(mut self, float: FloatFmt) -> Self {
+        self.opts.satn.float = float;
+        self
+    }
+
+    /// See [`SatnOptions::non_finite`].
+    pub fn non_finite(mut self, non_finite: NonFinite) -> Self {
+        self.opts.satn.non_finite = non_finite;
+        self
+    }
+
+    /// See [`SatnOptions::bytes`].
+    pub fn bytes(mut self, bytes: ByteEncoding) -> Self {
+        self.opts.satn.bytes = bytes;
+        self
+    }
+
+    /// See [`SatnOptions::max_depth`].
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.opts.satn.max_depth = Some(max_depth);
+        self
+    }
+
+    /// See [`SatnOptions::depth_exceeded`].
+    pub fn depth_exceeded(mut self, depth_exceeded: DepthExceeded) -> Self {
+        self.opts.satn.depth_exceeded = depth_exceeded;
+        self
+    }
+
+    /// See [`SatnOptions::max_array_elements`].
+    pub fn max_array_elements(mut self, max_elements: usize) -> Self {
+        self.opts.satn.max_array_elements = Some(max_elements);
+        self
+    }
+
+    /// See [`SatnOptions::max_bytes`].
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.opts.satn.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// See [`SatnOptions::field_order`].
+    ///
+    /// With [`FieldOrder::ByName`], the output is invariant to the declaration order of fields,
+    /// so e.g., a snapshot taken before and after a migration reordering the fields
+    /// of a [`ProductType`] compares equal.
+    /// This requires all fields to be named, so the value should be typed, e.g., a [`ValueWithType`](crate::ValueWithType),
+    /// and [`SatnFmt::try_format`] returns an error if any field is unnamed.
+    pub fn field_order(mut self, field_order: FieldOrder) -> Self {
+        self.opts.satn.field_order = field_order;
+        self
+    }
+
+    /// See [`SatnOptions::variant_case`].
+    pub fn variant_case(mut self, variant_case: VariantCase) -> Self {
+        self.opts.satn.variant_case = variant_case;
+        self
+    }
+
+    /// See [`SatnOptions::annotate_types`], which only applies to [`SatnFmt::format_typed`].
+    pub fn annotate_types(mut self, annotate_types: bool) -> Self {
+        self.opts.satn.annotate_types = annotate_types;
+        self
+    }
+
+    /// See [`SatnOptions::option_fmt`], which only applies to [`SatnFmt::format_typed`].
+    pub fn option_fmt(mut self, option_fmt: OptionFmt) -> Self {
+        self.opts.satn.option_fmt = option_fmt;
+        self
+    }
+
+    /// See [`SatnOptions::time_fmt`], which only applies to [`SatnFmt::format_typed`].
+    pub fn time_fmt(mut self, time_fmt: TimeFmt) -> Self {
+        self.opts.satn.time_fmt = time_fmt;
+        self
+    }
+
+    /// See [`SatnOptions::map_fmt`], which only applies to [`SatnFmt::format_typed`].
+    pub fn map_fmt(mut self, map_fmt: MapFmt) -> Self {
+        self.opts.satn.map_fmt = map_fmt;
+        self
+    }
+
+    /// See [`SatnOptions::index_base`].
+    pub fn index_base(mut self, index_base: IndexBase) -> Self {
+        self.opts.satn.index_base = index_base;
+        self
+    }
+
+    /// See [`SatnOptions::compact`].
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.opts.satn.compact = compact;
+        self
+    }
+
+    /// See [`SatnOptions::color`].
+    ///
+    /// To color only for reading in a terminal, pass [`supports_color()`].
+    pub fn color(mut self, color: bool) -> Self {
+        self.opts.satn.color = color;
+        self
+    }
+
+    /// Returns the options shared with the non-pretty output.
+    pub fn satn_options(&self) -> &SatnOptions {
+        &self.opts.satn
+    }
+
+    /// Formats `value` using the SATN data format into the returned `String`.
+    ///
+    /// This panics if formatting fails,
+    /// e.g., when the value is nested deeper than [`SatnFmt::max_depth`].
+    pub fn format<T: Satn + ?Sized>(&self, value: &T) -> String {
+        let mut out = String::new();
+        self.format_into(value, &mut out);
+        out
+    }
+
+    /// Formats `value` using the SATN data format into the returned `String`,
+    /// returning an error if formatting fails, unlike [`SatnFmt::format`].
+    pub fn try_format<T: Satn + ?Sized>(&self, value: &T) -> Result<String, SatnError> {
+        let mut out = String::new();
+        self.try_format_into(value, &mut out)?;
+        Ok(out)
+    }
+
+    /// Formats `value` using the SATN data format, appending it to `buf`.
+    ///
+    /// Unlike [`SatnFmt::format`], this reuses the allocation of `buf`,
+    /// so formatting many values into the same buffer allocates only as it grows.
+    /// Like it, this panics if formatting fails.
+    pub fn format_into<T: Satn + ?Sized>(&self, value: &T, buf: &mut String) {
+        self.try_format_into(value, buf)
+            .expect("formatting SATN into a `String` should not fail");
+    }
+
+    /// Formats `value` using the SATN data format, appending it to `buf`,
+    /// returning an error if formatting fails, unlike [`SatnFmt::format_into`].
+    pub fn try_format_into<T: Satn + ?Sized>(&self, value: &T, buf: &mut String) -> Result<(), SatnError> {
+        self.write_to(value, buf)
+    }
+
+    /// Formats `value` using the SATN data format into `sink`, e.g., a [`fmt::Formatter`].
+    pub fn write_to<T: Satn + ?Sized>(&self, value: &T, sink: &mut dyn fmt::Write) -> Result<(), SatnError> {
+        let layout = self.pretty.then(|| self.opts.layout());
+        write_limited(sink, self.opts.satn.max_bytes, layout, |f| {
+            value.serialize(SatnFormatter::new(f, &self.opts.satn))
+        })
+    }
+
+    /// Formats `value` of the type `ty` using the SATN data format into the returned `String`.
+    ///
+    /// Unlike [`SatnFmt::format`], this applies the options that need the type of the value as well,
+    /// i.e., [`SatnFmt::annotate_types`], [`SatnFmt::option_fmt`], [`SatnFmt::time_fmt`], and [`SatnFmt::map_fmt`].
+    /// Like it, this panics if formatting fails.
+    pub fn format_typed(&self, ty: WithTypespace<'_, AlgebraicType>, value: &AlgebraicValue) -> String {
+        self.try_format_typed(ty, value)
+            .expect("formatting SATN into a `String` should not fail")
+    }
+
+    /// Formats `value` of the type `ty` using the SATN data format into the returned `String`,
+    /// returning an error if formatting fails, unlike [`SatnFmt::format_typed`].
+    pub fn try_format_typed(
+        &self,
+        ty: WithTypespace<'_, AlgebraicType>,
+        value: &AlgebraicValue,
+    ) -> Result<String, SatnError> {
+        let layout = self.pretty.then(|| self.opts.layout());
+        let value = ty.with_value(value);
+        render_to_string(layout, self.opts.satn.max_bytes, |f| {
+            let fmt = SatnFormatter {
+                ty: Some(ty),
+                ..SatnFormatter::new(f, &self.opts.satn)
+            };
+            ser::Serialize::serialize(&value, fmt)
+        })
+    }
+
+    /// Returns the length in bytes of [`SatnFmt::format`], without allocating the `String`.
+    ///
+    /// Like it, this panics if formatting fails.
+    pub fn formatted_len<T: Satn + ?Sized>(&self, value: &T) -> usize {
+        let layout = self.pretty.then(|| self.opts.layout());
+        let res = count_bytes(layout, self.opts.satn.max_bytes, |f| {
+            value.serialize(SatnFormatter::new(f, &self.opts.satn))
+        });
+        res.expect("formatting SATN for counting should not fail")
     }
 }
 
-/// An implementation of [`fmt::Write`] supporting indented and non-idented formatting.
-enum Writer<'a, 'f> {
-    /// Uses the standard library's formatter i.e. plain formatting.
-    Normal(&'a mut fmt::Formatter<'f>),
-    /// Uses indented formatting.
-    Pretty(IndentedWriter<'a, 'f>),
+/// Options controlling the SATN output.
+///
+/// The options that need the type of the value, i.e., [`annotate_types`](Self::annotate_types),
+/// [`option_fmt`](Self::option_fmt), [`time_fmt`](Self::time_fmt), and [`map_fmt`](Self::map_fmt),
+/// only apply when it's given, e.g., to [`SatnFmt::format_typed`].
+#[derive(Debug, Clone, Default)]
+pub struct SatnOptions {
+    /// How to print finite floats.
+    pub float: FloatFmt,
+    /// How to print floats that are `NaN` or infinite.
+    pub non_finite: NonFinite,
+    /// The index of the first field, used in place of the name of unnamed fields.
+    pub index_base: IndexBase,
+    /// The order in which to print the fields of products.
+    pub field_order: FieldOrder,
+    /// How to print byte arrays.
+    pub bytes: ByteEncoding,
+    /// The maximum nesting of products, sums, and arrays to print, or `None` for no limit.
+    ///
+    /// Set this when formatting untrusted values, as each level of nesting formats recursively,
+    /// so a pathologically nested value could otherwise overflow the stack.
+    pub max_depth: Option<usize>,
+    /// The maximum number of elements to print of each array, or `None` for no limit.
+    ///
+    /// Any further elements are summarized, e.g., as `[1, 2, ... (3 more)]`.
+    /// This is intended for debug output, e.g., of huge arrays in logs,
+    /// so neither [`Satn::to_satn`] nor [`Satn::to_satn_pretty`] truncate by default.
+    pub max_array_elements: Option<usize>,
+    /// What to do with values nested deeper than [`max_depth`](Self::max_depth).
+    ///
+    /// To handle [`DepthExceeded::Error`], use a fallible method, e.g., [`SatnFmt::try_format`].
+    pub depth_exceeded: DepthExceeded,
+    /// A predicate on field names, printing the fields it matches as `<redacted>`, e.g., to mask PII in logs.
+    ///
+    /// This applies to named fields at any depth, and the values of redacted fields are never serialized.
+    pub redact: Option<fn(&str) -> bool>,
+    /// Whether to label each field of a product with the labels of the fields it's nested in, joined by `.`,
+    /// e.g., `(outer = (outer.inner = (outer.inner.id = 1)))`,
+    /// so that fields of the same name in different products can be told apart, e.g., when grepping.
+    ///
+    /// The output is not valid SATN then, so it can't be read back with [`from_satn`].
+    pub field_paths: bool,
+    /// Whether to annotate product elements and sum variants with their types, e.g., `(name: U32 = 5)`.
+    pub annotate_types: bool,
+    /// How to case the names of sum variants, e.g., to print those of generated enums consistently.
+    ///
+    /// The tags of unnamed variants are printed as is.
+    /// As [`from_satn`] matches variant names exactly, names cased otherwise than declared can't be read back.
+    pub variant_case: VariantCase,
+    /// How to print values of option-shaped sums.
+    pub option_fmt: OptionFmt,
+    /// Whether to leave out the whitespace between elements and around `=`, e.g., `(a=1,b=[2,3])`.
+    pub compact: bool,
+    /// The maximum number of bytes to write, or `None` for no limit.
+    ///
+    /// Once reached, formatting stops, even in the middle of a product or string,
+    /// and `... (truncated)` is appended, so the output is at most that long plus the marker.
+    /// This is a last resort against huge values, e.g., in logs,
+    /// for those that [`max_depth`](Self::max_depth) and [`max_array_elements`](Self::max_array_elements) don't cut short.
+    pub max_bytes: Option<usize>,
+    /// How to print [`Timestamp`]s and [`TimeDuration`]s.
+    pub time_fmt: TimeFmt,
+    /// How to print arrays of key-value products.
+    pub map_fmt: MapFmt,
+    /// Whether to color names, strings, numbers, and byte arrays with ANSI escape codes, see [`Role`].
+    ///
+    /// This is only meant for humans reading the output in a terminal, and [`from_satn`] can't read it back.
+    /// See [`supports_color`] for whether the terminal is likely to render the colors.
+    pub color: bool,
 }
 
-impl<'f> Writer<'_, 'f> {
-    /// Provided with a formatter `f`, runs `func` provided with a `Writer`.
-    fn with<R>(f: &mut fmt::Formatter<'_>, func: impl FnOnce(Writer<'_, '_>) -> R) -> R {
-        let mut state;
-        // We use `alternate`, i.e., the `#` flag to let the user trigger pretty printing.
-        let f = if f.alternate() {
-            state = IndentState {
-                indent: 0,
-                on_newline: true,
-            };
-            Writer::Pretty(IndentedWriter { f, state: &mut state })
+impl SatnOptions {
+    /// Returns `role` if tokens are colored, for [`write_painted`].
+    fn paint(&self, role: Role) -> Option<Role> {
+        self.color.then_some(role)
+    }
+
+    /// Returns the options of [`SatnFmt::snapshot`].
+    ///
+    /// Unlike [`SatnOptions::default`], which follows SATN as it evolves,
+    /// these pin every option, so that a new one has to be pinned here too.
+    pub fn snapshot() -> Self {
+        Self {
+            float: FloatFmt::Shortest,
+            non_finite: NonFinite::Bare,
+            index_base: IndexBase::Zero,
+            field_order: FieldOrder::Declared,
+            bytes: ByteEncoding::Hex,
+            max_depth: None,
+            max_array_elements: None,
+            depth_exceeded: DepthExceeded::Error,
+            redact: None,
+            field_paths: false,
+            annotate_types: false,
+            variant_case: VariantCase::AsIs,
+            option_fmt: OptionFmt::Tagged,
+            compact: false,
+            max_bytes: None,
+            time_fmt: TimeFmt::Product,
+            map_fmt: MapFmt::Array,
+            color: false,
+        }
+    }
+
+    /// Returns the options of [`SatnFmt::canonical`].
+    ///
+    /// Like [`SatnOptions::snapshot`], these pin every option.
+    pub fn canonical() -> Self {
+        Self {
+            float: FloatFmt::Shortest,
+            non_finite: NonFinite::Bare,
+            index_base: IndexBase::Zero,
+            field_order: FieldOrder::Declared,
+            bytes: ByteEncoding::Hex,
+            max_depth: None,
+            max_array_elements: None,
+            depth_exceeded: DepthExceeded::Error,
+            redact: None,
+            field_paths: false,
+            annotate_types: false,
+            variant_case: VariantCase::AsIs,
+            option_fmt: OptionFmt::Tagged,
+            compact: true,
+            max_bytes: None,
+            time_fmt: TimeFmt::Product,
+            map_fmt: MapFmt::Array,
+            color: false,
+        }
+    }
+
+    /// Returns whether the field `name` should be printed as `<redacted>`.
+    fn is_redacted(&self, name: Option<&str>) -> bool {
+        name.zip(self.redact).is_some_and(|(name, redact)| redact(name))
+    }
+
+    /// Returns the separator between the elements of products and arrays.
+    fn separator(&self) -> &'static str {
+        if self.compact {
+            ","
         } else {
-            Writer::Normal(f)
-        };
-        func(f)
+            ", "
+        }
     }
 
-    /// Returns a sub-writer without moving `self`.
-    fn as_mut(&mut self) -> Writer<'_, 'f> {
-        match self {
-            Writer::Normal(f) => Writer::Normal(f),
-            Writer::Pretty(f) => Writer::Pretty(f.as_mut()),
+    /// Returns the separator between the key and the value of a map entry, see [`MapFmt::Braces`].
+    fn colon(&self) -> &'static str {
+        if self.compact {
+            ":"
+        } else {
+            ": "
         }
     }
-}
 
-/// A formatter that adds decoration atop of the standard library's formatter.
-struct IndentedWriter<'a, 'f> {
-    f: &'a mut fmt::Formatter<'f>,
-    state: &'a mut IndentState,
+    /// Returns the separator between the name of an element or variant and its value.
+    fn assign(&self) -> &'static str {
+        if self.compact {
+            "="
+        } else {
+            " = "
+        }
+    }
 }
 
-/// The indentation state.
-struct IndentState {
-    /// Number of tab indentations to make.
-    indent: u32,
-    /// Whether we were last on a newline.
-    on_newline: bool,
+/// The kinds of tokens colored by [`SatnOptions::color`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// The names of fields and variants, or their indices if unnamed, in blue.
+    Name,
+    /// String literals, in green.
+    String,
+    /// Integers and floats, in yellow.
+    Number,
+    /// Byte arrays, in magenta.
+    Bytes,
 }
 
-impl<'f> IndentedWriter<'_, 'f> {
-    /// Returns a sub-writer without moving `self`.
-    fn as_mut(&mut self) -> IndentedWriter<'_, 'f> {
-        IndentedWriter {
-            f: self.f,
-            state: self.state,
+impl Role {
+    /// Returns the ANSI escape code starting the color of this role.
+    fn escape(self) -> &'static str {
+        match self {
+            Self::Name => "\x1b[34m",
+            Self::String => "\x1b[32m",
+            Self::Number => "\x1b[33m",
+            Self::Bytes => "\x1b[35m",
         }
     }
 }
 
-impl fmt::Write for IndentedWriter<'_, '_> {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for s in s.split_inclusive('\n') {
-            if self.state.on_newline {
-                // Indent 4 characters times the indentation level.
-                for _ in 0..self.state.indent {
-                    self.f.write_str("    ")?;
-                }
-            }
+/// The ANSI escape code ending the color of a [`Role`].
+const COLOR_RESET: &str = "\x1b[0m";
+
+/// Returns whether the terminal likely renders [`SatnOptions::color`],
+/// i.e., whether both stdout and stderr are terminals and `$NO_COLOR` is unset or empty.
+pub fn supports_color() -> bool {
+    use std::io::IsTerminal as _;
+    io::stdout().is_terminal()
+        && io::stderr().is_terminal()
+        && std::env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
+}
+
+/// Writes the token written by `write` to `f`, colored per `role` unless `None`.
+fn write_painted<W: fmt::Write + ?Sized, E: From<fmt::Error>>(
+    f: &mut W,
+    role: Option<Role>,
+    write: impl FnOnce(&mut W) -> Result<(), E>,
+) -> Result<(), E> {
+    let Some(role) = role else {
+        return write(f);
+    };
+    f.write_str(role.escape())?;
+    write(f)?;
+    Ok(f.write_str(COLOR_RESET)?)
+}
+
+/// What to do with values nested deeper than [`SatnOptions::max_depth`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DepthExceeded {
+    /// Fail formatting.
+    #[default]
+    Error,
+    /// Print the contents of such values as `...`, e.g., `(a = (...), b = [...])`.
+    Elide,
+}
+
+/// How to print byte arrays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ByteEncoding {
+    /// `0x` followed by lowercase hex digits, e.g., `0xbeef`.
+    #[default]
+    Hex,
+    /// `0x` followed by uppercase hex digits, e.g., `0xBEEF`.
+    HexUpper,
+    /// Standard, padded, base64, e.g., `vu8=`.
+    ///
+    /// This is more compact for large blobs, but unlike hex, [`from_satn`] can't read it back.
+    Base64,
+}
+
+/// The order in which to print the fields of products.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FieldOrder {
+    /// In the order they are declared in.
+    #[default]
+    Declared,
+    /// Sorted by name, failing formatting for unnamed fields.
+    ///
+    /// This costs an allocation per field, as each field is rendered to a buffer before sorting.
+    ByName,
+    /// Sorted by name, with any unnamed fields after, in index order, e.g., `(a = 1, b = 2, 0 = 3)`.
+    ///
+    /// Like [`FieldOrder::ByName`], this costs an allocation per field, as each is buffered before sorting.
+    Sorted,
+}
 
-            self.state.on_newline = s.ends_with('\n');
-            self.f.write_str(s)?;
+/// How to case the names of sum variants, see [`SatnOptions::variant_case`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VariantCase {
+    /// As declared, e.g., `Active`.
+    #[default]
+    AsIs,
+    /// In uppercase, e.g., `ACTIVE`.
+    Upper,
+    /// In lowercase, e.g., `active`.
+    Lower,
+}
+
+impl VariantCase {
+    /// Returns `name` in this case.
+    fn apply(self, name: &str) -> Cow<'_, str> {
+        match self {
+            Self::AsIs => name.into(),
+            Self::Upper => name.to_uppercase().into(),
+            Self::Lower => name.to_lowercase().into(),
         }
-        Ok(())
     }
 }
 
-impl fmt::Write for Writer<'_, '_> {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
+/// How to print [`Timestamp`]s and [`TimeDuration`]s, see [`SatnOptions::time_fmt`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeFmt {
+    /// As the products they are, e.g., `(__timestamp_micros_since_unix_epoch__ = 1000000)`.
+    #[default]
+    Product,
+    /// As quoted strings, timestamps in RFC 3339 in UTC, e.g., `"1970-01-01T00:00:01.000000Z"`,
+    /// and time durations in signed seconds, e.g., `"+1.500000"`.
+    ///
+    /// Both have all six digits of microseconds, so [`from_satn`] reads back the exact value.
+    /// Timestamps outside the years 1 to 9999, which RFC 3339 can't express, are still printed as products.
+    Quoted,
+}
+
+/// How to print values of option-shaped sums, i.e., sums of two variants one of which is unit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OptionFmt {
+    /// Like any other sum, e.g., `(some = 5)` or `(none = ())`.
+    #[default]
+    Tagged,
+    /// As just the payload of the other variant if present, e.g., `5`, and as `none` if absent.
+    Bare,
+}
+
+/// How to print arrays whose elements are products of exactly the fields `key` and `value`, in that order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapFmt {
+    /// Like any other array, e.g., `[(key = "a", value = 1)]`.
+    #[default]
+    Array,
+    /// As a map between braces, e.g., `{"a": 1}`, which can't be parsed back.
+    Braces,
+}
+
+/// Whether the indices of unnamed fields start at `0` or `1`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndexBase {
+    /// The first field is `0`, e.g., `(0 = a, 1 = b)`.
+    #[default]
+    Zero,
+    /// The first field is `1`, e.g., `(1 = a, 2 = b)`, as in SQL.
+    One,
+}
+
+impl IndexBase {
+    /// Returns the displayed index of the field at the 0-based `idx`.
+    fn display(self, idx: usize) -> usize {
         match self {
-            Writer::Normal(f) => f.write_str(s),
-            Writer::Pretty(f) => f.write_str(s),
+            Self::Zero => idx,
+            Self::One => idx + 1,
         }
     }
 }
 
+/// How to print finite floating point numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FloatFmt {
+    /// The shortest representation that round-trips, e.g., `12300`, `0.1`, or `1` for `1.0`.
+    ///
+    /// Note that this never uses an exponent,
+    /// so tiny numbers, e.g., subnormals, are printed with hundreds of digits.
+    #[default]
+    Shortest,
+    /// A fixed number of digits after the decimal point, e.g., `0.100` for `Fixed(3)`.
+    ///
+    /// Note that this may not round-trip.
+    Fixed(usize),
+    /// Scientific notation with the shortest mantissa that round-trips, e.g., `1.23e4` or `1e-1`.
+    Scientific,
+}
+
+/// How to print floating point numbers that are `NaN` or infinite.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonFinite {
+    /// As the bare tokens `NaN`, `inf`, and `-inf`.
+    #[default]
+    Bare,
+    /// As the quoted tokens `"NaN"`, `"+inf"`, and `"-inf"`,
+    /// which consumers that only accept finite numbers can still tell apart and read.
+    Quoted,
+}
+
+/// A wrapper around a `T: Satn`
+/// providing `Display` and `Debug` implementations
+/// that uses postgres SATN formatting for `T`.
+pub struct PsqlWrapper<'a, T: ?Sized> {
+    pub ty: PsqlType<'a>,
+    pub value: T,
+}
+
+impl<T: ?Sized> PsqlWrapper<'_, T> {
+    /// Converts `&T` to `&PsqlWrapper<T>`.
+    pub fn from_ref(t: &T) -> &Self {
+        // SAFETY: `repr(transparent)` turns the ABI of `T`
+        // into the same as `Self` so we can also cast `&T` to `&Self`.
+        unsafe { &*(t as *const T as *const Self) }
+    }
+}
+
+impl<T: Satn + ?Sized> fmt::Display for PsqlWrapper<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt_psql(f, &self.ty)
+    }
+}
+
+impl<T: Satn + ?Sized> fmt::Debug for PsqlWrapper<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt_psql(f, &self.ty)
+    }
+}
+
 /// Provides the SATN data format implementing [`Serializer`](ser::Serializer).
 struct SatnFormatter<'a, 'f> {
     /// The sink / writer / output / formatter.
     f: Writer<'a, 'f>,
+    /// The options controlling the output.
+    opts: &'a SatnOptions,
+    /// The number of products, sums, and arrays the value is nested in.
+    depth: usize,
+    /// The type of the value, for the options that need it, see [`SatnFmt::format_typed`].
+    ty: Option<WithTypespace<'a, AlgebraicType>>,
+    /// Whether the value is an entry of an array printed as a map, see [`MapFmt::Braces`].
+    map_entry: bool,
+    /// The names of the fields the value is nested in, joined by `.`, see [`SatnOptions::field_paths`].
+    path: &'a str,
 }
 
-/// An error occurred during serialization to the SATS data format.
-#[derive(From, Into)]
-struct SatnError(fmt::Error);
+/// An error that occurs during serialization to the SATN data format.
+///
+/// This is either a failure to write to the underlying sink,
+/// or a value that can't be formatted with the given options, which comes with a message.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("{}", .message.as_deref().unwrap_or("failed to write SATN"))]
+pub struct SatnError {
+    /// Why formatting failed, or `None` for a write failure.
+    message: Option<Box<str>>,
+}
+
+impl SatnError {
+    /// Returns whether this is a failure to write to the underlying sink.
+    pub fn is_write_error(&self) -> bool {
+        self.message.is_none()
+    }
+
+    /// Returns why the value couldn't be formatted, unless this is a write error.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+}
 
 impl ser::Error for SatnError {
-    fn custom<T: fmt::Display>(_msg: T) -> Self {
-        Self(fmt::Error)
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        let message = Some(msg.to_string().into());
+        Self { message }
+    }
+}
+
+impl From<fmt::Error> for SatnError {
+    fn from(_: fmt::Error) -> Self {
+        Self { message: None }
+    }
+}
+
+impl From<SatnError> for fmt::Error {
+    fn from(_: SatnError) -> Self {
+        fmt::Error
+    }
+}
+
+impl<'a, 'f> SatnFormatter<'a, 'f> {
+    /// Returns a formatter of a value at the top level into `f`, customized by `opts`.
+    fn new(f: Writer<'a, 'f>, opts: &'a SatnOptions) -> Self {
+        Self {
+            f,
+            opts,
+            depth: 0,
+            ty: None,
+            map_entry: false,
+            path: "",
+        }
+    }
+
+    /// Returns a formatter into `f` of a value nested at `depth` under the field `path`,
+    /// e.g., an element of an array or product or the payload of a sum, without a type.
+    fn nested(f: Writer<'a, 'f>, opts: &'a SatnOptions, depth: usize, path: &'a str) -> Self {
+        Self {
+            depth,
+            path,
+            ..Self::new(f, opts)
+        }
     }
 }
 
@@ -246,6 +1135,101 @@ impl SatnFormatter<'_, '_> {
         self.f.write_fmt(args)?;
         Ok(())
     }
+
+    /// Writes the float `v` formatted according to the options.
+    fn write_float(&mut self, v: impl Copy + Into<f64> + fmt::Display + fmt::LowerExp) -> Result<(), SatnError> {
+        self.painted(Role::Number, |this| this.write_float_plain(v))
+    }
+
+    /// Like [`SatnFormatter::write_float`], but never colored.
+    fn write_float_plain(&mut self, v: impl Copy + Into<f64> + fmt::Display + fmt::LowerExp) -> Result<(), SatnError> {
+        let class: f64 = v.into();
+        if !class.is_finite() {
+            return match self.opts.non_finite {
+                NonFinite::Bare => write!(self, "{v}"),
+                NonFinite::Quoted if class.is_nan() => write!(self, "\"NaN\""),
+                NonFinite::Quoted if class.is_sign_negative() => write!(self, "\"-inf\""),
+                NonFinite::Quoted => write!(self, "\"+inf\""),
+            };
+        }
+        match self.opts.float {
+            FloatFmt::Shortest => write!(self, "{v}"),
+            FloatFmt::Fixed(precision) => write!(self, "{v:.precision$}"),
+            FloatFmt::Scientific => write!(self, "{v:e}"),
+        }
+    }
+
+    /// Returns the depth of the contents of a product, sum, or array being entered,
+    /// or `None` if they should be elided as they're nested deeper than [`SatnOptions::max_depth`].
+    fn nest(&self) -> Result<Option<usize>, SatnError> {
+        let depth = self.depth + 1;
+        match self.opts.max_depth {
+            Some(max_depth) if depth > max_depth => match self.opts.depth_exceeded {
+                DepthExceeded::Error => Err(ser::Error::custom(format_args!(
+                    "value is nested deeper than the maximum depth of {max_depth}"
+                ))),
+                DepthExceeded::Elide => Ok(None),
+            },
+            _ => Ok(Some(depth)),
+        }
+    }
+
+    /// Writes the string `v` in quotes,
+    /// escaping `"`, `\`, and control characters with backslash sequences
+    /// so that [`from_satn`] can read it back.
+    fn write_quoted(&mut self, v: &str) -> Result<(), SatnError> {
+        self.painted(Role::String, |this| Ok(write_quoted(&mut this.f, v, '"')?))
+    }
+
+    /// Writes the token written by `write`, colored per `role` if [`SatnOptions::color`].
+    fn painted(&mut self, role: Role, write: impl FnOnce(&mut Self) -> Result<(), SatnError>) -> Result<(), SatnError> {
+        let Some(role) = self.opts.paint(role) else {
+            return write(self);
+        };
+        self.f.write_str(role.escape())?;
+        write(self)?;
+        Ok(self.f.write_str(COLOR_RESET)?)
+    }
+
+    /// Writes the integer `v`, colored as a [`Role::Number`] if [`SatnOptions::color`].
+    fn write_int(&mut self, v: impl fmt::Display) -> Result<(), SatnError> {
+        self.painted(Role::Number, |this| write!(this, "{v}"))
+    }
+}
+
+/// Writes `v` to `f` as a literal in `quote`s, e.g., `"` for a string, escaping `quote` inside it.
+fn write_quoted(f: &mut impl fmt::Write, v: &str, quote: char) -> fmt::Result {
+    f.write_char(quote)?;
+    // The start of the run of characters that need no escaping.
+    let mut run = 0;
+    for (idx, c) in v.char_indices() {
+        if c != quote && c != '\\' && !c.is_control() {
+            continue;
+        }
+        f.write_str(&v[run..idx])?;
+        match c {
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\\' => f.write_str("\\\\")?,
+            c if c == quote => write!(f, "\\{c}")?,
+            _ => write!(f, "\\u{{{:x}}}", u32::from(c))?,
+        }
+        run = idx + c.len_utf8();
+    }
+    f.write_str(&v[run..])?;
+    f.write_char(quote)
+}
+
+/// Writes the `name` of a variant to `f`,
+/// quoted as a string literal unless it's a word [`from_satn`] can read as is, e.g., `"my variant"`.
+fn write_variant_name(f: &mut impl fmt::Write, name: &str) -> fmt::Result {
+    let is_word = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '+' | '.');
+    if !name.is_empty() && name.chars().all(is_word) {
+        f.write_str(name)
+    } else {
+        write_quoted(f, name, '"')
+    }
 }
 
 impl<'a, 'f> ser::Serializer for SatnFormatter<'a, 'f> {
@@ -259,99 +1243,205 @@ impl<'a, 'f> ser::Serializer for SatnFormatter<'a, 'f> {
         write!(self, "{v}")
     }
     fn serialize_u8(mut self, v: u8) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_u16(mut self, v: u16) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_u32(mut self, v: u32) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_u64(mut self, v: u64) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_u128(mut self, v: u128) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_u256(mut self, v: u256) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_i8(mut self, v: i8) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_i16(mut self, v: i16) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_i32(mut self, v: i32) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_i64(mut self, v: i64) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_i128(mut self, v: i128) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_i256(mut self, v: i256) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_int(v)
     }
     fn serialize_f32(mut self, v: f32) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_float(v)
     }
     fn serialize_f64(mut self, v: f64) -> Result<Self::Ok, Self::Error> {
-        write!(self, "{v}")
+        self.write_float(v)
     }
 
     fn serialize_str(mut self, v: &str) -> Result<Self::Ok, Self::Error> {
-        write!(self, "\"{v}\"")
+        self.write_quoted(v)
+    }
+
+    /// Writes `v` in single quotes, e.g., `'a'`, escaped like a string, which [`from_satn`] doesn't read.
+    fn serialize_char(mut self, v: char) -> Result<Self::Ok, Self::Error> {
+        let mut buf = [0; 4];
+        let v = v.encode_utf8(&mut buf);
+        self.painted(Role::String, |this| Ok(write_quoted(&mut this.f, v, '\'')?))
     }
 
     fn serialize_bytes(mut self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        write!(self, "0x{}", hex::encode(v))
+        let encoding = self.opts.bytes;
+        self.painted(Role::Bytes, |this| match encoding {
+            ByteEncoding::Hex => write!(this, "0x{}", hex::encode(v)),
+            ByteEncoding::HexUpper => write!(this, "0x{}", hex::encode_upper(v)),
+            ByteEncoding::Base64 => write!(this, "{}", BASE64_STANDARD.encode(v)),
+        })
     }
 
     fn serialize_array(mut self, _len: usize) -> Result<Self::SerializeArray, Self::Error> {
-        write!(self, "[")?; // Closed via `.end()`.
+        let depth = self.nest()?;
+        let elem_ty = self.ty.map(resolve_ty).and_then(|ty| match ty.ty() {
+            AlgebraicType::Array(array) => Some(ty.with(&*array.elem_ty)),
+            _ => None,
+        });
+        let map = self.opts.map_fmt == MapFmt::Braces && elem_ty.is_some_and(|ty| is_map_entry(resolve_ty(ty).ty()));
+        write!(self, "{}", if map { '{' } else { '[' })?; // Closed via `.end()`.
+        if depth.is_none() {
+            write!(self, "...")?;
+        }
         Ok(ArrayFormatter {
-            f: EntryWrapper::new(self.f),
+            f: EntryWrapper::with_separator(self.f, self.opts.separator()).for_array(),
+            opts: self.opts,
+            depth,
+            len: 0,
+            elem_ty,
+            map,
+            path: self.path,
         })
     }
 
+    fn __serialize_primitive_array(mut self, array: ser::PrimitiveArray<'_>) -> Result<Self::Ok, Self::Error> {
+        // Write the elements in a tight loop, byte-identical to formatting them one by one,
+        // unless they're laid out one by one, when pretty printing, or truncated, elided or colored.
+        let truncated = self.opts.max_array_elements.is_some_and(|max| array.len() > max);
+        if truncated || self.opts.color || matches!(self.f, Writer::Pretty(_)) || self.nest()?.is_none() {
+            return array.serialize_elements(self);
+        }
+        self.f.write_char('[')?;
+        array.write_joined(&mut self.f, self.opts.separator())?;
+        self.f.write_char(']')?;
+        Ok(())
+    }
+
     fn serialize_seq_product(self, len: usize) -> Result<Self::SerializeSeqProduct, Self::Error> {
         // Delegate to named products handling of element formatting.
         self.serialize_named_product(len).map(|inner| SeqFormatter { inner })
     }
 
     fn serialize_named_product(mut self, _len: usize) -> Result<Self::SerializeNamedProduct, Self::Error> {
-        write!(self, "(")?; // Closed via `.end()`.
+        let ty = self.ty.map(resolve_ty).and_then(|ty| match ty.ty() {
+            AlgebraicType::Product(product) => Some(ty.with(product)),
+            _ => None,
+        });
+        let quoted_time = self.opts.time_fmt == TimeFmt::Quoted
+            && ty.is_some_and(|ty| ty.ty().is_timestamp() || ty.ty().is_time_duration());
+        // A map entry is printed as `key: value`, at the depth of the map, and a quoted time as just a string.
+        let depth = if self.map_entry || quoted_time {
+            Some(self.depth)
+        } else {
+            let depth = self.nest()?;
+            write!(self, "(")?; // Closed via `.end()`.
+            if depth.is_none() {
+                write!(self, "...")?;
+            }
+            depth
+        };
         Ok(NamedFormatter {
-            f: EntryWrapper::new(self.f),
+            f: EntryWrapper::with_separator(self.f, self.opts.separator()),
             idx: 0,
+            opts: self.opts,
+            depth,
+            buffered: Vec::new(),
+            ty,
+            map_entry: self.map_entry,
+            quoted_time,
+            path: self.path,
         })
     }
 
     fn serialize_variant<T: ser::Serialize + ?Sized>(
         mut self,
-        _tag: u8,
+        tag: u8,
         name: Option<&str>,
         value: &T,
     ) -> Result<Self::Ok, Self::Error> {
+        let sum = self.ty.map(resolve_ty).and_then(|ty| match ty.ty() {
+            AlgebraicType::Sum(sum) => Some(ty.with(sum)),
+            _ => None,
+        });
+        let variant = sum.and_then(|sum| sum.ty().variants.get(tag as usize));
+        let ty = sum.zip(variant).map(|(sum, var)| sum.with(&var.algebraic_type));
+
+        if self.opts.option_fmt == OptionFmt::Bare && sum.is_some_and(|sum| is_option_shaped(sum.ty())) {
+            // Print the payload of an option-shaped sum without a wrapper, hence at the same depth.
+            if variant.is_some_and(|var| var.is_unit()) {
+                return write!(self, "none");
+            }
+            return value.serialize(SatnFormatter { ty, ..self });
+        }
+
+        let Some(depth) = self.nest()? else {
+            return write!(self, "(...)");
+        };
         write!(self, "(")?;
-        EntryWrapper::<','>::new(self.f.as_mut()).entry(|mut f| {
-            if let Some(name) = name {
-                write!(f, "{name}")?;
+        let mut entries = EntryWrapper::new(self.f.as_mut());
+        entries.entry(|mut f| {
+            // Like unnamed fields, unnamed variants are labeled by their tag.
+            write_painted(&mut f, self.opts.paint(Role::Name), |f| match name {
+                Some(name) => write_variant_name(f, &self.opts.variant_case.apply(name)),
+                None => write!(f, "{tag}"),
+            })?;
+            if let Some(ty) = ty.filter(|_| self.opts.annotate_types) {
+                write!(f, ": {}", fmt_algebraic_type(ty.ty()))?;
             }
-            write!(f, " = ")?;
-            value.serialize(SatnFormatter { f })?;
-            Ok(())
+            write!(f, "{}", self.opts.assign())?;
+            value.serialize(SatnFormatter {
+                ty,
+                ..SatnFormatter::nested(f, self.opts, depth, self.path)
+            })
         })?;
+        entries.end()?;
         write!(self, ")")
     }
+
+    fn serialize_unit(mut self) -> Result<Self::Ok, Self::Error> {
+        write!(self, "()")
+    }
 }
 
 /// Defines the SATN formatting for arrays.
 struct ArrayFormatter<'a, 'f> {
     /// The formatter for each element separating elements by a `,`.
-    f: EntryWrapper<'a, 'f, ','>,
+    f: EntryWrapper<'a, 'f>,
+    /// The options controlling the output.
+    opts: &'a SatnOptions,
+    /// The depth of the elements, or `None` if they're elided.
+    depth: Option<usize>,
+    /// The number of elements serialized so far, including those truncated.
+    len: usize,
+    /// The type of the elements, if they should be annotated.
+    elem_ty: Option<WithTypespace<'a, AlgebraicType>>,
+    /// Whether the elements are printed as map entries, see [`MapFmt::Braces`].
+    map: bool,
+    /// The path of the array, see [`SatnFormatter::path`].
+    path: &'a str,
 }
 
 impl ser::SerializeArray for ArrayFormatter<'_, '_> {
@@ -359,12 +1449,31 @@ impl ser::SerializeArray for ArrayFormatter<'_, '_> {
     type Error = SatnError;
 
     fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, elem: &T) -> Result<(), Self::Error> {
-        self.f.entry(|f| elem.serialize(SatnFormatter { f }).map_err(|e| e.0))?;
+        let Some(depth) = self.depth else {
+            return Ok(());
+        };
+        self.len += 1;
+        if self.opts.max_array_elements.is_some_and(|max| self.len > max) {
+            return Ok(());
+        }
+        let (opts, ty, map_entry, path) = (self.opts, self.elem_ty, self.map, self.path);
+        self.f.entry(|f| {
+            elem.serialize(SatnFormatter {
+                ty,
+                map_entry,
+                ..SatnFormatter::nested(f, opts, depth, path)
+            })
+        })?;
         Ok(())
     }
 
     fn end(mut self) -> Result<Self::Ok, Self::Error> {
-        write!(self.f.fmt, "]")?;
+        if let Some(max) = self.opts.max_array_elements.filter(|&max| self.len > max) {
+            let more = self.len - max;
+            self.f.entry(|mut f| write!(f, "... ({more} more)"))?;
+        }
+        self.f.end()?;
+        write!(self.f.writer(), "{}", if self.map { '}' } else { ']' })?;
         Ok(())
     }
 }
@@ -375,60 +1484,260 @@ struct SeqFormatter<'a, 'f> {
     inner: NamedFormatter<'a, 'f>,
 }
 
-impl ser::SerializeSeqProduct for SeqFormatter<'_, '_> {
-    type Ok = ();
-    type Error = SatnError;
+impl ser::SerializeSeqProduct for SeqFormatter<'_, '_> {
+    type Ok = ();
+    type Error = SatnError;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, elem: &T) -> Result<(), Self::Error> {
+        ser::SerializeNamedProduct::serialize_element(&mut self.inner, None, elem)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        ser::SerializeNamedProduct::end(self.inner)
+    }
+}
+
+/// Provides the data format for named products for SATN.
+struct NamedFormatter<'a, 'f> {
+    /// The formatter for each element separating elements by a `,`.
+    f: EntryWrapper<'a, 'f>,
+    /// The index of the element.
+    idx: usize,
+    /// The options controlling the output.
+    opts: &'a SatnOptions,
+    /// The depth of the elements, or `None` if they're elided.
+    depth: Option<usize>,
+    /// The name and rendering of each element, buffered to sort them unless in [`FieldOrder::Declared`].
+    buffered: Vec<(Option<Box<str>>, String)>,
+    /// The type of the product, if its elements should be annotated.
+    ty: Option<WithTypespace<'a, ProductType>>,
+    /// Whether the product is a map entry, printed as `key: value`, see [`MapFmt::Braces`].
+    map_entry: bool,
+    /// Whether the product is a time, printed as a string, see [`TimeFmt::Quoted`].
+    quoted_time: bool,
+    /// The path of the product, see [`SatnFormatter::path`].
+    path: &'a str,
+}
+
+impl ser::SerializeNamedProduct for NamedFormatter<'_, '_> {
+    type Ok = ();
+    type Error = SatnError;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(
+        &mut self,
+        name: Option<&str>,
+        elem: &T,
+    ) -> Result<(), Self::Error> {
+        let Some(depth) = self.depth else {
+            return Ok(());
+        };
+        if let Some(ty) = self.ty.filter(|_| self.quoted_time) {
+            return write_quoted_time(self.f.writer(), self.opts, ty.ty(), name, &value_serialize(elem));
+        }
+        let idx = self.opts.index_base.display(self.idx);
+        let opts = self.opts;
+        let ty = self.ty.and_then(|ty| {
+            let elem_ty = &ty.ty().elements.get(self.idx)?.algebraic_type;
+            Some(ty.with(elem_ty))
+        });
+        if self.map_entry {
+            let f = self.f.writer();
+            if self.idx > 0 {
+                f.write_str(opts.colon())?;
+            }
+            self.idx += 1;
+            let f = f.as_mut();
+            return elem.serialize(SatnFormatter {
+                ty,
+                ..SatnFormatter::nested(f, opts, depth, self.path)
+            });
+        }
+        // The path of the element, printed as its label, see `SatnOptions::field_paths`.
+        let path = opts.field_paths.then(|| {
+            let field = name.map_or_else(|| idx.to_string(), str::to_owned);
+            match self.path {
+                "" => field,
+                parent => format!("{parent}.{field}"),
+            }
+        });
+        let write_elem = |mut f: Writer<'_, '_>| {
+            let annotation = ty.filter(|_| opts.annotate_types).map(|ty| ty.ty());
+            write_field_label(&mut f, path.as_deref().or(name), idx, annotation, opts)?;
+            if opts.is_redacted(name) {
+                return Ok(write!(f, "<redacted>")?);
+            }
+            elem.serialize(SatnFormatter {
+                ty,
+                ..SatnFormatter::nested(f, opts, depth, path.as_deref().unwrap_or_default())
+            })
+        };
+        if self.opts.field_order != FieldOrder::Declared {
+            if self.opts.field_order == FieldOrder::ByName && name.is_none() {
+                return Err(ser::Error::custom(format_args!(
+                    "cannot sort unnamed field {idx} by name"
+                )));
+            }
+            let rendered = self.f.writer().render_entry(write_elem)?;
+            self.buffered.push((name.map(Into::into), rendered));
+            self.idx += 1;
+            return Ok(());
+        }
+
+        let res = self.f.entry(write_elem);
+        self.idx += 1;
+        res?;
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        if self.map_entry || self.quoted_time {
+            return Ok(());
+        }
+        // Named elements go first, sorted by name,
+        // and then unnamed ones, which the stable sort keeps in index order.
+        self.buffered
+            .sort_by(|(a, _), (b, _)| (a.is_none(), a).cmp(&(b.is_none(), b)));
+        for (_, rendered) in &self.buffered {
+            self.f.entry(|mut f| f.write_str(rendered))?;
+        }
+        self.f.end()?;
+        write!(self.f.writer(), ")")?;
+        Ok(())
+    }
+}
+
+/// Writes the label of a product element, i.e., its `name`, or its displayed `idx` if unnamed,
+/// then its `ty` if annotated, and then `assign`, e.g., ` = `.
+fn write_field_label(
+    f: &mut Writer<'_, '_>,
+    name: Option<&str>,
+    idx: usize,
+    ty: Option<&AlgebraicType>,
+    opts: &SatnOptions,
+) -> fmt::Result {
+    write_painted(f, opts.paint(Role::Name), |f| match name {
+        Some(name) => write!(f, "{name}"),
+        None => write!(f, "{idx}"),
+    })?;
+    if let Some(ty) = ty {
+        write!(f, ": {}", fmt_algebraic_type(ty))?;
+    }
+    f.write_str(opts.assign())
+}
+
+/// Returns whether `sum` is option-shaped, i.e., has two variants, and exactly one of them is unit.
+fn is_option_shaped(sum: &SumType) -> bool {
+    matches!(&*sum.variants, [a, b] if a.is_unit() != b.is_unit())
+}
+
+/// Returns whether `ty` is the product of a map entry, i.e., of exactly the fields `key` and `value`, in that order.
+fn is_map_entry(ty: &AlgebraicType) -> bool {
+    matches!(ty, AlgebraicType::Product(product) if matches!(
+        &*product.elements,
+        [key, value] if key.name() == Some("key") && value.name() == Some("value")
+    ))
+}
 
-    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, elem: &T) -> Result<(), Self::Error> {
-        ser::SerializeNamedProduct::serialize_element(&mut self.inner, None, elem)
+/// Returns `ty` with any [`AlgebraicType::Ref`]s resolved in its typespace.
+fn resolve_ty(mut ty: WithTypespace<'_, AlgebraicType>) -> WithTypespace<'_, AlgebraicType> {
+    while let AlgebraicType::Ref(r) = ty.ty() {
+        ty = ty.resolve(*r);
     }
+    ty
+}
 
-    fn end(self) -> Result<Self::Ok, Self::Error> {
-        ser::SerializeNamedProduct::end(self.inner)
-    }
+/// Renders the output of `func` into a `String`,
+/// pretty printed per `layout` if provided.
+fn render_to_string(
+    layout: Option<Layout<'_>>,
+    max_bytes: Option<usize>,
+    func: impl FnMut(Writer<'_, '_>) -> Result<(), SatnError>,
+) -> Result<String, SatnError> {
+    let mut out = String::new();
+    write_limited(&mut out, max_bytes, layout, func)?;
+    Ok(out)
 }
 
-/// Provides the data format for named products for SATN.
-struct NamedFormatter<'a, 'f> {
-    /// The formatter for each element separating elements by a `,`.
-    f: EntryWrapper<'a, 'f, ','>,
-    /// The index of the element.
-    idx: usize,
+/// The marker appended to output cut off at [`SatnOptions::max_bytes`].
+const TRUNCATED_MARKER: &str = "... (truncated)";
+
+/// Writes the output of `func` into `sink`, pretty printed per `layout` if provided,
+/// cutting it off at `max_bytes`, if any, and then appending [`TRUNCATED_MARKER`].
+fn write_limited(
+    sink: &mut dyn fmt::Write,
+    max_bytes: Option<usize>,
+    layout: Option<Layout<'_>>,
+    func: impl FnMut(Writer<'_, '_>) -> Result<(), SatnError>,
+) -> Result<(), SatnError> {
+    let Some(remaining) = max_bytes else {
+        return Writer::with_sink(sink, layout, func);
+    };
+    let mut limit = ByteLimit {
+        f: sink,
+        remaining,
+        exceeded: false,
+    };
+    match Writer::with_sink(&mut limit, layout, func) {
+        // The write that went over the limit failed to stop formatting, so the error is just that.
+        Err(_) if limit.exceeded => Ok(limit.f.write_str(TRUNCATED_MARKER)?),
+        res => res,
+    }
 }
 
-impl ser::SerializeNamedProduct for NamedFormatter<'_, '_> {
-    type Ok = ();
-    type Error = SatnError;
+/// A writer passing at most `remaining` bytes on to `f`, failing once more are written.
+struct ByteLimit<'a> {
+    f: &'a mut dyn fmt::Write,
+    /// The number of bytes that can still be written.
+    remaining: usize,
+    /// Whether more than the limit was written.
+    exceeded: bool,
+}
 
-    fn serialize_element<T: ser::Serialize + ?Sized>(
-        &mut self,
-        name: Option<&str>,
-        elem: &T,
-    ) -> Result<(), Self::Error> {
-        let res = self.f.entry(|mut f| {
-            // Format the name or use the index if unnamed.
-            if let Some(name) = name {
-                write!(f, "{name}")?;
-            } else {
-                write!(f, "{}", self.idx)?;
-            }
-            write!(f, " = ")?;
-            elem.serialize(SatnFormatter { f })?;
-            Ok(())
-        });
-        self.idx += 1;
-        res?;
-        Ok(())
+impl fmt::Write for ByteLimit<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if let Some(remaining) = self.remaining.checked_sub(s.len()) {
+            self.remaining = remaining;
+            return self.f.write_str(s);
+        }
+        // Write as much as fits, without splitting a character.
+        let mut end = self.remaining;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.f.write_str(&s[..end])?;
+        self.remaining = 0;
+        self.exceeded = true;
+        Err(fmt::Error)
     }
+}
 
-    fn end(mut self) -> Result<Self::Ok, Self::Error> {
-        write!(self.f.fmt, ")")?;
+/// Returns the length in bytes of the output of `func`,
+/// pretty printed per `layout` if provided and cut off at `max_bytes`, discarding the output itself.
+fn count_bytes(
+    layout: Option<Layout<'_>>,
+    max_bytes: Option<usize>,
+    func: impl FnMut(Writer<'_, '_>) -> Result<(), SatnError>,
+) -> Result<usize, SatnError> {
+    let mut counter = ByteCounter { len: 0 };
+    write_limited(&mut counter, max_bytes, layout, func)?;
+    Ok(counter.len)
+}
+
+/// A writer that only counts the bytes written into it.
+struct ByteCounter {
+    /// The number of bytes written so far.
+    len: usize,
+}
+
+impl fmt::Write for ByteCounter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.len += s.len();
         Ok(())
     }
 }
 
-struct PsqlEntryWrapper<'a, 'f, const SEP: char> {
-    entry: EntryWrapper<'a, 'f, SEP>,
+struct PsqlEntryWrapper<'a, 'f> {
+    entry: EntryWrapper<'a, 'f>,
     /// The index of the element.
     idx: usize,
     ty: &'a PsqlType<'a>,
@@ -436,25 +1745,31 @@ struct PsqlEntryWrapper<'a, 'f, const SEP: char> {
 
 /// Provides the data format for named products for `SQL`.
 struct PsqlNamedFormatter<'a, 'f> {
-    /// The formatter for each element separating elements by a `,`.
-    f: PsqlEntryWrapper<'a, 'f, ','>,
+    /// The formatter for each element separating elements by [`PsqlDelimiters::separator`].
+    f: PsqlEntryWrapper<'a, 'f>,
     /// If is not [Self::is_special] to control if we start with `(`
     start: bool,
     /// Remember what format we are using
     use_fmt: PsqlPrintFmt,
+    /// The depth of the elements, or `None` if they're elided.
+    depth: Option<usize>,
+    /// Whether to print the only element without delimiters or name, see [`PsqlOptions::bare_single_field`].
+    bare: bool,
 }
 
 impl<'a, 'f> PsqlNamedFormatter<'a, 'f> {
-    pub fn new(ty: &'a PsqlType<'a>, f: Writer<'a, 'f>) -> Self {
+    pub fn new(ty: &'a PsqlType<'a>, f: Writer<'a, 'f>, depth: Option<usize>) -> Self {
         Self {
+            depth,
             start: true,
             f: PsqlEntryWrapper {
-                entry: EntryWrapper::new(f),
+                entry: EntryWrapper::with_separator(f, &ty.opts.delimiters.separator),
                 idx: 0,
                 ty,
             },
             // Will set later
             use_fmt: PsqlPrintFmt::Satn,
+            bare: false,
         }
     }
 }
@@ -468,43 +1783,51 @@ impl ser::SerializeNamedProduct for PsqlNamedFormatter<'_, '_> {
         name: Option<&str>,
         elem: &T,
     ) -> Result<(), Self::Error> {
+        let Some(depth) = self.depth else {
+            return Ok(());
+        };
         // For binary data & special types, output in `hex` format and skip the tagging of each value
         // We need to check for both the  enclosing(`self.f.ty`) type and the inner element(`name`) type.
         self.use_fmt = self.f.ty.use_fmt(name);
         let res = self.f.entry.entry(|mut f| {
-            let PsqlType { tuple, field, idx } = self.f.ty;
-            if !self.use_fmt.is_special() {
+            let PsqlType { tuple, field, idx, .. } = self.f.ty;
+            if !self.use_fmt.is_special() && !self.bare {
+                let delimiters = &self.f.ty.opts.delimiters;
                 if self.start {
-                    write!(f, "(")?;
+                    write!(f, "{}", delimiters.open)?;
                     self.start = false;
                 }
                 // Format the name or use the index if unnamed.
                 if let Some(name) = name {
                     write!(f, "{name}")?;
                 } else {
-                    write!(f, "{idx}")?;
+                    write!(f, "{}", self.f.ty.opts.satn.index_base.display(self.f.idx))?;
                 }
-                write!(f, " = ")?;
+                write!(f, "{}", delimiters.assign)?;
             }
             //Is a nested product type?
+            if self.f.ty.opts.satn.is_redacted(name) {
+                return Ok(write!(f, "<redacted>")?);
+            }
             let (tuple, field, idx) = if let Some(product) = field.algebraic_type.as_product() {
                 (product, &product.elements[self.f.idx], self.f.idx)
             } else {
                 (*tuple, *field, *idx)
             };
 
-            elem.serialize(PsqlFormatter {
-                fmt: SatnFormatter { f },
-                ty: &PsqlType { tuple, field, idx },
-            })?;
-
-            Ok(())
+            let fmt = SatnFormatter::nested(f, &self.f.ty.opts.satn, depth, "");
+            let ty = PsqlType {
+                tuple,
+                field,
+                idx,
+                opts: self.f.ty.opts,
+            };
+            serialize_psql(elem, fmt, &ty)
         });
 
-        // Advance to the next field.
-        if !self.use_fmt.is_special() {
-            self.f.idx += 1;
-        }
+        // Advance to the next field, even a special one,
+        // so that `self.f.idx` stays the position of the element in the product.
+        self.f.idx += 1;
 
         res?;
 
@@ -512,8 +1835,9 @@ impl ser::SerializeNamedProduct for PsqlNamedFormatter<'_, '_> {
     }
 
     fn end(mut self) -> Result<Self::Ok, Self::Error> {
-        if !self.use_fmt.is_special() {
-            write!(self.f.entry.fmt, ")")?;
+        if !self.use_fmt.is_special() && !self.bare {
+            self.f.entry.end()?;
+            write!(self.f.entry.writer(), "{}", self.f.ty.opts.delimiters.close)?;
         }
         Ok(())
     }
@@ -549,6 +1873,8 @@ pub enum PsqlPrintFmt {
     Duration,
     /// Print as `Satn` format
     Satn,
+    /// Print with the [`PsqlCustomType`] at this index in [`PsqlOptions::custom_types`]
+    Custom(usize),
 }
 
 impl PsqlPrintFmt {
@@ -566,6 +1892,122 @@ pub struct PsqlType<'a> {
     pub field: &'a ProductTypeElement,
     /// The index of the field in the tuple/struct
     pub idx: usize,
+    /// The options controlling the output
+    pub opts: &'a PsqlOptions,
+}
+
+/// Options controlling the `SQL` output.
+#[derive(Debug, Clone, Default)]
+pub struct PsqlOptions {
+    /// Whether to print 16-byte special values, i.e., [`ConnectionId`]s,
+    /// as a canonical UUID (`xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`) instead of `hex`.
+    ///
+    /// Off by default, as not every 16-byte value is a UUID.
+    ///
+    /// [`ConnectionId`]: ProductType::is_connection_id
+    pub uuid: bool,
+    /// The options for the SATN output of non-special values, e.g., floats.
+    pub satn: SatnOptions,
+    /// The delimiters of products.
+    pub delimiters: PsqlDelimiters,
+    /// How to print the hex of [`Identity`]s and [`ConnectionId`]s not printed as a UUID,
+    /// or `None`, the default, to print them like other bytes, per [`SatnOptions::bytes`].
+    ///
+    /// [`Identity`]: ProductType::is_identity
+    /// [`ConnectionId`]: ProductType::is_connection_id
+    pub hex_style: Option<HexStyle>,
+    /// The `chrono` format string for [`Timestamp`]s, e.g., `"%Y-%m-%d %H:%M:%S%.6f%:z"`,
+    /// or `None`, the default, to print them in RFC 3339.
+    ///
+    /// Timestamps are formatted in UTC, including those before the Unix epoch.
+    /// This does not apply to [`TimeDuration`]s, which aren't points in time.
+    pub timestamp_format: Option<Cow<'static, str>>,
+    /// Whether to print a value that is a product of a single field as just the value of that field,
+    /// e.g., `5` rather than `(0 = 5)`, like psql does for a single column.
+    ///
+    /// This only applies to the value itself, not to the products nested in it.
+    pub bare_single_field: bool,
+    /// The names of the fields whose unsigned integers, up to `u64`, are printed in hex, e.g., `0x1f`,
+    /// as is more readable for flags and masks. Other integers are printed in decimal.
+    ///
+    /// A field is looked up by the name of the [`PsqlType::field`] being printed, e.g., the column of a row.
+    pub hex_fields: Vec<Cow<'static, str>>,
+    /// User-defined special types, rendered by their own closure rather than as a product.
+    ///
+    /// The first type detecting a product wins,
+    /// but the built-in special types, e.g., [`Identity`], always take precedence.
+    ///
+    /// [`Identity`]: ProductType::is_identity
+    pub custom_types: Vec<PsqlCustomType>,
+}
+
+/// The detector of a [`PsqlCustomType`].
+type DetectFn = dyn Fn(&ProductType) -> bool + Send + Sync;
+
+/// The renderer of a [`PsqlCustomType`].
+type RenderFn = dyn Fn(&mut dyn fmt::Write, &ProductType, &ProductValue) -> fmt::Result + Send + Sync;
+
+/// A user-defined special type in the `SQL` output, see [`PsqlOptions::custom_types`].
+#[derive(Clone)]
+pub struct PsqlCustomType {
+    /// Returns whether a product type is this special type.
+    detect: Arc<DetectFn>,
+    /// Writes a value of this special type, given the type.
+    render: Arc<RenderFn>,
+}
+
+impl PsqlCustomType {
+    /// Returns a special type for the product types `detect` returns `true` for,
+    /// whose values are written by `render`, e.g., `12.50 EUR` for a currency wrapper.
+    pub fn new(
+        detect: impl Fn(&ProductType) -> bool + Send + Sync + 'static,
+        render: impl Fn(&mut dyn fmt::Write, &ProductType, &ProductValue) -> fmt::Result + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            detect: Arc::new(detect),
+            render: Arc::new(render),
+        }
+    }
+}
+
+impl fmt::Debug for PsqlCustomType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PsqlCustomType").finish_non_exhaustive()
+    }
+}
+
+/// How to print the hex of special values in the `SQL` output, see [`PsqlOptions::hex_style`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexStyle {
+    /// Whether to prefix the hex with `0x`.
+    pub prefix: bool,
+    /// Whether to use uppercase hex digits.
+    pub uppercase: bool,
+}
+
+/// The delimiters of products in the `SQL` output,
+/// e.g., to mimic the Postgres rendering of composite types with `,` and `=`.
+#[derive(Debug, Clone)]
+pub struct PsqlDelimiters {
+    /// Opens a product, `(` by default.
+    pub open: Cow<'static, str>,
+    /// Closes a product, `)` by default.
+    pub close: Cow<'static, str>,
+    /// Separates the elements of a product, `, ` by default.
+    pub separator: Cow<'static, str>,
+    /// Separates the name of an element from its value, ` = ` by default.
+    pub assign: Cow<'static, str>,
+}
+
+impl Default for PsqlDelimiters {
+    fn default() -> Self {
+        Self {
+            open: Cow::Borrowed("("),
+            close: Cow::Borrowed(")"),
+            separator: Cow::Borrowed(", "),
+            assign: Cow::Borrowed(" = "),
+        }
+    }
 }
 
 impl PsqlType<'_> {
@@ -573,40 +2015,121 @@ impl PsqlType<'_> {
     ///
     /// Is required to check both the enclosing type and the inner element type
     fn use_fmt(&self, name: Option<&str>) -> PsqlPrintFmt {
-        if self.tuple.is_identity()
-            || self.tuple.is_connection_id()
-            || self.field.algebraic_type.is_identity()
-            || self.field.algebraic_type.is_connection_id()
-            || name.map(ProductType::is_identity_tag).unwrap_or_default()
-            || name.map(ProductType::is_connection_id_tag).unwrap_or_default()
-        {
-            return PsqlPrintFmt::Hex;
-        };
+        let special = self
+            .tuple
+            .special_type()
+            .or_else(|| self.field.algebraic_type.as_product()?.special_type())
+            .or_else(|| name.and_then(ProductType::special_tag));
+        match special {
+            Some(SpecialType::Identity | SpecialType::ConnectionId) => PsqlPrintFmt::Hex,
+            Some(SpecialType::Timestamp) => PsqlPrintFmt::Timestamp,
+            Some(SpecialType::TimeDuration) => PsqlPrintFmt::Duration,
+            None => self.custom_fmt().unwrap_or(PsqlPrintFmt::Satn),
+        }
+    }
 
-        if self.tuple.is_timestamp()
-            || self.field.algebraic_type.is_timestamp()
-            || name.map(ProductType::is_timestamp_tag).unwrap_or_default()
-        {
-            return PsqlPrintFmt::Timestamp;
-        };
+    /// Returns whether the current field is one of [`PsqlOptions::hex_fields`].
+    fn is_hex_field(&self) -> bool {
+        let hex_fields = &self.opts.hex_fields;
+        (self.field.name()).is_some_and(|name| hex_fields.iter().any(|field| field == name))
+    }
 
-        if self.tuple.is_time_duration()
-            || self.field.algebraic_type.is_time_duration()
-            || name.map(ProductType::is_time_duration_tag).unwrap_or_default()
-        {
-            return PsqlPrintFmt::Duration;
-        };
+    /// Returns the custom special type of the current field, if any.
+    ///
+    /// Unlike the built-in ones, this doesn't consider the enclosing type,
+    /// as a custom type is rendered as a whole, and never looked into.
+    fn custom_fmt(&self) -> Option<PsqlPrintFmt> {
+        let product = self.field.algebraic_type.as_product()?;
+        let idx = self
+            .opts
+            .custom_types
+            .iter()
+            .position(|custom| (custom.detect)(product))?;
+        Some(PsqlPrintFmt::Custom(idx))
+    }
+}
+
+/// Serializes `value`, of the type of `ty.field`, in the `SQL` format into `fmt`,
+/// dispatching to the renderer of its [`PsqlCustomType`], if any.
+fn serialize_psql<'a, 'f, T: ser::Serialize + ?Sized>(
+    value: &T,
+    mut fmt: SatnFormatter<'a, 'f>,
+    ty: &'a PsqlType<'a>,
+) -> Result<(), SatnError> {
+    if let (PsqlPrintFmt::Custom(idx), Some(product)) = (ty.use_fmt(None), ty.field.algebraic_type.as_product()) {
+        if let Ok(value) = value_serialize(value).into_product() {
+            return Ok((ty.opts.custom_types[idx].render)(&mut fmt.f, product, &value)?);
+        }
+    }
+    value.serialize(PsqlFormatter { fmt, ty })
+}
 
-        PsqlPrintFmt::Satn
+/// The timestamps RFC 3339 can express, from the year 1 to 9999, in microseconds since the Unix epoch.
+const RFC3339_MICROS: core::ops::RangeInclusive<i64> = -62_135_596_800_000_000..=253_402_300_799_999_999;
+
+/// Writes `value`, the field `name` of a timestamp or time duration of type `ty`, into `f`
+/// as a string, see [`TimeFmt::Quoted`].
+fn write_quoted_time(
+    f: &mut Writer<'_, '_>,
+    opts: &SatnOptions,
+    ty: &ProductType,
+    name: Option<&str>,
+    value: &AlgebraicValue,
+) -> Result<(), SatnError> {
+    let &AlgebraicValue::I64(micros) = value else {
+        return Err(ser::Error::custom("expected the microseconds of a time to be an `i64`"));
+    };
+    let text = if ty.is_timestamp() {
+        (RFC3339_MICROS.contains(&micros))
+            .then(|| DateTime::from_timestamp_micros(micros))
+            .flatten()
+            .map(|time| time.to_rfc3339_opts(SecondsFormat::Micros, true))
+    } else {
+        Some(fmt_time_duration(micros))
+    };
+    match text {
+        Some(text) => write_painted(f, opts.paint(Role::String), |f| Ok(write!(f, "\"{text}\"")?)),
+        // Printed as the product it is, which `from_satn` reads too.
+        None => Ok(write!(f, "({}{}{micros})", name.unwrap_or("0"), opts.assign())?),
     }
 }
 
+/// Returns the time duration of `micros` in signed seconds, e.g., `+1.500000`, as [`TimeDuration`] displays it.
+///
+/// Unlike that, this supports the shortest duration, `i64::MIN` microseconds.
+fn fmt_time_duration(micros: i64) -> String {
+    let sign = if micros < 0 { "-" } else { "+" };
+    let abs = micros.unsigned_abs();
+    format!("{sign}{}.{:06}", abs / 1_000_000, abs % 1_000_000)
+}
+
+/// Writes the timestamp of `micros` since the Unix epoch into `f`, in UTC, per the `chrono` `format` string.
+fn write_timestamp(f: &mut SatnFormatter<'_, '_>, micros: i64, format: &str) -> Result<(), SatnError> {
+    let items = StrftimeItems::new(format);
+    if items.clone().any(|item| item == Item::Error) {
+        return Err(ser::Error::custom(format_args!("invalid timestamp format `{format}`")));
+    }
+    let Some(time) = DateTime::from_timestamp_micros(micros) else {
+        return Err(ser::Error::custom(format_args!(
+            "timestamp of {micros} microseconds since the Unix epoch is out of range"
+        )));
+    };
+    write!(f, "{}", time.format_with_items(items))
+}
+
 /// An implementation of [`Serializer`](ser::Serializer) for `SQL` output.
 struct PsqlFormatter<'a, 'f> {
     fmt: SatnFormatter<'a, 'f>,
     ty: &'a PsqlType<'a>,
 }
 
+impl PsqlFormatter<'_, '_> {
+    /// Writes the integer `v` of one of [`PsqlOptions::hex_fields`] as `0x` and its lowercase hex.
+    fn write_hex(mut self, v: impl fmt::LowerHex) -> Result<(), SatnError> {
+        self.fmt.painted(Role::Number, |f| write!(f, "{v:#x}"))
+    }
+}
+
 impl<'a, 'f> ser::Serializer for PsqlFormatter<'a, 'f> {
     type Ok = ();
     type Error = SatnError;
@@ -618,15 +2141,27 @@ impl<'a, 'f> ser::Serializer for PsqlFormatter<'a, 'f> {
         self.fmt.serialize_bool(v)
     }
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        if self.ty.is_hex_field() {
+            return self.write_hex(v);
+        }
         self.fmt.serialize_u8(v)
     }
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        if self.ty.is_hex_field() {
+            return self.write_hex(v);
+        }
         self.fmt.serialize_u16(v)
     }
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        if self.ty.is_hex_field() {
+            return self.write_hex(v);
+        }
         self.fmt.serialize_u32(v)
     }
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        if self.ty.is_hex_field() {
+            return self.write_hex(v);
+        }
         self.fmt.serialize_u64(v)
     }
     fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
@@ -656,10 +2191,13 @@ impl<'a, 'f> ser::Serializer for PsqlFormatter<'a, 'f> {
                 write!(self.fmt, "{}", TimeDuration::from_micros(v))?;
                 Ok(())
             }
-            PsqlPrintFmt::Timestamp => {
-                write!(self.fmt, "{}", Timestamp::from_micros_since_unix_epoch(v))?;
-                Ok(())
-            }
+            PsqlPrintFmt::Timestamp => match &self.ty.opts.timestamp_format {
+                Some(format) => write_timestamp(&mut self.fmt, v, format),
+                None => {
+                    write!(self.fmt, "{}", Timestamp::from_micros_since_unix_epoch(v))?;
+                    Ok(())
+                }
+            },
             _ => self.fmt.serialize_i64(v),
         }
     }
@@ -680,8 +2218,38 @@ impl<'a, 'f> ser::Serializer for PsqlFormatter<'a, 'f> {
         self.fmt.serialize_str(v)
     }
 
-    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        self.fmt.serialize_bytes(v)
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        self.fmt.serialize_char(v)
+    }
+
+    fn serialize_bytes(mut self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        match (self.ty.use_fmt(None), self.ty.opts.hex_style) {
+            (PsqlPrintFmt::Hex, _) if self.ty.opts.uuid && v.len() == 16 => {
+                let (a, rest) = v.split_at(4);
+                let (b, rest) = rest.split_at(2);
+                let (c, rest) = rest.split_at(2);
+                let (d, e) = rest.split_at(2);
+                write!(
+                    self.fmt,
+                    "{}-{}-{}-{}-{}",
+                    hex::encode(a),
+                    hex::encode(b),
+                    hex::encode(c),
+                    hex::encode(d),
+                    hex::encode(e)
+                )
+            }
+            (PsqlPrintFmt::Hex, Some(HexStyle { prefix, uppercase })) => {
+                let prefix = if prefix { "0x" } else { "" };
+                let hex = if uppercase {
+                    hex::encode_upper(v)
+                } else {
+                    hex::encode(v)
+                };
+                write!(self.fmt, "{prefix}{hex}")
+            }
+            _ => self.fmt.serialize_bytes(v),
+        }
     }
 
     fn serialize_array(self, len: usize) -> Result<Self::SerializeArray, Self::Error> {
@@ -694,8 +2262,17 @@ impl<'a, 'f> ser::Serializer for PsqlFormatter<'a, 'f> {
         })
     }
 
-    fn serialize_named_product(self, _len: usize) -> Result<Self::SerializeNamedProduct, Self::Error> {
-        Ok(PsqlNamedFormatter::new(self.ty, self.fmt.f))
+    fn serialize_named_product(mut self, len: usize) -> Result<Self::SerializeNamedProduct, Self::Error> {
+        // Only the value itself is at depth 0, not any products nested in it.
+        let top_level = self.fmt.depth == 0;
+        let depth = self.fmt.nest()?;
+        if depth.is_none() {
+            // Closed via `.end()`.
+            write!(self.fmt, "{}...", self.ty.opts.delimiters.open)?;
+        }
+        let mut fmt = PsqlNamedFormatter::new(self.ty, self.fmt.f, depth);
+        fmt.bare = self.ty.opts.bare_single_field && top_level && len == 1 && depth.is_some();
+        Ok(fmt)
     }
 
     fn serialize_variant<T: ser::Serialize + ?Sized>(
@@ -737,3 +2314,1574 @@ impl<'a, 'f> ser::Serializer for PsqlFormatter<'a, 'f> {
         unsafe { self.fmt.serialize_str_in_chunks(total_len, string) }
     }
 }
+
+/// Writes `value` in the SATN data format into `writer`.
+///
+/// Unlike [`Satn::to_satn`], this streams the output
+/// rather than buffering all of it into a `String`,
+/// so for a large value, `writer` should be buffered, e.g., by a [`BufWriter`](io::BufWriter).
+pub fn write_satn<W: io::Write>(value: &(impl Satn + ?Sized), writer: W) -> io::Result<()> {
+    write_satn_with(value, writer, &SatnOptions::default())
+}
+
+/// Writes `value` in the SATN data format into `writer`, customized by `opts`.
+///
+/// A failure to format the value, e.g., as it's nested deeper than [`SatnOptions::max_depth`],
+/// is returned as an [`io::ErrorKind::InvalidInput`] error wrapping the [`SatnError`].
+pub fn write_satn_with<W: io::Write>(value: &(impl Satn + ?Sized), writer: W, opts: &SatnOptions) -> io::Result<()> {
+    let mut sink = IoWriter { writer, error: None };
+    let res = Writer::with_sink(&mut sink, None, |f| value.serialize(SatnFormatter::new(f, opts)));
+    match (res, sink.error) {
+        (Ok(()), _) => Ok(()),
+        (Err(_), Some(err)) => Err(err),
+        (Err(err), None) => Err(io::Error::new(io::ErrorKind::InvalidInput, err)),
+    }
+}
+
+/// Adapts an [`io::Write`] to [`fmt::Write`],
+/// keeping the I/O error, which `fmt::Error` can't carry.
+struct IoWriter<W> {
+    writer: W,
+    /// The first I/O error that occurred, if any.
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.writer.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
+
+/// An error that occurs when merging a patch into a value with [`satn_merge`].
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeError {
+    /// The base value is not a product with as many fields as the type.
+    #[error("the base is not a product with {0} fields")]
+    InvalidBase(usize),
+    /// The patch is not a product with as many fields as the type.
+    #[error("the patch is not a product with {0} fields")]
+    InvalidPatch(usize),
+    /// The field at `idx` of the patch is not an `option`.
+    #[error("field {idx} of the patch is not an option")]
+    NotAnOption { idx: usize },
+}
+
+/// Overlays `patch` onto the product `base`, of type `ty`, returning the merged product.
+///
+/// The `patch` has a field for each field of `ty`, each being an `option`.
+/// For a field that is `none`, the field of `base` is kept,
+/// whereas for a field that is `some(value)`, the field of `base` is replaced by `value`.
+///
+/// The replacement is wholesale, whatever the type of the field.
+/// That is, an array field is replaced by the array of the patch rather than merged element-wise,
+/// a sum field takes the variant and payload of the patch even when both have the same variant,
+/// and a product field is replaced as a whole.
+/// The `value`s of the patch are not checked against the types of the fields.
+pub fn satn_merge(
+    base: &AlgebraicValue,
+    patch: &AlgebraicValue,
+    ty: &ProductType,
+) -> Result<AlgebraicValue, MergeError> {
+    let arity = ty.elements.len();
+    let base = base
+        .as_product()
+        .filter(|base| base.elements.len() == arity)
+        .ok_or(MergeError::InvalidBase(arity))?;
+    let patch = patch
+        .as_product()
+        .filter(|patch| patch.elements.len() == arity)
+        .ok_or(MergeError::InvalidPatch(arity))?;
+
+    let elements = base
+        .elements
+        .iter()
+        .zip(&*patch.elements)
+        .enumerate()
+        .map(|(idx, (base, patch))| match patch {
+            // `some(value)`.
+            AlgebraicValue::Sum(SumValue { tag: 0, value }) => Ok((**value).clone()),
+            // `none`.
+            AlgebraicValue::Sum(SumValue { tag: 1, .. }) => Ok(base.clone()),
+            _ => Err(MergeError::NotAnOption { idx }),
+        })
+        .collect::<Result<Box<[_]>, _>>()?;
+    Ok(AlgebraicValue::product(elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product_type::IDENTITY_TAG;
+    use crate::{AlgebraicType, AlgebraicTypeRef, ArrayValue, ProductValue, Typespace};
+
+    /// Formats each field of `row`, typed at `ty`, as `sql` does for a table cell.
+    fn psql_row(ty: &ProductType, row: &[AlgebraicValue], opts: &PsqlOptions) -> Vec<String> {
+        let ts = Typespace::EMPTY.with_type(ty);
+        ty.elements
+            .iter()
+            .zip(row)
+            .enumerate()
+            .map(|(idx, (field, value))| {
+                let ty = PsqlType {
+                    tuple: ty,
+                    field,
+                    idx,
+                    opts,
+                };
+                let value = ts.with(&field.algebraic_type).with_value(value);
+                PsqlWrapper { ty, value }.to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_is_stable() {
+        let ty = AlgebraicType::product([
+            ("int", AlgebraicType::I32),
+            ("float", AlgebraicType::F64),
+            ("str", AlgebraicType::String),
+            ("bytes", AlgebraicType::bytes()),
+            ("array", AlgebraicType::array(AlgebraicType::U16)),
+            ("ts", AlgebraicType::timestamp()),
+            ("dur", AlgebraicType::time_duration()),
+            ("non_finite", AlgebraicType::array(AlgebraicType::F64)),
+        ]);
+        let val = AlgebraicValue::product([
+            (-1i32).into(),
+            0.1f64.into(),
+            "spacetime".into(),
+            AlgebraicValue::Bytes([1, 2].into()),
+            AlgebraicValue::Array([1u16, 2, 3].into()),
+            AlgebraicValue::product([1_000_000i64.into()]),
+            AlgebraicValue::product([(-1_500_000i64).into()]),
+            AlgebraicValue::Array(
+                [f64::NAN, f64::INFINITY, f64::NEG_INFINITY]
+                    .map(crate::F64::from)
+                    .into(),
+            ),
+        ]);
+        let snapshot = Typespace::EMPTY.with_type(&ty).with_value(&val).to_satn_snapshot();
+        assert_eq!(
+            snapshot,
+            concat!(
+                r#"(int = -1, float = 0.1, str = "spacetime", bytes = 0x0102, array = [1, 2, 3], "#,
+                "ts = (__timestamp_micros_since_unix_epoch__ = 1000000), ",
+                "dur = (__time_duration_micros__ = -1500000), ",
+                "non_finite = [NaN, inf, -inf])",
+            )
+        );
+    }
+
+    #[test]
+    fn psql_hex_fields() {
+        let ty = ProductType::from([
+            ("flags", AlgebraicType::U32),
+            ("mask", AlgebraicType::U8),
+            ("n", AlgebraicType::U64),
+            ("delta", AlgebraicType::I32),
+        ]);
+        let row = [31u32.into(), 0u8.into(), 255u64.into(), (-1i32).into()];
+        assert_eq!(psql_row(&ty, &row, &PsqlOptions::default()), ["31", "0", "255", "-1"]);
+        let opts = PsqlOptions {
+            hex_fields: vec!["flags".into(), "mask".into(), "delta".into()],
+            ..Default::default()
+        };
+        // Signed integers stay decimal.
+        assert_eq!(psql_row(&ty, &row, &opts), ["0x1f", "0x0", "255", "-1"]);
+    }
+
+    #[test]
+    fn psql_bare_single_field() {
+        let render = |ty: &ProductType, row: &AlgebraicValue, bare_single_field| {
+            let opts = PsqlOptions {
+                bare_single_field,
+                ..Default::default()
+            };
+            let field = ProductTypeElement::new(ty.clone().into(), None);
+            let psql_ty = PsqlType {
+                tuple: ty,
+                field: &field,
+                idx: 0,
+                opts: &opts,
+            };
+            let value = Typespace::EMPTY.with_type(&field.algebraic_type).with_value(row);
+            PsqlWrapper { ty: psql_ty, value }.to_string()
+        };
+
+        let ty = ProductType::from([("n", AlgebraicType::U32)]);
+        let row = AlgebraicValue::product([5u32.into()]);
+        assert_eq!(render(&ty, &row, false), "(n = 5)");
+        assert_eq!(render(&ty, &row, true), "5");
+
+        let ty = ProductType::from([("n", AlgebraicType::U32), ("s", AlgebraicType::String)]);
+        let row = AlgebraicValue::product([5u32.into(), "a".into()]);
+        assert_eq!(render(&ty, &row, true), r#"(n = 5, s = "a")"#);
+
+        // Nested products keep their delimiters.
+        let ty = ProductType::from([("p", AlgebraicType::product([("x", AlgebraicType::U32)]))]);
+        let row = AlgebraicValue::product([AlgebraicValue::product([5u32.into()])]);
+        assert_eq!(render(&ty, &row, true), "(x = 5)");
+    }
+
+    #[test]
+    fn psql_connection_id_as_hex_or_uuid() {
+        let ty = ProductType::from([("conn", AlgebraicType::connection_id())]);
+        let id = 0x0011_2233_4455_6677_8899_aabb_ccdd_eeffu128;
+        let row = [AlgebraicValue::product([id.into()])];
+
+        let hex = psql_row(&ty, &row, &PsqlOptions::default());
+        assert_eq!(hex, ["0x00112233445566778899aabbccddeeff"]);
+
+        let opts = PsqlOptions {
+            uuid: true,
+            ..Default::default()
+        };
+        let uuid = psql_row(&ty, &row, &opts);
+        assert_eq!(uuid, ["00112233-4455-6677-8899-aabbccddeeff"]);
+    }
+
+    #[test]
+    fn psql_uuid_leaves_other_bytes_alone() {
+        let ty = ProductType::from([("bytes", AlgebraicType::bytes()), ("id", AlgebraicType::identity())]);
+        let row = [
+            AlgebraicValue::Bytes([7; 16].into()),
+            AlgebraicValue::product([u256::from(1u128).into()]),
+        ];
+        let opts = PsqlOptions {
+            uuid: true,
+            ..Default::default()
+        };
+        let out = psql_row(&ty, &row, &opts);
+        assert_eq!(
+            out,
+            [
+                "0x07070707070707070707070707070707",
+                "0x0000000000000000000000000000000000000000000000000000000000000001",
+            ]
+        );
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        let s = "a \"quote\", a \\, a\nnewline, a\ttab, a \u{1b} and naïve 日本語 🦀";
+        let escaped = r#""a \"quote\", a \\, a\nnewline, a\ttab, a \u{1b} and naïve 日本語 🦀""#;
+        assert_eq!(s.to_satn(), escaped);
+        assert_eq!(s.to_satn_pretty(), escaped);
+
+        let ty = ProductType::from([("s", AlgebraicType::String)]);
+        assert_eq!(psql_row(&ty, &[s.into()], &PsqlOptions::default()), [escaped]);
+    }
+
+    #[test]
+    fn display_width_counts_columns() {
+        assert_eq!(display_width(""), 0);
+        assert_eq!(display_width("(a = 1)"), 7);
+        // CJK characters are two columns wide.
+        assert_eq!(display_width("日本語"), 6);
+        assert_eq!(display_width("\"日本\""), 6);
+        // Combining marks take up no columns.
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width("a\u{200b}b"), 2);
+    }
+
+    #[test]
+    fn color_wraps_tokens() {
+        let ty = AlgebraicType::product([
+            ("name", AlgebraicType::String),
+            ("n", AlgebraicType::I32),
+            ("blob", AlgebraicType::bytes()),
+            ("tag", AlgebraicType::option(AlgebraicType::F64)),
+        ]);
+        let value = AlgebraicValue::product([
+            "a".into(),
+            (-1i32).into(),
+            AlgebraicValue::Bytes([1u8].into()),
+            AlgebraicValue::OptionSome(1.5f64.into()),
+        ]);
+        let opts = SatnOptions {
+            color: true,
+            ..Default::default()
+        };
+        let (name, string, number, bytes, reset) = ("\x1b[34m", "\x1b[32m", "\x1b[33m", "\x1b[35m", "\x1b[0m");
+        let colored = Typespace::EMPTY
+            .with_type(&ty)
+            .to_satn_typed_with(&value, &opts)
+            .unwrap();
+        assert_eq!(
+            colored,
+            format!(
+                "({name}name{reset} = {string}\"a\"{reset}, {name}n{reset} = {number}-1{reset}, \
+                 {name}blob{reset} = {bytes}0x01{reset}, {name}tag{reset} = ({name}some{reset} = {number}1.5{reset}))"
+            )
+        );
+        // The escape codes take up no columns, so lines break as without them.
+        let plain = Typespace::EMPTY
+            .with_type(&ty)
+            .to_satn_typed_with(&value, &SatnOptions::default())
+            .unwrap();
+        assert_eq!(display_width(&colored), display_width(&plain));
+        let array = AlgebraicValue::Array([1u32, 2].into());
+        let narrow = |color| SatnPrettyOptions {
+            max_line_width: Some(6),
+            satn: SatnOptions {
+                color,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            array.to_satn_pretty_with(&narrow(true)),
+            format!("[{number}1{reset}, {number}2{reset}]")
+        );
+        assert_eq!(array.to_satn_pretty_with(&narrow(false)), "[1, 2]");
+    }
+
+    #[test]
+    fn unit_renders_as_empty_parens() {
+        assert_eq!(().to_satn(), "()");
+        assert_eq!(AlgebraicValue::unit().to_satn(), "()");
+        assert_eq!(AlgebraicValue::unit().to_satn_pretty(), "()");
+
+        let ty = AlgebraicType::unit();
+        assert_eq!(
+            Typespace::EMPTY
+                .with_type(&ty)
+                .with_value(&AlgebraicValue::unit())
+                .to_satn(),
+            "()"
+        );
+    }
+
+    #[test]
+    fn unit_nested_in_sum() {
+        let ty = AlgebraicType::option(AlgebraicType::U8);
+        let none = AlgebraicValue::OptionNone();
+        let value = Typespace::EMPTY.with_type(&ty).with_value(&none);
+        assert_eq!(value.to_satn(), "(none = ())");
+        assert_eq!(value.to_satn_pretty(), "(\n    none = (),\n)");
+
+        let untyped = AlgebraicValue::sum(1, AlgebraicValue::unit());
+        assert_eq!(untyped.to_satn(), "(1 = ())");
+    }
+
+    #[test]
+    fn float_formats() {
+        let value = AlgebraicValue::product([12300f64.into(), 0.1f32.into(), (-0.5f64).into()]);
+        let with = |float| {
+            value.to_satn_with(&SatnOptions {
+                float,
+                ..Default::default()
+            })
+        };
+        assert_eq!(with(FloatFmt::Shortest), "(0 = 12300, 1 = 0.1, 2 = -0.5)");
+        assert_eq!(with(FloatFmt::Fixed(2)), "(0 = 12300.00, 1 = 0.10, 2 = -0.50)");
+        assert_eq!(with(FloatFmt::Scientific), "(0 = 1.23e4, 1 = 1e-1, 2 = -5e-1)");
+        assert_eq!(value.to_satn(), with(FloatFmt::default()));
+
+        let ty = ProductType::from([("float", AlgebraicType::F64)]);
+        let opts = PsqlOptions {
+            satn: SatnOptions {
+                float: FloatFmt::Scientific,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(psql_row(&ty, &[12300f64.into()], &opts), ["1.23e4"]);
+    }
+
+    #[test]
+    fn byte_encodings() {
+        let value = AlgebraicValue::Bytes([0xbe, 0xef].into());
+        let with = |bytes| {
+            value.to_satn_with(&SatnOptions {
+                bytes,
+                ..Default::default()
+            })
+        };
+        assert_eq!(with(ByteEncoding::Hex), "0xbeef");
+        assert_eq!(with(ByteEncoding::HexUpper), "0xBEEF");
+        assert_eq!(with(ByteEncoding::Base64), "vu8=");
+        assert_eq!(value.to_satn(), with(ByteEncoding::default()));
+
+        // Identities are printed as bytes, so follow the encoding too.
+        let ty = ProductType::from([("bytes", AlgebraicType::bytes()), ("id", AlgebraicType::identity())]);
+        let row = [value.clone(), AlgebraicValue::product([u256::from(0xabu128).into()])];
+        let opts = PsqlOptions {
+            satn: SatnOptions {
+                bytes: ByteEncoding::HexUpper,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let id = format!("0x{}AB", "0".repeat(62));
+        assert_eq!(psql_row(&ty, &row, &opts), ["0xBEEF", &id]);
+    }
+
+    #[test]
+    fn max_depth_errors_on_deep_nesting() {
+        let mut value = AlgebraicValue::U8(0);
+        for _ in 0..10_000 {
+            value = AlgebraicValue::product([value]);
+        }
+        let opts = SatnOptions {
+            max_depth: Some(64),
+            ..Default::default()
+        };
+        assert!(value.try_to_satn_with(&opts).is_err());
+
+        // Tear down iteratively, as dropping recursively could overflow the stack too.
+        while let AlgebraicValue::Product(product) = value {
+            value = product.elements.into_vec().pop().unwrap();
+        }
+    }
+
+    #[test]
+    fn max_depth_elides() {
+        let value = AlgebraicValue::product([
+            1u8.into(),
+            AlgebraicValue::product([2u8.into(), AlgebraicValue::product([3u8.into()])]),
+            AlgebraicValue::Array([ArrayValue::from([4u32])].into()),
+            AlgebraicValue::OptionSome(AlgebraicValue::product([5u8.into()])),
+        ]);
+        let with = |max_depth| {
+            value.try_to_satn_with(&SatnOptions {
+                max_depth: Some(max_depth),
+                depth_exceeded: DepthExceeded::Elide,
+                ..Default::default()
+            })
+        };
+        assert_eq!(with(0).unwrap(), "(...)");
+        assert_eq!(with(1).unwrap(), "(0 = 1, 1 = (...), 2 = [...], 3 = (...))");
+        assert_eq!(
+            with(2).unwrap(),
+            "(0 = 1, 1 = (0 = 2, 1 = (...)), 2 = [[...]], 3 = (0 = (...)))"
+        );
+        assert_eq!(with(3).unwrap(), value.to_satn());
+
+        let opts = SatnOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        assert!(value.try_to_satn_with(&opts).is_err());
+        assert_eq!(
+            value.try_to_satn_with(&SatnOptions::default()).unwrap(),
+            value.to_satn()
+        );
+
+        let inner = AlgebraicType::product([("r", AlgebraicType::U8)]);
+        let ty = ProductType::from([("p", AlgebraicType::product([("q", inner)]))]);
+        let row = [AlgebraicValue::product([AlgebraicValue::product([3u8.into()])])];
+        let opts = PsqlOptions {
+            satn: SatnOptions {
+                max_depth: Some(1),
+                depth_exceeded: DepthExceeded::Elide,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(psql_row(&ty, &row, &opts), ["(q = (...))"]);
+    }
+
+    #[test]
+    fn max_array_elements_truncates() {
+        let value = AlgebraicValue::product([
+            AlgebraicValue::Array([1u32, 2, 3, 4, 5].into()),
+            AlgebraicValue::Array([6u32].into()),
+        ]);
+        let with = |max_array_elements| {
+            value.to_satn_with(&SatnOptions {
+                max_array_elements,
+                ..Default::default()
+            })
+        };
+        assert_eq!(with(Some(2)), "(0 = [1, 2, ... (3 more)], 1 = [6])");
+        assert_eq!(with(Some(0)), "(0 = [... (5 more)], 1 = [... (1 more)])");
+        // No marker when the array fits.
+        assert_eq!(with(Some(5)), value.to_satn());
+        assert_eq!(with(None), value.to_satn());
+
+        let opts = SatnPrettyOptions {
+            satn: SatnOptions {
+                max_array_elements: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected = "(\n    0 = [\n        1,\n        ... (4 more),\n    ],\n    1 = [\n        6,\n    ],\n)";
+        assert_eq!(value.to_satn_pretty_with(&opts), expected);
+    }
+
+    #[test]
+    fn errors_carry_messages() {
+        let value = AlgebraicValue::product([AlgebraicValue::product([1u8.into()])]);
+        let opts = SatnOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let err = value.try_to_satn_with(&opts).unwrap_err();
+        assert!(!err.is_write_error());
+        assert_eq!(
+            err.message(),
+            Some("value is nested deeper than the maximum depth of 1")
+        );
+
+        let err = value.to_satn_by_name().unwrap_err();
+        assert_eq!(err.message(), Some("cannot sort unnamed field 0 by name"));
+
+        let err = <SatnError as ser::Error>::custom("boom");
+        assert_eq!(err.message(), Some("boom"));
+        assert!(SatnError::from(fmt::Error).is_write_error());
+    }
+
+    #[test]
+    fn write_satn_streams() {
+        let value = AlgebraicValue::product([
+            "streamed".into(),
+            AlgebraicValue::Array([1u32, 2].into()),
+            AlgebraicValue::product([AlgebraicValue::product([1u8.into()])]),
+        ]);
+        let mut out = Vec::new();
+        write_satn(&value, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), value.to_satn());
+
+        let opts = SatnOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let err = write_satn_with(&value, io::sink(), &opts).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // The I/O error of the writer is returned as is.
+        let mut full = [0u8; 4];
+        let err = write_satn(&value, &mut full[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn float_edge_cases() {
+        let subnormal = f64::from_bits(1);
+        let value = AlgebraicValue::product([1.0f64.into(), subnormal.into(), (-0.0f64).into()]);
+        let with = |float| {
+            value.to_satn_with(&SatnOptions {
+                float,
+                ..Default::default()
+            })
+        };
+        let tiny = format!("0.{}5", "0".repeat(323));
+        assert_eq!(with(FloatFmt::Shortest), format!("(0 = 1, 1 = {tiny}, 2 = -0)"));
+        assert_eq!(with(FloatFmt::Scientific), "(0 = 1e0, 1 = 5e-324, 2 = -0e0)");
+        assert_eq!(with(FloatFmt::Fixed(1)), "(0 = 1.0, 1 = 0.0, 2 = -0.0)");
+
+        let value = AlgebraicValue::product([f64::NAN.into(), f64::INFINITY.into(), f32::NEG_INFINITY.into()]);
+        let with = |non_finite| {
+            value.to_satn_with(&SatnOptions {
+                non_finite,
+                // Non-finite floats are unaffected by the format.
+                float: FloatFmt::Fixed(2),
+                ..Default::default()
+            })
+        };
+        assert_eq!(with(NonFinite::Bare), "(0 = NaN, 1 = inf, 2 = -inf)");
+        assert_eq!(with(NonFinite::Quoted), r#"(0 = "NaN", 1 = "+inf", 2 = "-inf")"#);
+    }
+
+    #[test]
+    fn merge_overlays_some_fields() {
+        let ty = ProductType::from([
+            ("int", AlgebraicType::U32),
+            ("array", AlgebraicType::array(AlgebraicType::U8)),
+            ("sum", AlgebraicType::option(AlgebraicType::U8)),
+        ]);
+        let base = AlgebraicValue::product([
+            1u32.into(),
+            AlgebraicValue::Array([1u8, 2, 3].into()),
+            AlgebraicValue::OptionSome(7u8.into()),
+        ]);
+
+        // Nothing changes for an all-`none` patch.
+        let none = AlgebraicValue::product([
+            AlgebraicValue::OptionNone(),
+            AlgebraicValue::OptionNone(),
+            AlgebraicValue::OptionNone(),
+        ]);
+        assert_eq!(satn_merge(&base, &none, &ty), Ok(base.clone()));
+
+        // Arrays and sums are replaced wholesale.
+        let patch = AlgebraicValue::product([
+            AlgebraicValue::OptionNone(),
+            AlgebraicValue::OptionSome(AlgebraicValue::Array([4u8].into())),
+            AlgebraicValue::OptionSome(AlgebraicValue::OptionNone()),
+        ]);
+        let merged = AlgebraicValue::product([
+            1u32.into(),
+            AlgebraicValue::Array([4u8].into()),
+            AlgebraicValue::OptionNone(),
+        ]);
+        assert_eq!(satn_merge(&base, &patch, &ty), Ok(merged));
+    }
+
+    #[test]
+    fn merge_rejects_malformed_patches() {
+        let ty = ProductType::from([("a", AlgebraicType::U32), ("b", AlgebraicType::U32)]);
+        let base = AlgebraicValue::product([1u32.into(), 2u32.into()]);
+
+        let short = AlgebraicValue::product([AlgebraicValue::OptionNone()]);
+        assert_eq!(satn_merge(&base, &short, &ty), Err(MergeError::InvalidPatch(2)));
+        assert_eq!(satn_merge(&short, &base, &ty), Err(MergeError::InvalidBase(2)));
+
+        let not_option = AlgebraicValue::product([AlgebraicValue::OptionNone(), 3u32.into()]);
+        assert_eq!(
+            satn_merge(&base, &not_option, &ty),
+            Err(MergeError::NotAnOption { idx: 1 })
+        );
+    }
+
+    #[test]
+    fn unnamed_index_base() {
+        let value = AlgebraicValue::product([1u8.into(), 2u8.into(), 3u8.into()]);
+        let zero = SatnOptions::default();
+        let one = SatnOptions {
+            index_base: IndexBase::One,
+            ..Default::default()
+        };
+        assert_eq!(value.to_satn_with(&zero), "(0 = 1, 1 = 2, 2 = 3)");
+        assert_eq!(value.to_satn_with(&one), "(1 = 1, 2 = 2, 3 = 3)");
+
+        let inner = ProductType::from([AlgebraicType::U8, AlgebraicType::U8, AlgebraicType::U8]);
+        let ty = ProductType::from([("id", AlgebraicType::U8), ("p", AlgebraicType::Product(inner))]);
+        let row = [0u8.into(), value];
+        let psql = |satn| {
+            let opts = PsqlOptions {
+                satn,
+                ..Default::default()
+            };
+            psql_row(&ty, &row, &opts)
+        };
+        assert_eq!(psql(zero), ["0", "(0 = 1, 1 = 2, 2 = 3)"]);
+        assert_eq!(psql(one), ["0", "(1 = 1, 2 = 2, 3 = 3)"]);
+    }
+
+    #[test]
+    fn by_name_is_invariant_to_field_order() {
+        let inner = ProductType::from([("y", AlgebraicType::U8), ("x", AlgebraicType::U8)]);
+        let ty: AlgebraicType = ProductType::from([
+            ("b", AlgebraicType::U8),
+            ("a", AlgebraicType::String),
+            ("c", AlgebraicType::Product(inner)),
+        ])
+        .into();
+        let value = AlgebraicValue::product([
+            1u8.into(),
+            "one".into(),
+            AlgebraicValue::product([2u8.into(), 3u8.into()]),
+        ]);
+
+        // The same type and value, but with every product's fields reversed.
+        let inner = ProductType::from([("x", AlgebraicType::U8), ("y", AlgebraicType::U8)]);
+        let reordered_ty: AlgebraicType = ProductType::from([
+            ("c", AlgebraicType::Product(inner)),
+            ("a", AlgebraicType::String),
+            ("b", AlgebraicType::U8),
+        ])
+        .into();
+        let reordered = AlgebraicValue::product([
+            AlgebraicValue::product([3u8.into(), 2u8.into()]),
+            "one".into(),
+            1u8.into(),
+        ]);
+
+        let by_name = Typespace::EMPTY.with_type(&ty).with_value(&value).to_satn_by_name();
+        let reordered_by_name = Typespace::EMPTY
+            .with_type(&reordered_ty)
+            .with_value(&reordered)
+            .to_satn_by_name();
+        assert_eq!(by_name.as_deref(), Ok(r#"(a = "one", b = 1, c = (x = 3, y = 2))"#));
+        assert_eq!(by_name, reordered_by_name);
+
+        // Unnamed fields have no stable order.
+        assert!(value.to_satn_by_name().is_err());
+    }
+
+    #[test]
+    fn sorted_puts_unnamed_fields_last() {
+        let inner = ProductType::from([(Some("z"), AlgebraicType::U8), (None, AlgebraicType::U8)]);
+        let ty: AlgebraicType = ProductType::from([
+            (None, AlgebraicType::U8),
+            (Some("b"), AlgebraicType::U8),
+            (None, AlgebraicType::Product(inner)),
+            (Some("a"), AlgebraicType::U8),
+        ])
+        .into();
+        let value = AlgebraicValue::product([
+            0u8.into(),
+            1u8.into(),
+            AlgebraicValue::product([2u8.into(), 3u8.into()]),
+            4u8.into(),
+        ]);
+        let opts = SatnOptions {
+            field_order: FieldOrder::Sorted,
+            index_base: IndexBase::One,
+            ..Default::default()
+        };
+        let typed = Typespace::EMPTY.with_type(&ty).with_value(&value);
+        assert_eq!(typed.to_satn_with(&opts), "(a = 4, b = 1, 1 = 0, 3 = (z = 2, 2 = 3))");
+    }
+
+    #[test]
+    fn max_bytes_truncates() {
+        let val = AlgebraicValue::product([1u32.into(), "héllo".into(), 3u32.into()]);
+        let render = |max_bytes| {
+            let opts = SatnOptions {
+                max_bytes,
+                ..Default::default()
+            };
+            val.to_satn_with(&opts)
+        };
+        let full = val.to_satn();
+        assert_eq!(render(None), full);
+        assert_eq!(render(Some(full.len())), full);
+        // Cut off in the middle of the product.
+        assert_eq!(render(Some(12)), "(0 = 1, 1 = ... (truncated)");
+        // Without splitting `é`.
+        assert_eq!(render(Some(15)), "(0 = 1, 1 = \"h... (truncated)");
+        let pretty = SatnPrettyOptions {
+            satn: SatnOptions {
+                max_bytes: Some(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(val.to_satn_pretty_with(&pretty), "(\n    0 = ... (truncated)");
+    }
+
+    #[test]
+    fn chars_are_single_quoted() {
+        struct Char(char);
+        impl ser::Serialize for Char {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_char(self.0)
+            }
+        }
+        assert_eq!(Char('a').to_satn(), "'a'");
+        assert_eq!(Char('\'').to_satn(), "'\\''");
+        assert_eq!(Char('"').to_satn(), "'\"'");
+        assert_eq!(Char('\\').to_satn(), "'\\\\'");
+        assert_eq!(Char('\n').to_satn(), "'\\n'");
+        assert_eq!(Char('é').to_satn(), "'é'");
+        // Strings still escape double quotes only.
+        assert_eq!("'\"".to_satn(), "\"'\\\"\"");
+        // Other formats without characters serialize them as strings.
+        assert_eq!(Char('a').to_satn_json(), "\"a\"");
+    }
+
+    #[test]
+    fn satn_debug() {
+        struct Point(ProductValue);
+        impl ser::Serialize for Point {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.0.serialize(serializer)
+            }
+        }
+        crate::satn_debug!(Point);
+
+        let point = Point(ProductValue::from_iter([1u32.into(), 2u32.into()]));
+        assert_eq!(format!("{point:?}"), "(0 = 1, 1 = 2)");
+        assert_eq!(format!("{point:#?}"), point.to_satn_pretty());
+    }
+
+    #[test]
+    fn pretty_indent_is_configurable() {
+        let value = AlgebraicValue::product([
+            AlgebraicValue::Array([1u32, 2].into()),
+            AlgebraicValue::product([3u8.into()]),
+        ]);
+        let two = SatnPrettyOptions {
+            indent: "  ".into(),
+            ..Default::default()
+        };
+        let tab = SatnPrettyOptions {
+            indent: "\t".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            value.to_satn_pretty_with(&SatnPrettyOptions::default()),
+            value.to_satn_pretty()
+        );
+        assert_eq!(
+            value.to_satn_pretty_with(&two),
+            "(\n  0 = [\n    1,\n    2,\n  ],\n  1 = (\n    0 = 3,\n  ),\n)"
+        );
+        assert_eq!(
+            value.to_satn_pretty_with(&tab),
+            "(\n\t0 = [\n\t\t1,\n\t\t2,\n\t],\n\t1 = (\n\t\t0 = 3,\n\t),\n)"
+        );
+    }
+
+    #[test]
+    fn pretty_width() {
+        let value = AlgebraicValue::product([
+            AlgebraicValue::Array([1u32, 2, 3].into()),
+            AlgebraicValue::product([
+                "a string too long to fit on a line of eighty".into(),
+                AlgebraicValue::product([4u8.into()]),
+            ]),
+        ]);
+        assert_eq!(
+            value.to_satn_pretty_width(20),
+            "(\n    0 = [1, 2, 3],\n    1 = (\n        0 = \"a string too long to fit on a line of eighty\",\n        1 = (0 = 4),\n    ),\n)"
+        );
+        assert_eq!(
+            value.to_satn_pretty_width(80),
+            "(\n    0 = [1, 2, 3],\n    1 = (0 = \"a string too long to fit on a line of eighty\", 1 = (0 = 4)),\n)"
+        );
+        assert_eq!(
+            value.to_satn_pretty_width(usize::MAX),
+            "(0 = [1, 2, 3], 1 = (0 = \"a string too long to fit on a line of eighty\", 1 = (0 = 4)))"
+        );
+    }
+
+    #[test]
+    fn variant_case() {
+        let ty = AlgebraicType::sum([("Active", AlgebraicType::U8), ("on_hold", AlgebraicType::unit())]);
+        let typed = |value: &AlgebraicValue, variant_case| {
+            let opts = SatnOptions {
+                variant_case,
+                ..Default::default()
+            };
+            Typespace::EMPTY
+                .with_type(&ty)
+                .to_satn_typed_with(value, &opts)
+                .unwrap()
+        };
+        let active = AlgebraicValue::sum(0, 1u8.into());
+        let on_hold = AlgebraicValue::sum(1, AlgebraicValue::unit());
+        assert_eq!(typed(&active, VariantCase::AsIs), "(Active = 1)");
+        assert_eq!(typed(&active, VariantCase::Upper), "(ACTIVE = 1)");
+        assert_eq!(typed(&on_hold, VariantCase::Upper), "(ON_HOLD = ())");
+        assert_eq!(typed(&active, VariantCase::Lower), "(active = 1)");
+        // Unnamed variants keep their tags.
+        let untyped = SatnFmt::new().variant_case(VariantCase::Upper).format(&active);
+        assert_eq!(untyped, "(0 = 1)");
+    }
+
+    #[test]
+    fn fmt_builder() {
+        let value = AlgebraicValue::product([
+            AlgebraicValue::Bytes([0xbe, 0xef].into()),
+            AlgebraicValue::product([AlgebraicValue::product([1u8.into()])]),
+        ]);
+        assert_eq!(SatnFmt::new().format(&value), value.to_satn());
+        assert_eq!(SatnFmt::new().pretty(true).format(&value), value.to_satn_pretty());
+        assert_eq!(
+            SatnFmt::new()
+                .indent("  ")
+                .max_depth(2)
+                .depth_exceeded(DepthExceeded::Elide)
+                .bytes(ByteEncoding::Base64)
+                .format(&value),
+            "(\n  0 = vu8=,\n  1 = (\n    0 = (...),\n  ),\n)"
+        );
+        assert_eq!(SatnFmt::new().compact(true).format(&value), "(0=0xbeef,1=(0=(0=1)))");
+        assert!(SatnFmt::new().max_depth(1).try_format(&value).is_err());
+    }
+
+    #[test]
+    fn pretty_array_sep() {
+        let value = AlgebraicValue::product([
+            AlgebraicValue::Array([1u32, 2].into()),
+            AlgebraicValue::product([3u8.into(), 4u8.into()]),
+        ]);
+        let opts = SatnPrettyOptions {
+            indent: "  ".into(),
+            array_sep: PrettySep::Newline,
+            ..Default::default()
+        };
+        // Only the elements of arrays are separated by newlines alone.
+        assert_eq!(
+            value.to_satn_pretty_with(&opts),
+            "(\n  0 = [\n    1\n    2\n  ],\n  1 = (\n    0 = 3,\n    1 = 4,\n  ),\n)"
+        );
+        let strict = SatnPrettyOptions { strict: true, ..opts };
+        assert_eq!(
+            value.to_satn_pretty_with(&strict),
+            "(\n  0 = [\n    1\n    2\n  ],\n  1 = (\n    0 = 3,\n    1 = 4\n  )\n)"
+        );
+        // Arrays kept on one line are still separated by commas.
+        let narrow = SatnPrettyOptions {
+            max_line_width: Some(24),
+            ..strict
+        };
+        assert_eq!(
+            value.to_satn_pretty_with(&narrow),
+            "(\n  0 = [1, 2],\n  1 = (0 = 3, 1 = 4)\n)"
+        );
+    }
+
+    #[test]
+    fn pretty_strict_has_no_trailing_separators() {
+        let value = AlgebraicValue::product([
+            AlgebraicValue::Array([1u32, 2].into()),
+            AlgebraicValue::product([3u8.into()]),
+            AlgebraicValue::sum(1, 4u8.into()),
+            AlgebraicValue::Array(ArrayValue::U32([].into())),
+        ]);
+        let strict = SatnPrettyOptions {
+            indent: "  ".into(),
+            strict: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            value.to_satn_pretty_with(&strict),
+            "(\n  0 = [\n    1,\n    2\n  ],\n  1 = (\n    0 = 3\n  ),\n  2 = (\n    1 = 4\n  ),\n  3 = []\n)"
+        );
+        // Entries kept on one line never had trailing separators.
+        let narrow = SatnPrettyOptions {
+            max_line_width: Some(20),
+            ..strict
+        };
+        assert_eq!(
+            value.to_satn_pretty_with(&narrow),
+            "(\n  0 = [1, 2],\n  1 = (0 = 3),\n  2 = (1 = 4),\n  3 = []\n)"
+        );
+    }
+
+    #[test]
+    fn redacted_fields_are_masked() {
+        let ty = AlgebraicType::product([
+            ("name", AlgebraicType::String),
+            (
+                "ssn",
+                AlgebraicType::product([("digits", AlgebraicType::product([AlgebraicType::String]))]),
+            ),
+            (
+                "inner",
+                AlgebraicType::product([("ssn", AlgebraicType::String), ("id", AlgebraicType::U32)]),
+            ),
+        ]);
+        let val = AlgebraicValue::product([
+            "alice".into(),
+            AlgebraicValue::product([AlgebraicValue::product(["123".into()])]),
+            AlgebraicValue::product(["456".into(), 7u32.into()]),
+        ]);
+        let value = Typespace::EMPTY.with_type(&ty).with_value(&val);
+        let opts = SatnOptions {
+            redact: Some(|name| name == "ssn"),
+            ..Default::default()
+        };
+        assert_eq!(
+            value.to_satn_with(&opts),
+            r#"(name = "alice", ssn = <redacted>, inner = (ssn = <redacted>, id = 7))"#
+        );
+
+        // Redacted values aren't descended into, so `ssn` isn't too deep.
+        assert!(value
+            .try_to_satn_with(&SatnOptions {
+                max_depth: Some(2),
+                ..Default::default()
+            })
+            .is_err());
+        let opts = SatnOptions {
+            max_depth: Some(2),
+            field_order: FieldOrder::ByName,
+            ..opts
+        };
+        assert_eq!(
+            value.try_to_satn_with(&opts).unwrap(),
+            r#"(inner = (id = 7, ssn = <redacted>), name = "alice", ssn = <redacted>)"#
+        );
+    }
+
+    #[test]
+    fn typed_annotates_elements() {
+        let point = AlgebraicType::product([("x", AlgebraicType::I32), ("y", AlgebraicType::I32)]);
+        let typespace = Typespace::new(vec![point]);
+        let ty = AlgebraicType::product([
+            ("id", AlgebraicType::U32),
+            ("at", AlgebraicType::Ref(AlgebraicTypeRef(0))),
+            ("path", AlgebraicType::array(AlgebraicType::Ref(AlgebraicTypeRef(0)))),
+            ("tag", AlgebraicType::option(AlgebraicType::String)),
+        ]);
+        let point = |x: i32, y: i32| ProductValue::from([x.into(), y.into()]);
+        let val = AlgebraicValue::product([
+            5u32.into(),
+            point(1, 2).into(),
+            AlgebraicValue::Array([point(3, 4)].into()),
+            AlgebraicValue::OptionSome("a".into()),
+        ]);
+        assert_eq!(
+            typespace.with_type(&ty).to_satn_annotated(&val),
+            r#"(id: U32 = 5, at: &0 = (x: I32 = 1, y: I32 = 2), path: Array<&0> = [(x: I32 = 3, y: I32 = 4)], tag: (some: String | none: ()) = (some: String = "a"))"#
+        );
+    }
+
+    #[test]
+    fn satn_fmt_format_typed() {
+        let ty = AlgebraicType::product([("tag", AlgebraicType::option(AlgebraicType::U8))]);
+        let ty = Typespace::EMPTY.with_type(&ty);
+        let val = AlgebraicValue::product([AlgebraicValue::OptionSome(5u8.into())]);
+        let fmt = SatnFmt::new().option_fmt(OptionFmt::Bare);
+        // Without the type, the sum isn't known to be an option.
+        assert_eq!(fmt.format(&ty.with_value(&val)), "(tag = (some = 5))");
+        assert_eq!(fmt.format_typed(ty, &val), "(tag = 5)");
+        assert_eq!(fmt.pretty(true).format_typed(ty, &val), "(\n    tag = 5,\n)");
+    }
+
+    #[test]
+    fn bsatn_to_satn() {
+        let ty = AlgebraicType::product([("id", AlgebraicType::U32), ("name", AlgebraicType::String)]);
+        let ty = Typespace::EMPTY.with_type(&ty);
+        let bytes = bsatn::to_vec(&AlgebraicValue::product([7u32.into(), "alice".into()])).unwrap();
+        assert_eq!(decode_bsatn_to_satn(ty, &bytes).unwrap(), r#"(id = 7, name = "alice")"#);
+        for len in 0..bytes.len() {
+            assert!(matches!(
+                decode_bsatn_to_satn(ty, &bytes[..len]),
+                Err(DecodeError::BufferLength { .. })
+            ));
+        }
+        let mut extra = bytes;
+        extra.push(0);
+        assert_eq!(
+            decode_bsatn_to_satn(ty, &extra),
+            Err(DecodeError::InvalidLen {
+                expected: extra.len() - 1,
+                given: extra.len(),
+            })
+        );
+    }
+
+    #[test]
+    fn field_paths_label_nested_fields() {
+        let ty = AlgebraicType::product([
+            ("id", AlgebraicType::U32),
+            (
+                "outer",
+                AlgebraicType::product([
+                    ("id", AlgebraicType::U32),
+                    ("inner", AlgebraicType::product([AlgebraicType::U32])),
+                ]),
+            ),
+            (
+                "items",
+                AlgebraicType::array(AlgebraicType::product([("id", AlgebraicType::U32)])),
+            ),
+        ]);
+        let value = AlgebraicValue::product([
+            1u32.into(),
+            AlgebraicValue::product([2u32.into(), AlgebraicValue::product([3u32.into()])]),
+            AlgebraicValue::Array([ProductValue::from([AlgebraicValue::U32(4)])].into()),
+        ]);
+        let opts = SatnOptions {
+            field_paths: true,
+            ..Default::default()
+        };
+        let satn = Typespace::EMPTY
+            .with_type(&ty)
+            .to_satn_typed_with(&value, &opts)
+            .unwrap();
+        assert_eq!(
+            satn,
+            "(id = 1, outer = (outer.id = 2, outer.inner = (outer.inner.0 = 3)), items = [(items.id = 4)])"
+        );
+        // Off by default.
+        assert_eq!(
+            value.to_satn_typed(&ty, Typespace::EMPTY),
+            "(id = 1, outer = (id = 2, inner = (0 = 3)), items = [(id = 4)])"
+        );
+    }
+
+    #[test]
+    fn value_to_satn_typed() {
+        let point = AlgebraicType::product([("x", AlgebraicType::I32), ("y", AlgebraicType::I32)]);
+        let typespace = Typespace::new(vec![point]);
+        let ty = AlgebraicType::product([
+            ("id", AlgebraicType::U32),
+            ("at", AlgebraicType::Ref(AlgebraicTypeRef(0))),
+        ]);
+        let val = AlgebraicValue::product([5u32.into(), crate::product![1i32, 2i32].into()]);
+        assert_eq!(val.to_satn(), "(0 = 5, 1 = (0 = 1, 1 = 2))");
+        assert_eq!(val.to_satn_typed(&ty, &typespace), "(id = 5, at = (x = 1, y = 2))");
+    }
+
+    #[test]
+    fn map_arrays() {
+        let entry = AlgebraicType::product([("key", AlgebraicType::String), ("value", AlgebraicType::U32)]);
+        let ty = AlgebraicType::product([
+            ("map", AlgebraicType::array(entry.clone())),
+            (
+                "pairs",
+                AlgebraicType::array(AlgebraicType::product([AlgebraicType::String, AlgebraicType::U32])),
+            ),
+        ]);
+        let val = AlgebraicValue::product([
+            AlgebraicValue::Array([crate::product!["a", 1u32], crate::product!["b", 2u32]].into()),
+            AlgebraicValue::Array([crate::product!["c", 3u32]].into()),
+        ]);
+        let ty = Typespace::EMPTY.with_type(&ty);
+        // Like any other array by default.
+        assert_eq!(
+            ty.to_satn_typed_with(&val, &SatnOptions::default()).unwrap(),
+            r#"(map = [(key = "a", value = 1), (key = "b", value = 2)], pairs = [(0 = "c", 1 = 3)])"#
+        );
+
+        let opts = SatnOptions {
+            map_fmt: MapFmt::Braces,
+            ..Default::default()
+        };
+        assert_eq!(
+            ty.to_satn_typed_with(&val, &opts).unwrap(),
+            r#"(map = {"a": 1, "b": 2}, pairs = [(0 = "c", 1 = 3)])"#
+        );
+        let compact = SatnOptions {
+            compact: true,
+            ..opts.clone()
+        };
+        assert_eq!(
+            ty.to_satn_typed_with(&val, &compact).unwrap(),
+            r#"(map={"a":1,"b":2},pairs=[(0="c",1=3)])"#
+        );
+        let pretty = SatnPrettyOptions {
+            satn: opts.clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            ty.to_satn_typed_pretty_with(&val, &pretty).unwrap(),
+            "(\n    map = {\n        \"a\": 1,\n        \"b\": 2,\n    },\n    pairs = [\n        (\n            0 = \"c\",\n            1 = 3,\n        ),\n    ],\n)"
+        );
+
+        // Empty maps are told apart by their type.
+        let ty = AlgebraicType::array(entry);
+        let ty = Typespace::EMPTY.with_type(&ty);
+        let val = AlgebraicValue::Array(ArrayValue::Product([].into()));
+        assert_eq!(ty.to_satn_typed_with(&val, &opts).unwrap(), "{}");
+    }
+
+    #[test]
+    fn bare_options() {
+        let ty = AlgebraicType::product([
+            ("a", AlgebraicType::option(AlgebraicType::U32)),
+            ("b", AlgebraicType::option(AlgebraicType::U32)),
+            (
+                "c",
+                AlgebraicType::option(AlgebraicType::product([("x", AlgebraicType::Bool)])),
+            ),
+        ]);
+        let val = AlgebraicValue::product([
+            AlgebraicValue::OptionSome(5u32.into()),
+            AlgebraicValue::OptionNone(),
+            AlgebraicValue::OptionSome(AlgebraicValue::product([true.into()])),
+        ]);
+        let ty = Typespace::EMPTY.with_type(&ty);
+        // Tagged by default, as for any other sum.
+        assert_eq!(
+            ty.to_satn_typed_with(&val, &SatnOptions::default()).unwrap(),
+            "(a = (some = 5), b = (none = ()), c = (some = (x = true)))"
+        );
+
+        let opts = SatnOptions {
+            option_fmt: OptionFmt::Bare,
+            ..Default::default()
+        };
+        assert_eq!(
+            ty.to_satn_typed_with(&val, &opts).unwrap(),
+            "(a = 5, b = none, c = (x = true))"
+        );
+        let pretty = SatnPrettyOptions {
+            satn: opts,
+            ..Default::default()
+        };
+        assert_eq!(
+            ty.to_satn_typed_pretty_with(&val, &pretty).unwrap(),
+            "(\n    a = 5,\n    b = none,\n    c = (\n        x = true,\n    ),\n)"
+        );
+
+        // Sums of two unit variants aren't option-shaped.
+        let ty = AlgebraicType::simple_enum(["on", "off"].into_iter());
+        let val = AlgebraicValue::sum(1, AlgebraicValue::unit());
+        let ty = Typespace::EMPTY.with_type(&ty);
+        let opts = SatnOptions {
+            option_fmt: OptionFmt::Bare,
+            ..Default::default()
+        };
+        assert_eq!(ty.to_satn_typed_with(&val, &opts).unwrap(), "(off = ())");
+    }
+
+    #[test]
+    fn max_line_width_keeps_fitting_values_on_one_line() {
+        let value = AlgebraicValue::product([
+            AlgebraicValue::product([1u32.into(), 2u32.into()]),
+            AlgebraicValue::Array([10u32, 20, 30, 40, 50, 60, 70, 80].into()),
+            AlgebraicValue::product([AlgebraicValue::product([3u32.into()]), "日本語日本語日本語".into()]),
+        ]);
+        let with = |max_line_width| {
+            let opts = SatnPrettyOptions {
+                max_line_width,
+                ..Default::default()
+            };
+            value.to_satn_pretty_with(&opts)
+        };
+        assert_eq!(with(None), value.to_satn_pretty());
+        // The whole value fits.
+        assert_eq!(with(Some(200)), value.to_satn());
+        // Each nested value is laid out independently.
+        let expected = "\
+(
+    0 = (0 = 1, 1 = 2),
+    1 = [10, 20, 30, 40, 50, 60, 70, 80],
+    2 = (
+        0 = (0 = 3),
+        1 = \"日本語日本語日本語\",
+    ),
+)";
+        assert_eq!(with(Some(42)), expected);
+        // The entry and the `,` after it fit exactly.
+        assert_eq!(with(Some(41)), expected);
+        let expected = "\
+(
+    0 = (0 = 1, 1 = 2),
+    1 = [
+        10,
+        20,
+        30,
+        40,
+        50,
+        60,
+        70,
+        80,
+    ],
+    2 = (
+        0 = (0 = 3),
+        1 = \"日本語日本語日本語\",
+    ),
+)";
+        assert_eq!(with(Some(40)), expected);
+
+        // Sorting buffers entries, which are laid out the same.
+        let opts = SatnPrettyOptions {
+            max_line_width: Some(40),
+            satn: SatnOptions {
+                field_order: FieldOrder::Sorted,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(value.to_satn_pretty_with(&opts), expected);
+    }
+
+    #[test]
+    fn satn_len_matches_rendering() {
+        let ty = AlgebraicType::product([
+            ("name", AlgebraicType::String),
+            ("tags", AlgebraicType::array(AlgebraicType::String)),
+            ("kind", AlgebraicType::option(AlgebraicType::F64)),
+        ]);
+        let val = AlgebraicValue::product([
+            "日本 \"quoted\"\n".into(),
+            AlgebraicValue::Array([Box::<str>::from("a"), "b".into()].into()),
+            AlgebraicValue::OptionSome(1.5f64.into()),
+        ]);
+        let values = [
+            AlgebraicValue::unit(),
+            AlgebraicValue::U64(u64::MAX),
+            AlgebraicValue::F32(f32::NAN.into()),
+            AlgebraicValue::String("\u{1}\t".into()),
+            AlgebraicValue::Bytes([0xde, 0xad, 0xbe, 0xef].into()),
+            AlgebraicValue::Array([ArrayValue::from([1u32, 2]), ArrayValue::from([0u32; 0])].into()),
+            AlgebraicValue::sum(1, AlgebraicValue::product([true.into()])),
+        ];
+        for value in &values {
+            assert_eq!(value.satn_len(), value.to_satn().len(), "{value:?}");
+            assert_eq!(value.satn_pretty_len(), value.to_satn_pretty().len(), "{value:?}");
+        }
+        let typed = Typespace::EMPTY.with_type(&ty).with_value(&val);
+        assert_eq!(typed.satn_len(), typed.to_satn().len());
+        assert_eq!(typed.satn_pretty_len(), typed.to_satn_pretty().len());
+        assert_ne!(typed.satn_len(), typed.satn_pretty_len());
+    }
+
+    #[test]
+    fn psql_delimiters_are_configurable() {
+        let ty = ProductType::from([(
+            "point",
+            AlgebraicType::product([("x", AlgebraicType::I32), ("y", AlgebraicType::I32)]),
+        )]);
+        let row = [AlgebraicValue::product([1i32.into(), 2i32.into()])];
+        assert_eq!(psql_row(&ty, &row, &PsqlOptions::default()), ["(x = 1, y = 2)"]);
+
+        let opts = PsqlOptions {
+            delimiters: PsqlDelimiters {
+                open: "{".into(),
+                close: "}".into(),
+                separator: ",".into(),
+                assign: "=".into(),
+            },
+            ..Default::default()
+        };
+        assert_eq!(psql_row(&ty, &row, &opts), ["{x=1,y=2}"]);
+    }
+
+    #[test]
+    fn psql_hex_style_applies_to_special_types() {
+        let ty = ProductType::from([
+            ("bytes", AlgebraicType::bytes()),
+            ("id", AlgebraicType::identity()),
+            (
+                "owner",
+                AlgebraicType::product([("name", AlgebraicType::String), ("id", AlgebraicType::identity())]),
+            ),
+        ]);
+        let id = || AlgebraicValue::product([u256::from(0xabu128).into()]);
+        let row = [
+            AlgebraicValue::Bytes([0xbe, 0xef].into()),
+            id(),
+            AlgebraicValue::product(["x".into(), id()]),
+        ];
+        let with = |prefix, uppercase| {
+            let opts = PsqlOptions {
+                hex_style: Some(HexStyle { prefix, uppercase }),
+                ..Default::default()
+            };
+            psql_row(&ty, &row, &opts)
+        };
+        let zeros = "0".repeat(62);
+
+        // Plain bytes are left alone.
+        assert_eq!(
+            with(false, true),
+            [
+                "0xbeef".to_owned(),
+                format!("{zeros}AB"),
+                format!("(name = \"x\", id = {zeros}AB)"),
+            ]
+        );
+        assert_eq!(
+            with(true, false),
+            [
+                "0xbeef".to_owned(),
+                format!("0x{zeros}ab"),
+                format!("(name = \"x\", id = 0x{zeros}ab)"),
+            ]
+        );
+        assert_eq!(with(true, false), psql_row(&ty, &row, &PsqlOptions::default()));
+    }
+
+    #[test]
+    fn psql_timestamp_format() {
+        let ty = ProductType::from([("ts", AlgebraicType::timestamp())]);
+        let row = |micros: i64| [AlgebraicValue::product([micros.into()])];
+        let opts = PsqlOptions {
+            timestamp_format: Some("%Y-%m-%d %H:%M:%S%.6f%:z".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            psql_row(&ty, &row(1_700_000_000_123_456), &opts),
+            ["2023-11-14 22:13:20.123456+00:00"]
+        );
+        assert_eq!(psql_row(&ty, &row(0), &opts), ["1970-01-01 00:00:00.000000+00:00"]);
+        // Before the Unix epoch, the fraction still counts up from the second.
+        assert_eq!(psql_row(&ty, &row(-1), &opts), ["1969-12-31 23:59:59.999999+00:00"]);
+        assert_eq!(
+            psql_row(&ty, &row(-86_400_000_000 * 365 - 500_000), &opts),
+            ["1968-12-31 23:59:59.500000+00:00"]
+        );
+
+        let opts = SatnOptions::default();
+        let err = render_to_string(None, None, |f| {
+            let mut f = SatnFormatter::new(f, &opts);
+            write_timestamp(&mut f, 0, "%Y-%Q")
+        })
+        .unwrap_err();
+        assert_eq!(err.message(), Some("invalid timestamp format `%Y-%Q`"));
+    }
+
+    #[test]
+    fn psql_custom_types() {
+        let currency = || AlgebraicType::product([("cents", AlgebraicType::I64), ("code", AlgebraicType::String)]);
+        let ty = ProductType::from([
+            ("price", currency()),
+            (
+                "line",
+                AlgebraicType::product([("qty", AlgebraicType::U32), ("total", currency())]),
+            ),
+            ("id", AlgebraicType::identity()),
+        ]);
+        let eur = |cents: i64| AlgebraicValue::product([cents.into(), "EUR".into()]);
+        let row = [
+            eur(1250),
+            AlgebraicValue::product([3u32.into(), eur(3750)]),
+            AlgebraicValue::product([u256::from(0xabu128).into()]),
+        ];
+        let is_currency = |ty: &ProductType| ty.elements.first().is_some_and(|e| e.has_name("cents"));
+        let render = |f: &mut dyn fmt::Write, _: &ProductType, value: &ProductValue| match &*value.elements {
+            [AlgebraicValue::I64(cents), AlgebraicValue::String(code)] => {
+                write!(f, "{}.{:02} {code}", cents / 100, cents % 100)
+            }
+            _ => Err(fmt::Error),
+        };
+
+        let id = format!("0x{}ab", "0".repeat(62));
+        let opts = PsqlOptions {
+            custom_types: vec![PsqlCustomType::new(is_currency, render)],
+            ..Default::default()
+        };
+        assert_eq!(
+            psql_row(&ty, &row, &opts),
+            ["12.50 EUR", "(qty = 3, total = 37.50 EUR)", &id]
+        );
+
+        // The built-in special types take precedence.
+        let opts = PsqlOptions {
+            custom_types: vec![PsqlCustomType::new(|_| true, |f, _, _| f.write_str("custom"))],
+            ..Default::default()
+        };
+        assert_eq!(psql_row(&ty, &row, &opts), ["custom", "custom", &id]);
+    }
+
+    #[test]
+    fn psql_nested_element_types_line_up() {
+        let inner = AlgebraicType::product([("a", AlgebraicType::U32), ("b", AlgebraicType::timestamp())]);
+        let ty = ProductType::from([("id", AlgebraicType::identity()), ("inner", inner.clone())]);
+        let id = AlgebraicValue::product([u256::from(0xabu128).into()]);
+        let inner_val = AlgebraicValue::product([1u32.into(), AlgebraicValue::product([0i64.into()])]);
+        let hex = format!("0x{}ab", "0".repeat(62));
+        let ts = "1970-01-01T00:00:00+00:00";
+
+        let row = [id.clone(), inner_val.clone()];
+        assert_eq!(
+            psql_row(&ty, &row, &PsqlOptions::default()),
+            [hex.clone(), format!("(a = 1, b = {ts})")]
+        );
+
+        // The same, nested in a column.
+        let col = ProductType::from([("col", AlgebraicType::Product(ty))]);
+        let row = [AlgebraicValue::product([id, inner_val])];
+        assert_eq!(
+            psql_row(&col, &row, &PsqlOptions::default()),
+            [format!("(id = {hex}, inner = (a = 1, b = {ts}))")]
+        );
+
+        // A field named like a special tag mustn't shift the types of those after it.
+        let ty = ProductType::from([(
+            "col",
+            AlgebraicType::product([(IDENTITY_TAG, AlgebraicType::U256), ("b", AlgebraicType::timestamp())]),
+        )]);
+        let row = [AlgebraicValue::product([
+            u256::from(0xabu128).into(),
+            AlgebraicValue::product([0i64.into()]),
+        ])];
+        assert!(psql_row(&ty, &row, &PsqlOptions::default())[0].ends_with(&format!("b = {ts})")));
+    }
+
+    #[test]
+    fn write_satn_into_appends() {
+        let values = [
+            AlgebraicValue::product([1u32.into(), AlgebraicValue::String("a".into())]),
+            AlgebraicValue::product([AlgebraicValue::product([2u8.into()])]),
+        ];
+        let mut buf = String::from("> ");
+        let mut pretty = String::from("> ");
+        for value in &values {
+            value.write_satn_into(&mut buf);
+            value.write_satn_pretty_into(&mut pretty);
+        }
+        assert_eq!(buf, format!("> {}{}", values[0].to_satn(), values[1].to_satn()));
+        assert_eq!(
+            pretty,
+            format!("> {}{}", values[0].to_satn_pretty(), values[1].to_satn_pretty())
+        );
+
+        // The buffer is reused, so a cleared one needs no more room for the same values.
+        let capacity = buf.capacity();
+        buf.clear();
+        for value in &values {
+            value.write_satn_into(&mut buf);
+        }
+        assert_eq!(buf.capacity(), capacity);
+    }
+
+    #[test]
+    fn canonical_has_no_insignificant_whitespace() {
+        let ty = AlgebraicType::product([
+            ("name", AlgebraicType::String),
+            ("score", AlgebraicType::F64),
+            ("raw", AlgebraicType::bytes()),
+            (
+                "nested",
+                AlgebraicType::product([AlgebraicType::F32, AlgebraicType::F32]),
+            ),
+        ]);
+        let val = AlgebraicValue::product([
+            AlgebraicValue::String("a, b = c".into()),
+            AlgebraicValue::F64(0.1f64.into()),
+            AlgebraicValue::Bytes([0xBE, 0xEF].into()),
+            AlgebraicValue::product([
+                AlgebraicValue::F32((-f32::NAN).into()),
+                AlgebraicValue::F32(f32::NEG_INFINITY.into()),
+            ]),
+        ]);
+        let typed = Typespace::EMPTY.with_type(&ty).with_value(&val);
+        assert_eq!(
+            typed.to_satn_canonical(),
+            r#"(name="a, b = c",score=0.1,raw=0xbeef,nested=(0=NaN,1=-inf))"#
+        );
+        assert_eq!(
+            typed.to_satn(),
+            r#"(name = "a, b = c", score = 0.1, raw = 0xbeef, nested = (0 = NaN, 1 = -inf))"#
+        );
+    }
+
+    #[test]
+    fn variant_labels() {
+        let unnamed = AlgebraicType::sum([AlgebraicType::U8, AlgebraicType::String]);
+        let value = AlgebraicValue::sum(1, AlgebraicValue::String("x".into()));
+        let typed = Typespace::EMPTY.with_type(&unnamed).with_value(&value);
+        assert_eq!(typed.to_satn(), r#"(1 = "x")"#);
+        assert_eq!(typed.to_satn_canonical(), r#"(1="x")"#);
+        assert_eq!(from_satn(&unnamed, &typed.to_satn()), Ok(value));
+
+        let named = AlgebraicType::sum([("my variant", AlgebraicType::U8), ("plain", AlgebraicType::U8)]);
+        for (tag, expected) in [(0, r#"("my variant" = 5)"#), (1, "(plain = 5)")] {
+            let value = AlgebraicValue::sum(tag, 5u8.into());
+            let typed = Typespace::EMPTY.with_type(&named).with_value(&value);
+            assert_eq!(typed.to_satn(), expected);
+            assert_eq!(from_satn(&named, &typed.to_satn()), Ok(value));
+        }
+    }
+
+    #[test]
+    fn primitive_arrays_match_elementwise() {
+        /// A `u32` serialized like any other array element.
+        struct Elementwise(u32);
+        impl ser::Serialize for Elementwise {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_u32(self.0)
+            }
+        }
+
+        let nums = [0, 1, u32::MAX];
+        let elementwise = nums.map(Elementwise);
+        let nested = AlgebraicValue::product([AlgebraicValue::Array(nums.into())]);
+        for opts in [
+            SatnOptions::default(),
+            SatnOptions::canonical(),
+            SatnOptions {
+                max_array_elements: Some(2),
+                ..Default::default()
+            },
+            SatnOptions {
+                max_depth: Some(0),
+                depth_exceeded: DepthExceeded::Elide,
+                ..Default::default()
+            },
+        ] {
+            assert_eq!(nums.to_satn_with(&opts), elementwise.to_satn_with(&opts), "{opts:?}");
+        }
+        assert_eq!(nums.to_satn(), "[0, 1, 4294967295]");
+        assert_eq!(nums.to_satn_pretty(), elementwise.to_satn_pretty());
+        assert_eq!(nested.to_satn(), "(0 = [0, 1, 4294967295])");
+        assert_eq!([true, false].to_satn(), "[true, false]");
+        assert_eq!([0i8; 0].to_satn(), "[]");
+    }
+}
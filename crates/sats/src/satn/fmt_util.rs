@@ -0,0 +1,418 @@
+//! The layout machinery of the SATN data format, for reuse by other [`Serializer`](crate::ser::Serializer)s.
+//!
+//! A serializer formats into a [`Writer`], obtained from [`Writer::with`] or [`Writer::with_sink`],
+//! and formats the elements of each product or array as entries of an [`EntryWrapper`],
+//! which separates them and, when pretty printing, puts each on its own indented line,
+//! so it gets the same normal and pretty output as [`Satn`](super::Satn) for free.
+//! See the [JSON format](super::json) for an example.
+//!
+//! This API is semi-stable:
+//! the items here and their documented behavior are only changed in breaking releases of this crate,
+//! but the exact pretty printed output, e.g., when entries are kept on one line, may be refined at any time.
+//!
+//! This module only depends on `core`, `alloc`, and `unicode-width`, not on `std` or the rest of this crate.
+
+use alloc::string::String;
+use core::fmt::{self, Write as _};
+
+/// The indentation per nesting level used when pretty printing, unless configured otherwise.
+const DEFAULT_INDENT: &str = "    ";
+
+/// How to separate the entries of a list put on their own lines when pretty printing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PrettySep {
+    /// A comma and a newline, e.g., `[\n    1,\n    2,\n]`.
+    #[default]
+    Comma,
+    /// A newline only, e.g., `[\n    1\n    2\n]`, which makes long entries, e.g., byte arrays, easier to diff.
+    ///
+    /// The output is not valid SATN then, so it can't be read back with [`from_satn`](super::from_satn).
+    /// Entries kept on one line are still separated by commas.
+    Newline,
+}
+
+/// How to lay out pretty printed output.
+#[derive(Clone, Copy)]
+pub struct Layout<'a> {
+    /// The indentation per nesting level.
+    pub indent: &'a str,
+    /// The maximum width of lines, see [`SatnPrettyOptions::max_line_width`](super::SatnPrettyOptions::max_line_width).
+    pub max_line_width: Option<usize>,
+    /// Whether to only separate entries, see [`SatnPrettyOptions::strict`](super::SatnPrettyOptions::strict).
+    pub strict: bool,
+    /// How to separate the elements of arrays, see [`SatnPrettyOptions::array_sep`](super::SatnPrettyOptions::array_sep).
+    pub array_sep: PrettySep,
+}
+
+impl Layout<'_> {
+    /// The layout used when pretty printing, unless configured otherwise.
+    pub const DEFAULT: Layout<'static> = Layout {
+        indent: DEFAULT_INDENT,
+        max_line_width: None,
+        strict: false,
+        array_sep: PrettySep::Comma,
+    };
+}
+
+/// Wraps a writer for formatting lists separated by `sep` into it.
+pub struct EntryWrapper<'a, 'f> {
+    /// The writer we're formatting into.
+    fmt: Writer<'a, 'f>,
+    /// The separator between entries on one line, e.g., `, `.
+    /// When pretty printing, the trailing whitespace of it is replaced by a newline.
+    sep: &'a str,
+    /// Whether there were any fields.
+    /// Initially `false` and then `true` after calling [`.entry(..)`](EntryWrapper::entry).
+    has_fields: bool,
+    /// Whether pretty printing ends every entry with `sep`, as SATN allows,
+    /// rather than only separating entries, as JSON requires.
+    /// Ignored when pretty printing with a [strict](Layout::strict) layout.
+    trailing: bool,
+    /// Whether the entries are the elements of an array, see [`EntryWrapper::for_array`].
+    array: bool,
+}
+
+impl<'a, 'f> EntryWrapper<'a, 'f> {
+    /// Constructs the entry wrapper using the writer `fmt`, separating entries by `, `.
+    pub fn new(fmt: Writer<'a, 'f>) -> Self {
+        Self::with_separator(fmt, ", ")
+    }
+
+    /// Constructs the entry wrapper using the writer `fmt`, separating entries by `sep`.
+    ///
+    /// Call [`.end()`](EntryWrapper::end) before closing the structure,
+    /// in case the layout is [strict](Layout::strict).
+    pub fn with_separator(fmt: Writer<'a, 'f>, sep: &'a str) -> Self {
+        Self {
+            fmt,
+            sep,
+            has_fields: false,
+            trailing: true,
+            array: false,
+        }
+    }
+
+    /// Constructs the entry wrapper using the writer `fmt`, separating entries by `, `,
+    /// but never writing a separator after the last entry.
+    ///
+    /// Call [`.end()`](EntryWrapper::end) before closing the structure.
+    pub fn separated(fmt: Writer<'a, 'f>) -> Self {
+        Self {
+            trailing: false,
+            ..Self::new(fmt)
+        }
+    }
+
+    /// Marks the entries as the elements of an array,
+    /// which are then separated by newlines alone when pretty printing with [`PrettySep::Newline`].
+    pub fn for_array(self) -> Self {
+        Self { array: true, ..self }
+    }
+
+    /// Returns the writer the entries are formatted into, e.g., to close the structure after the last entry.
+    pub fn writer(&mut self) -> &mut Writer<'a, 'f> {
+        &mut self.fmt
+    }
+
+    /// Formats another entry in the larger structure.
+    ///
+    /// The formatting for the element / entry itself is provided by the function `entry`.
+    /// When pretty printing with a [`SatnPrettyOptions::max_line_width`](super::SatnPrettyOptions::max_line_width),
+    /// `entry` is first called with a dry run, measuring whether it fits on one line.
+    pub fn entry<E: From<fmt::Error>>(&mut self, mut entry: impl FnMut(Writer) -> Result<(), E>) -> Result<(), E> {
+        let res = (|| match &mut self.fmt {
+            Writer::Pretty(f) => {
+                let sep = match f.state.array_sep {
+                    PrettySep::Newline if self.array => "",
+                    _ => self.sep.trim_end(),
+                };
+                let trailing = self.trailing && !f.state.strict;
+                if self.has_fields && !trailing {
+                    f.write_str(sep)?;
+                }
+                if !self.has_fields || !trailing {
+                    f.write_char('\n')?;
+                }
+                // Keep the entry on one line if it fits there along with the separator after it.
+                let width = f.state.entry_column(f.indent) + display_width(sep);
+                let fits = (f.state.max_line_width).is_some_and(|max| fits(max.saturating_sub(width), &mut entry));
+                f.state.indent += 1;
+                if fits {
+                    entry(Writer::Normal(&mut *f))?;
+                } else {
+                    entry(Writer::Pretty(f.as_mut()))?;
+                }
+                if trailing {
+                    f.write_str(sep)?;
+                    f.write_char('\n')?;
+                }
+                f.state.indent -= 1;
+                Ok(())
+            }
+            Writer::Normal(f) => {
+                if self.has_fields {
+                    f.write_str(self.sep)?;
+                }
+                entry(Writer::Normal(f))
+            }
+        })();
+        self.has_fields = true;
+        res
+    }
+
+    /// Finishes the list, moving to a new line when pretty printing a [separated](EntryWrapper::separated) list,
+    /// or any list with a [strict](Layout::strict) layout.
+    pub fn end(&mut self) -> fmt::Result {
+        match &mut self.fmt {
+            Writer::Pretty(f) if self.has_fields && (!self.trailing || f.state.strict) => f.write_char('\n'),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// An implementation of [`fmt::Write`] supporting indented and non-idented formatting.
+pub enum Writer<'a, 'f> {
+    /// Writes to the underlying sink as is, i.e. plain formatting.
+    Normal(&'a mut (dyn fmt::Write + 'f)),
+    /// Uses indented formatting.
+    Pretty(IndentedWriter<'a, 'f>),
+}
+
+impl<'f> Writer<'_, 'f> {
+    /// Provided with a formatter `f`, runs `func` provided with a `Writer`,
+    /// which is laid out per `layout` when pretty printing.
+    pub fn with<E: From<fmt::Error>>(
+        f: &mut fmt::Formatter<'_>,
+        layout: Layout<'_>,
+        func: impl FnMut(Writer<'_, '_>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        // We use `alternate`, i.e., the `#` flag to let the user trigger pretty printing.
+        let layout = f.alternate().then_some(layout);
+        Writer::with_sink(f, layout, func)
+    }
+
+    /// Provided with any `sink`, runs `func` provided with a `Writer` into it,
+    /// which pretty prints, laid out per `layout`, if provided.
+    pub fn with_sink<E: From<fmt::Error>>(
+        sink: &mut dyn fmt::Write,
+        layout: Option<Layout<'_>>,
+        mut func: impl FnMut(Writer<'_, '_>) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let Some(Layout {
+            indent,
+            max_line_width,
+            strict,
+            array_sep,
+        }) = layout
+        else {
+            return func(Writer::Normal(sink));
+        };
+        // Keep the whole value on one line if it fits there.
+        if max_line_width.is_some_and(|max| fits(max, &mut func)) {
+            return func(Writer::Normal(sink));
+        }
+        let mut state = IndentState {
+            indent: 0,
+            on_newline: true,
+            margin: 0,
+            max_line_width,
+            strict,
+            array_sep,
+        };
+        func(Writer::Pretty(IndentedWriter {
+            f: sink,
+            state: &mut state,
+            indent,
+        }))
+    }
+
+    /// Renders the entry `func` into a `String`, laid out as if written into `self` directly,
+    /// to write it later into `self` with [`EntryWrapper::entry`], e.g., after sorting entries.
+    pub fn render_entry<E: From<fmt::Error>>(
+        &self,
+        mut func: impl FnMut(Writer<'_, '_>) -> Result<(), E>,
+    ) -> Result<String, E> {
+        let mut out = String::new();
+        match self {
+            Writer::Normal(_) => func(Writer::Normal(&mut out))?,
+            Writer::Pretty(f) => {
+                // Like `EntryWrapper::entry`, keep the entry on one line if it fits there.
+                let margin = f.state.entry_column(f.indent);
+                let (max_line_width, strict, array_sep) = (f.state.max_line_width, f.state.strict, f.state.array_sep);
+                if max_line_width.is_some_and(|max| fits(max.saturating_sub(margin + 1), &mut func)) {
+                    func(Writer::Normal(&mut out))?;
+                } else {
+                    let mut state = IndentState {
+                        indent: 0,
+                        on_newline: true,
+                        margin,
+                        max_line_width,
+                        strict,
+                        array_sep,
+                    };
+                    func(Writer::Pretty(IndentedWriter {
+                        f: &mut out,
+                        state: &mut state,
+                        indent: f.indent,
+                    }))?;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns a sub-writer without moving `self`.
+    pub fn as_mut(&mut self) -> Writer<'_, 'f> {
+        match self {
+            Writer::Normal(f) => Writer::Normal(&mut **f),
+            Writer::Pretty(f) => Writer::Pretty(f.as_mut()),
+        }
+    }
+}
+
+/// A writer that adds indentation atop of the underlying sink.
+pub struct IndentedWriter<'a, 'f> {
+    f: &'a mut (dyn fmt::Write + 'f),
+    state: &'a mut IndentState,
+    /// The indentation per nesting level.
+    indent: &'a str,
+}
+
+/// The indentation state.
+struct IndentState {
+    /// Number of tab indentations to make.
+    indent: u32,
+    /// Whether we were last on a newline.
+    on_newline: bool,
+    /// The width of the indentation the output will be nested in, when rendered to be written later.
+    margin: usize,
+    /// The maximum width of lines, see [`SatnPrettyOptions::max_line_width`](super::SatnPrettyOptions::max_line_width).
+    max_line_width: Option<usize>,
+    /// Whether entries are only separated, see [`Layout::strict`].
+    strict: bool,
+    /// How to separate the elements of arrays, see [`Layout::array_sep`].
+    array_sep: PrettySep,
+}
+
+impl IndentState {
+    /// Returns the column at which the entries of the innermost list start,
+    /// which are one level deeper than the list, when indenting by `indent` per level.
+    fn entry_column(&self, indent: &str) -> usize {
+        self.margin + (self.indent as usize + 1) * display_width(indent)
+    }
+}
+
+/// Returns whether the output of `func` fits on one line of `width` columns, by a dry run.
+fn fits<E>(width: usize, func: impl FnOnce(Writer<'_, '_>) -> Result<(), E>) -> bool {
+    func(Writer::Normal(&mut WidthCounter { remaining: width })).is_ok()
+}
+
+/// A writer that only measures the output, failing once it doesn't fit on one line.
+struct WidthCounter {
+    /// The number of columns left on the line.
+    remaining: usize,
+}
+
+impl fmt::Write for WidthCounter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let width = display_width(s);
+        if s.contains('\n') || width > self.remaining {
+            return Err(fmt::Error);
+        }
+        self.remaining -= width;
+        Ok(())
+    }
+}
+
+impl<'f> IndentedWriter<'_, 'f> {
+    /// Returns a sub-writer without moving `self`.
+    pub fn as_mut(&mut self) -> IndentedWriter<'_, 'f> {
+        IndentedWriter {
+            f: self.f,
+            state: self.state,
+            indent: self.indent,
+        }
+    }
+}
+
+impl fmt::Write for IndentedWriter<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for s in s.split_inclusive('\n') {
+            if self.state.on_newline {
+                // Indent by the unit times the indentation level.
+                for _ in 0..self.state.indent {
+                    self.f.write_str(self.indent)?;
+                }
+            }
+
+            self.state.on_newline = s.ends_with('\n');
+            self.f.write_str(s)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Write for Writer<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self {
+            Writer::Normal(f) => f.write_str(s),
+            Writer::Pretty(f) => f.write_str(s),
+        }
+    }
+}
+
+/// Returns the number of terminal columns `s` occupies when displayed.
+///
+/// Unlike `s.len()` or `s.chars().count()`,
+/// this accounts for wide characters, e.g., CJK, taking up two columns,
+/// and zero-width characters, e.g., combining marks, taking up none.
+/// The ANSI escape codes of [`SatnOptions::color`](super::SatnOptions::color) take up no columns either.
+/// Any feature measuring rendered SATN, e.g., for wrapping or alignment, should use this.
+pub fn display_width(s: &str) -> usize {
+    if !s.contains('\x1b') {
+        return unicode_width::UnicodeWidthStr::width(s);
+    }
+    s.split('\x1b')
+        .enumerate()
+        .map(|(i, part)| {
+            // Each part after the first starts with the rest of an escape code, e.g., `[32m`.
+            let text = match part.strip_prefix('[').and_then(|code| code.split_once('m')) {
+                Some((_, text)) if i > 0 => text,
+                _ => part,
+            };
+            unicode_width::UnicodeWidthStr::width(text)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_laid_out() {
+        let render = |layout| {
+            let mut out = String::new();
+            Writer::with_sink(&mut out, layout, |mut f| {
+                f.write_char('[')?;
+                let mut entries = EntryWrapper::separated(f);
+                for i in 1..=2 {
+                    entries.entry(|mut f| write!(f, "{i}"))?;
+                }
+                entries.end()?;
+                entries.writer().write_char(']')
+            })
+            .unwrap();
+            out
+        };
+        assert_eq!(render(None), "[1, 2]");
+        assert_eq!(render(Some(Layout::DEFAULT)), "[\n    1,\n    2\n]");
+        let narrow = Layout {
+            indent: "  ",
+            max_line_width: Some(6),
+            strict: false,
+            array_sep: PrettySep::Comma,
+        };
+        assert_eq!(render(Some(narrow)), "[1, 2]");
+    }
+}
@@ -0,0 +1,354 @@
+//! A JSON data format for [`Serialize`](ser::Serialize) values, laid out like SATN.
+//!
+//! The output is valid JSON for any [`AlgebraicValue`](crate::AlgebraicValue):
+//! - named products are objects and unnamed products are arrays,
+//!   while products with some named fields are objects keyed by index for the unnamed ones,
+//! - sums are objects with a single key, the variant name or tag, mapping to the payload,
+//! - arrays are arrays, except for byte arrays, which are base64 strings,
+//! - NaN and the infinities, which JSON lacks, are the strings `"NaN"`, `"+inf"`, and `"-inf"`.
+
+use super::fmt_util::{EntryWrapper, Layout, Writer};
+use super::{render_to_string, SatnError};
+use crate::{i256, ser, u256};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use core::fmt::{self, Write as _};
+
+/// Formats `value` as JSON into the returned `String`.
+pub fn to_string<T: ser::Serialize + ?Sized>(value: &T) -> String {
+    render_to_string(None, None, |f| value.serialize(JsonFormatter { f }))
+        .expect("formatting JSON into a `String` should not fail")
+}
+
+/// Pretty prints `value` as JSON into the returned `String`.
+pub fn to_string_pretty<T: ser::Serialize + ?Sized>(value: &T) -> String {
+    render_to_string(Some(Layout::DEFAULT), None, |f| value.serialize(JsonFormatter { f }))
+        .expect("formatting JSON into a `String` should not fail")
+}
+
+/// A wrapper around a `T: Serialize` providing a `Display` implementation
+/// that formats `T` as JSON, pretty printing it when the `#` flag is used.
+pub struct Json<T: ?Sized>(pub T);
+
+impl<T: ser::Serialize + ?Sized> fmt::Display for Json<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Writer::with(f, Layout::DEFAULT, |f| self.0.serialize(JsonFormatter { f }))?;
+        Ok(())
+    }
+}
+
+/// Provides the JSON data format implementing [`Serializer`](ser::Serializer).
+struct JsonFormatter<'a, 'f> {
+    /// The sink / writer / output / formatter.
+    f: Writer<'a, 'f>,
+}
+
+impl JsonFormatter<'_, '_> {
+    /// Writes `v` as a JSON number, or as a string if it has no JSON representation.
+    ///
+    /// An `f32` is printed as itself, e.g., `0.1`, rather than as the `f64` it widens to.
+    fn write_float(&mut self, v: impl Copy + Into<f64> + fmt::Display) -> Result<(), SatnError> {
+        let class: f64 = v.into();
+        if class.is_nan() {
+            self.f.write_str("\"NaN\"")?;
+        } else if class.is_infinite() {
+            let inf = if class.is_sign_positive() {
+                "\"+inf\""
+            } else {
+                "\"-inf\""
+            };
+            self.f.write_str(inf)?;
+        } else {
+            write!(self.f, "{v}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `v` as a JSON string, escaping quotes, backslashes, and control characters.
+fn write_json_str(f: &mut Writer<'_, '_>, v: &str) -> fmt::Result {
+    f.write_char('"')?;
+    // The start of the run of characters that need no escaping.
+    let mut run = 0;
+    for (idx, c) in v.char_indices() {
+        if !matches!(c, '"' | '\\') && !c.is_control() {
+            continue;
+        }
+        f.write_str(&v[run..idx])?;
+        match c {
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{8}' => f.write_str("\\b")?,
+            '\u{c}' => f.write_str("\\f")?,
+            '"' | '\\' => write!(f, "\\{c}")?,
+            // JSON only has `\uXXXX` escapes, which suffice as control characters are in the BMP.
+            _ => write!(f, "\\u{:04x}", u32::from(c))?,
+        }
+        run = idx + c.len_utf8();
+    }
+    f.write_str(&v[run..])?;
+    f.write_char('"')
+}
+
+impl<'a, 'f> ser::Serializer for JsonFormatter<'a, 'f> {
+    type Ok = ();
+    type Error = SatnError;
+    type SerializeArray = JsonArrayFormatter<'a, 'f>;
+    type SerializeSeqProduct = JsonArrayFormatter<'a, 'f>;
+    type SerializeNamedProduct = JsonObjectFormatter<'a, 'f>;
+
+    fn serialize_bool(mut self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_u8(mut self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_u16(mut self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_u32(mut self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_u64(mut self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_u128(mut self, v: u128) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_u256(mut self, v: u256) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_i8(mut self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_i16(mut self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_i32(mut self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_i64(mut self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_i128(mut self, v: i128) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_i256(mut self, v: i256) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "{v}")?)
+    }
+    fn serialize_f32(mut self, v: f32) -> Result<Self::Ok, Self::Error> {
+        self.write_float(v)
+    }
+    fn serialize_f64(mut self, v: f64) -> Result<Self::Ok, Self::Error> {
+        self.write_float(v)
+    }
+
+    fn serialize_str(mut self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(write_json_str(&mut self.f, v)?)
+    }
+
+    fn serialize_bytes(mut self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(write!(self.f, "\"{}\"", BASE64_STANDARD.encode(v))?)
+    }
+
+    fn serialize_array(mut self, _len: usize) -> Result<Self::SerializeArray, Self::Error> {
+        self.f.write_char('[')?; // Closed via `.end()`.
+        Ok(JsonArrayFormatter {
+            f: EntryWrapper::separated(self.f),
+        })
+    }
+
+    fn serialize_seq_product(self, len: usize) -> Result<Self::SerializeSeqProduct, Self::Error> {
+        self.serialize_array(len)
+    }
+
+    fn serialize_named_product(self, _len: usize) -> Result<Self::SerializeNamedProduct, Self::Error> {
+        Ok(JsonObjectFormatter {
+            f: self.f,
+            fields: Vec::new(),
+        })
+    }
+
+    fn serialize_variant<T: ser::Serialize + ?Sized>(
+        mut self,
+        tag: u8,
+        name: Option<&str>,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.f.write_char('{')?;
+        let mut f = EntryWrapper::separated(self.f.as_mut());
+        f.entry(|mut f| {
+            match name {
+                Some(name) => write_json_str(&mut f, name)?,
+                None => write!(f, "\"{tag}\"")?,
+            }
+            f.write_str(": ")?;
+            value.serialize(JsonFormatter { f })
+        })?;
+        f.end()?;
+        self.f.write_char('}')?;
+        Ok(())
+    }
+}
+
+/// Defines the JSON formatting for arrays and unnamed products.
+pub(super) struct JsonArrayFormatter<'a, 'f> {
+    /// The formatter for each element separating elements by a `,`.
+    f: EntryWrapper<'a, 'f>,
+}
+
+impl ser::SerializeArray for JsonArrayFormatter<'_, '_> {
+    type Ok = ();
+    type Error = SatnError;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, elem: &T) -> Result<(), Self::Error> {
+        self.f.entry(|f| elem.serialize(JsonFormatter { f }))
+    }
+
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        self.f.end()?;
+        self.f.writer().write_char(']')?;
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeqProduct for JsonArrayFormatter<'_, '_> {
+    type Ok = ();
+    type Error = SatnError;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, elem: &T) -> Result<(), Self::Error> {
+        ser::SerializeArray::serialize_element(self, elem)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        ser::SerializeArray::end(self)
+    }
+}
+
+/// Defines the JSON formatting for named products.
+///
+/// The elements are buffered, as a product is only an object
+/// if any of its elements is named, and an array otherwise.
+pub(super) struct JsonObjectFormatter<'a, 'f> {
+    /// The sink / writer / output / formatter.
+    f: Writer<'a, 'f>,
+    /// The name and rendering of each element.
+    fields: Vec<(Option<Box<str>>, String)>,
+}
+
+impl ser::SerializeNamedProduct for JsonObjectFormatter<'_, '_> {
+    type Ok = ();
+    type Error = SatnError;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(
+        &mut self,
+        name: Option<&str>,
+        elem: &T,
+    ) -> Result<(), Self::Error> {
+        let rendered = self.f.render_entry(|f| elem.serialize(JsonFormatter { f }))?;
+        self.fields.push((name.map(Into::into), rendered));
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        let is_object = self.fields.iter().any(|(name, _)| name.is_some());
+        self.f.write_char(if is_object { '{' } else { '[' })?;
+        let mut f = EntryWrapper::separated(self.f.as_mut());
+        for (idx, (name, rendered)) in self.fields.iter().enumerate() {
+            f.entry(|mut f| {
+                if is_object {
+                    match name {
+                        Some(name) => write_json_str(&mut f, name)?,
+                        None => write!(f, "\"{idx}\"")?,
+                    }
+                    f.write_str(": ")?;
+                }
+                f.write_str(rendered)
+            })?;
+        }
+        f.end()?;
+        self.f.write_char(if is_object { '}' } else { ']' })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_string, to_string_pretty};
+    use crate::{AlgebraicType, AlgebraicValue, ArrayValue, ProductValue, Typespace};
+
+    fn json_of(ty: &AlgebraicType, val: &AlgebraicValue) -> String {
+        to_string(&Typespace::EMPTY.with_type(ty).with_value(val))
+    }
+
+    #[test]
+    fn products_are_objects_or_arrays() {
+        let ty = AlgebraicType::product([("a", AlgebraicType::U32), ("b", AlgebraicType::String)]);
+        let val = AlgebraicValue::product([1u32.into(), "x".into()]);
+        assert_eq!(json_of(&ty, &val), r#"{"a": 1, "b": "x"}"#);
+
+        let ty = AlgebraicType::product([AlgebraicType::U32, AlgebraicType::Bool]);
+        let val = AlgebraicValue::product([1u32.into(), true.into()]);
+        assert_eq!(json_of(&ty, &val), "[1, true]");
+
+        let ty = AlgebraicType::product([(Some("a"), AlgebraicType::U8), (None, AlgebraicType::U8)]);
+        let val = AlgebraicValue::product([1u8.into(), 2u8.into()]);
+        assert_eq!(json_of(&ty, &val), r#"{"a": 1, "1": 2}"#);
+
+        let val = ProductValue::from_iter([1u32.into(), "y".into()]);
+        assert_eq!(to_string(&val), r#"[1, "y"]"#);
+    }
+
+    #[test]
+    fn bytes_strings_and_floats() {
+        let val = AlgebraicValue::Bytes([1u8, 2, 3].into());
+        assert_eq!(to_string(&val), r#""AQID""#);
+
+        let val = AlgebraicValue::String("a\"b\\c\n\u{1}".into());
+        assert_eq!(to_string(&val), r#""a\"b\\c\n\u0001""#);
+
+        let val = AlgebraicValue::Array([1.5f64, f64::NAN, f64::NEG_INFINITY].into());
+        assert_eq!(to_string(&val), r#"[1.5, "NaN", "-inf"]"#);
+        let val = AlgebraicValue::product([0.1f32.into(), f32::INFINITY.into()]);
+        assert_eq!(to_string(&val), r#"[0.1, "+inf"]"#);
+    }
+
+    #[test]
+    fn variants_are_single_key_objects() {
+        let ty = AlgebraicType::option(AlgebraicType::U32);
+        let val = AlgebraicValue::OptionSome(5u32.into());
+        assert_eq!(json_of(&ty, &val), r#"{"some": 5}"#);
+        let val = AlgebraicValue::OptionNone();
+        assert_eq!(json_of(&ty, &val), r#"{"none": []}"#);
+
+        let val = AlgebraicValue::sum(1, 7u32.into());
+        assert_eq!(to_string(&val), r#"{"1": 7}"#);
+    }
+
+    #[test]
+    fn pretty_output() {
+        let ty = AlgebraicType::product([
+            ("a", AlgebraicType::array(AlgebraicType::U32)),
+            ("b", AlgebraicType::product([("c", AlgebraicType::Bool)])),
+            ("d", AlgebraicType::array(AlgebraicType::U32)),
+        ]);
+        let val = AlgebraicValue::product([
+            AlgebraicValue::Array([1u32, 2].into()),
+            AlgebraicValue::product([true.into()]),
+            AlgebraicValue::Array(ArrayValue::from([0u32; 0])),
+        ]);
+        let pretty = to_string_pretty(&Typespace::EMPTY.with_type(&ty).with_value(&val));
+        let expected = "\
+{
+    \"a\": [
+        1,
+        2
+    ],
+    \"b\": {
+        \"c\": true
+    },
+    \"d\": []
+}";
+        assert_eq!(pretty, expected);
+    }
+}
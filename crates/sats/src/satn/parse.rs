@@ -0,0 +1,577 @@
+//! Parsing of SATN text back into [`AlgebraicValue`]s.
+
+use crate::{i256, u256, AlgebraicType, AlgebraicValue, ArrayValue, ProductType, ProductValue, SumType, SumValue};
+use crate::{F32, F64};
+use core::str::FromStr;
+use std::borrow::Cow;
+
+/// An error that occurs when parsing SATN with [`from_satn`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("{message} at offset {offset}")]
+pub struct SatnParseError {
+    /// The byte offset into the input at which the error occurred.
+    pub offset: usize,
+    /// What went wrong.
+    pub message: Cow<'static, str>,
+}
+
+/// Parses `input`, in the SATN format, as a value of type `ty`.
+///
+/// This is the inverse of [`Satn::to_satn`](super::Satn::to_satn)
+/// and [`Satn::to_satn_pretty`](super::Satn::to_satn_pretty), accepting:
+/// - products as `(name = value, ...)`, in any order,
+///   where unnamed fields are labeled by their 0-based index,
+///   and the empty product as `()`,
+/// - sums as `(variant = value)`, where the variant may also be given by its tag,
+/// - arrays as `[elem, ...]`, and byte arrays also as `0x` followed by hex digits,
+/// - strings as `"..."`, where `"` and `\` are escaped by a `\`,
+///   and control characters as `\n`, `\r`, `\t`, or `\u{hex}`,
+/// - numbers and booleans as written by Rust, and floats also in quotes, e.g., `"NaN"`,
+/// - timestamps and time durations also as strings, as written with [`TimeFmt::Quoted`](super::TimeFmt::Quoted),
+///   i.e., timestamps in RFC 3339, e.g., `"1970-01-01T00:00:01Z"`, and durations in seconds, e.g., `"-1.5"`.
+///
+/// Trailing commas are allowed, as is whitespace, including newlines, between tokens
+/// and around the value, e.g., the final newline of a file of pretty printed SATN.
+/// A leading byte order mark, as some editors save, is skipped,
+/// but still counted in the offsets of errors.
+///
+/// Type references are rejected, as there's no typespace to resolve them in.
+pub fn from_satn(ty: &AlgebraicType, input: &str) -> Result<AlgebraicValue, SatnParseError> {
+    let pos = if input.starts_with(BOM) { BOM.len_utf8() } else { 0 };
+    let mut parser = Parser { input, pos };
+    let value = parser.value(ty)?;
+    parser.skip_ws();
+    if !parser.rest().is_empty() {
+        return Err(parser.error("unexpected trailing input"));
+    }
+    Ok(value)
+}
+
+/// The byte order mark, which is meaningless in UTF-8, but may start text saved by editors.
+const BOM: char = '\u{feff}';
+
+/// A recursive descent parser for SATN, driven by the type being parsed.
+struct Parser<'a> {
+    /// The full input.
+    input: &'a str,
+    /// The byte offset into `input` where parsing continues.
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Returns the input yet to be parsed.
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    /// Returns an error at the current position.
+    fn error(&self, message: impl Into<Cow<'static, str>>) -> SatnParseError {
+        self.error_at(self.pos, message)
+    }
+
+    /// Returns an error at `offset`.
+    fn error_at(&self, offset: usize, message: impl Into<Cow<'static, str>>) -> SatnParseError {
+        let message = message.into();
+        SatnParseError { offset, message }
+    }
+
+    /// Skips any whitespace.
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips whitespace and then `token`, if it's next, returning whether it was.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_ws();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    /// Skips whitespace and then `token`, which must be next.
+    fn expect(&mut self, token: &str) -> Result<(), SatnParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{token}`")))
+        }
+    }
+
+    /// Skips whitespace and then returns the offset of and the next word,
+    /// i.e., the run of characters that can make up a number, a boolean, or a name.
+    ///
+    /// The word is empty when no such character is next.
+    fn word(&mut self) -> (usize, &'a str) {
+        self.skip_ws();
+        let start = self.pos;
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '+' | '.')))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        (start, &rest[..len])
+    }
+
+    /// Parses the elements in a `,` separated list closed by `close`, allowing a trailing `,`.
+    fn list<T>(
+        &mut self,
+        close: &str,
+        mut elem: impl FnMut(&mut Self) -> Result<T, SatnParseError>,
+    ) -> Result<Vec<T>, SatnParseError> {
+        let mut elems = Vec::new();
+        while !self.eat(close) {
+            elems.push(elem(self)?);
+            if !self.eat(",") {
+                self.expect(close)?;
+                break;
+            }
+        }
+        Ok(elems)
+    }
+
+    /// Parses a value of type `ty`.
+    fn value(&mut self, ty: &AlgebraicType) -> Result<AlgebraicValue, SatnParseError> {
+        Ok(match ty {
+            AlgebraicType::Ref(r) => return Err(self.error(format!("cannot resolve type reference {r}"))),
+            AlgebraicType::Sum(ty) => self.sum(ty)?.into(),
+            AlgebraicType::Product(ty) => self.product(ty)?.into(),
+            AlgebraicType::Array(ty) => self.array(&ty.elem_ty)?.into(),
+            AlgebraicType::String => self.string()?.into(),
+            AlgebraicType::Bool => self.bool()?.into(),
+            AlgebraicType::I8 => self.number::<i8>()?.into(),
+            AlgebraicType::U8 => self.number::<u8>()?.into(),
+            AlgebraicType::I16 => self.number::<i16>()?.into(),
+            AlgebraicType::U16 => self.number::<u16>()?.into(),
+            AlgebraicType::I32 => self.number::<i32>()?.into(),
+            AlgebraicType::U32 => self.number::<u32>()?.into(),
+            AlgebraicType::I64 => self.number::<i64>()?.into(),
+            AlgebraicType::U64 => self.number::<u64>()?.into(),
+            AlgebraicType::I128 => self.number::<i128>()?.into(),
+            AlgebraicType::U128 => self.number::<u128>()?.into(),
+            AlgebraicType::I256 => self.number::<i256>()?.into(),
+            AlgebraicType::U256 => self.number::<u256>()?.into(),
+            AlgebraicType::F32 => self.float::<f32>()?.into(),
+            AlgebraicType::F64 => self.float::<f64>()?.into(),
+        })
+    }
+
+    /// Parses a sum value `(variant = value)` of type `ty`.
+    fn sum(&mut self, ty: &SumType) -> Result<SumValue, SatnParseError> {
+        self.expect("(")?;
+        self.skip_ws();
+        let start = self.pos;
+        // Names that aren't words are quoted.
+        let quoted = self.rest().starts_with('"').then(|| self.string()).transpose()?;
+        let label = match &quoted {
+            Some(name) => &**name,
+            None => self.word().1,
+        };
+        let tag = match label {
+            _ if quoted.is_some() => ty
+                .get_variant(label)
+                .map(|(tag, _)| tag)
+                .ok_or_else(|| self.error_at(start, format!("no variant {label:?}")))?,
+            // Earlier versions wrote unnamed variants without a label, rather than their tag,
+            // which is only unambiguous when there's a single variant.
+            "" if ty.variants.len() == 1 => 0,
+            "" => return Err(self.error_at(start, "expected a variant name or tag")),
+            _ => ty
+                .get_variant(label)
+                .map(|(tag, _)| tag)
+                .or_else(|| {
+                    label
+                        .parse()
+                        .ok()
+                        .filter(|&tag: &u8| (tag as usize) < ty.variants.len())
+                })
+                .ok_or_else(|| self.error_at(start, format!("no variant `{label}`")))?,
+        };
+        self.expect("=")?;
+        let value = self.value(&ty.variants[tag as usize].algebraic_type)?;
+        self.eat(",");
+        self.expect(")")?;
+        Ok(SumValue::new(tag, value))
+    }
+
+    /// Parses a product value `(name = value, ...)` of type `ty`.
+    fn product(&mut self, ty: &ProductType) -> Result<ProductValue, SatnParseError> {
+        self.skip_ws();
+        if (ty.is_timestamp() || ty.is_time_duration()) && self.rest().starts_with('"') {
+            return self.quoted_time(ty);
+        }
+        self.expect("(")?;
+        let mut fields = vec![None; ty.elements.len()];
+        self.list(")", |this| {
+            let (start, label) = this.word();
+            let idx = ty
+                .index_of_field_name(label)
+                .or_else(|| label.parse().ok().filter(|&idx| idx < fields.len()))
+                .ok_or_else(|| this.error_at(start, format!("no field `{label}`")))?;
+            this.expect("=")?;
+            let value = this.value(&ty.elements[idx].algebraic_type)?;
+            if fields[idx].replace(value).is_some() {
+                return Err(this.error_at(start, format!("duplicate field `{label}`")));
+            }
+            Ok(())
+        })?;
+        fields
+            .into_iter()
+            .enumerate()
+            .map(|(idx, field)| {
+                field.ok_or_else(|| {
+                    let name = ty.elements[idx].name().map_or_else(|| idx.to_string(), Into::into);
+                    self.error(format!("missing field `{name}`"))
+                })
+            })
+            .collect()
+    }
+
+    /// Parses a timestamp or time duration of type `ty` as a string, see [`from_satn`].
+    fn quoted_time(&mut self, ty: &ProductType) -> Result<ProductValue, SatnParseError> {
+        let start = self.pos;
+        let text = self.string()?;
+        let micros = if ty.is_timestamp() {
+            (chrono::DateTime::parse_from_rfc3339(&text).ok())
+                .map(|time| time.timestamp_micros())
+                .ok_or_else(|| self.error_at(start, format!("expected an RFC 3339 timestamp, found {text:?}")))?
+        } else {
+            parse_time_duration(&text)
+                .ok_or_else(|| self.error_at(start, format!("expected a duration in seconds, found {text:?}")))?
+        };
+        Ok([AlgebraicValue::I64(micros)].into())
+    }
+
+    /// Parses an array value `[elem, ...]` with elements of type `elem_ty`.
+    fn array(&mut self, elem_ty: &AlgebraicType) -> Result<ArrayValue, SatnParseError> {
+        if *elem_ty == AlgebraicType::U8 && self.eat("0x") {
+            return self.bytes().map(Into::into);
+        }
+
+        self.expect("[")?;
+        macro_rules! elems {
+            ($elem:expr) => {
+                self.list("]", $elem)?.into_boxed_slice().into()
+            };
+        }
+        Ok(match elem_ty {
+            AlgebraicType::Ref(r) => return Err(self.error(format!("cannot resolve type reference {r}"))),
+            AlgebraicType::Sum(ty) => elems!(|this| this.sum(ty)),
+            AlgebraicType::Product(ty) => elems!(|this| this.product(ty)),
+            AlgebraicType::Array(ty) => elems!(|this| this.array(&ty.elem_ty)),
+            AlgebraicType::String => elems!(Self::string),
+            AlgebraicType::Bool => elems!(Self::bool),
+            AlgebraicType::I8 => elems!(Self::number::<i8>),
+            AlgebraicType::U8 => elems!(Self::number::<u8>),
+            AlgebraicType::I16 => elems!(Self::number::<i16>),
+            AlgebraicType::U16 => elems!(Self::number::<u16>),
+            AlgebraicType::I32 => elems!(Self::number::<i32>),
+            AlgebraicType::U32 => elems!(Self::number::<u32>),
+            AlgebraicType::I64 => elems!(Self::number::<i64>),
+            AlgebraicType::U64 => elems!(Self::number::<u64>),
+            AlgebraicType::I128 => elems!(Self::number::<i128>),
+            AlgebraicType::U128 => elems!(Self::number::<u128>),
+            AlgebraicType::I256 => elems!(Self::number::<i256>),
+            AlgebraicType::U256 => elems!(Self::number::<u256>),
+            AlgebraicType::F32 => elems!(|this| this.float::<f32>().map(F32::from)),
+            AlgebraicType::F64 => elems!(|this| this.float::<f64>().map(F64::from)),
+        })
+    }
+
+    /// Parses the hex digits of a byte array following `0x`.
+    fn bytes(&mut self) -> Result<Box<[u8]>, SatnParseError> {
+        let start = self.pos;
+        let rest = self.rest();
+        let len = rest.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(rest.len());
+        self.pos += len;
+        hex::decode(&rest[..len])
+            .map(Into::into)
+            .map_err(|_| self.error_at(start, "expected an even number of hex digits"))
+    }
+
+    /// Parses a string `"..."`, undoing the escapes written by [`Satn::to_satn`](super::Satn::to_satn).
+    fn string(&mut self) -> Result<Box<str>, SatnParseError> {
+        self.expect("\"")?;
+        let mut string = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((idx, c)) = chars.next() {
+            let c = match c {
+                '"' => {
+                    self.pos += idx + 1;
+                    return Ok(string.into());
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => '\n',
+                    Some((_, 'r')) => '\r',
+                    Some((_, 't')) => '\t',
+                    Some((_, c @ ('"' | '\\'))) => c,
+                    // A `\u{hex}` escape of any other character.
+                    Some((_, 'u')) => chars
+                        .as_str()
+                        .strip_prefix('{')
+                        .and_then(|rest| rest.split_once('}'))
+                        .and_then(|(hex, _)| {
+                            let c = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)?;
+                            chars.nth(hex.len() + 1);
+                            Some(c)
+                        })
+                        .ok_or_else(|| self.error_at(self.pos + idx, "invalid unicode escape"))?,
+                    _ => return Err(self.error_at(self.pos + idx, "invalid escape")),
+                },
+                c => c,
+            };
+            string.push(c);
+        }
+        Err(self.error_at(self.pos - 1, "unterminated string"))
+    }
+
+    /// Parses `true` or `false`.
+    fn bool(&mut self) -> Result<bool, SatnParseError> {
+        match self.word() {
+            (_, "true") => Ok(true),
+            (_, "false") => Ok(false),
+            (start, word) => Err(self.error_at(start, format!("expected `true` or `false`, found `{word}`"))),
+        }
+    }
+
+    /// Parses a number of type `N`.
+    fn number<N: FromStr>(&mut self) -> Result<N, SatnParseError> {
+        let (start, word) = self.word();
+        word.parse()
+            .map_err(|_| self.error_at(start, format!("expected a number, found `{word}`")))
+    }
+
+    /// Parses a float of type `N`, which may be quoted,
+    /// as `NaN` and infinities are with [`NonFinite::Quoted`](super::NonFinite::Quoted).
+    fn float<N: FromStr>(&mut self) -> Result<N, SatnParseError> {
+        if !self.eat("\"") {
+            return self.number();
+        }
+        let float = self.number()?;
+        self.expect("\"")?;
+        Ok(float)
+    }
+}
+
+/// Parses a time duration in seconds, with an optional sign and at most six decimals, e.g., `+1.500000`,
+/// into microseconds.
+fn parse_time_duration(text: &str) -> Option<i64> {
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (secs, frac) = match unsigned.split_once('.') {
+        Some((_, "")) => return None,
+        Some(parts) => parts,
+        None => (unsigned, ""),
+    };
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if secs.is_empty() || !is_digits(secs) || frac.len() > 6 || !is_digits(frac) {
+        return None;
+    }
+    let frac = if frac.is_empty() {
+        0
+    } else {
+        format!("{frac:0<6}").parse::<i128>().ok()?
+    };
+    let micros = secs.parse::<i128>().ok()? * 1_000_000 + frac;
+    i64::try_from(if negative { -micros } else { micros }).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::satn::{Satn as _, SatnFmt, TimeFmt};
+
+    /// Asserts that `value` of type `ty` survives a trip through both plain and pretty SATN.
+    fn assert_round_trip(ty: &AlgebraicType, value: AlgebraicValue) {
+        let typed = crate::Typespace::EMPTY.with_type(ty).with_value(&value);
+        assert_eq!(from_satn(ty, &typed.to_satn()), Ok(value.clone()));
+        assert_eq!(from_satn(ty, &typed.to_satn_pretty()), Ok(value));
+    }
+
+    #[test]
+    fn round_trip() {
+        let ty = AlgebraicType::product([
+            ("id", AlgebraicType::U64),
+            ("name", AlgebraicType::String),
+            ("score", AlgebraicType::F64),
+            ("tags", AlgebraicType::array(AlgebraicType::U32)),
+            ("data", AlgebraicType::bytes()),
+            ("nick", AlgebraicType::option(AlgebraicType::String)),
+            ("unit", AlgebraicType::unit()),
+        ]);
+        let value = AlgebraicValue::product([
+            42u64.into(),
+            "alice".into(),
+            (-1.5f64).into(),
+            AlgebraicValue::Array([1u32, 2, 3].into()),
+            AlgebraicValue::Bytes([0xde, 0xad].into()),
+            AlgebraicValue::OptionSome("al".into()),
+            AlgebraicValue::unit(),
+        ]);
+        assert_round_trip(&ty, value);
+
+        let ty = AlgebraicType::product([AlgebraicType::I8, AlgebraicType::Bool]);
+        assert_round_trip(&ty, AlgebraicValue::product([(-3i8).into(), true.into()]));
+        assert_round_trip(&AlgebraicType::unit(), AlgebraicValue::unit());
+    }
+
+    #[test]
+    fn lenient_input() {
+        let ty = AlgebraicType::product([("a", AlgebraicType::U8), ("b", AlgebraicType::bytes())]);
+        let expected = AlgebraicValue::product([1u8.into(), AlgebraicValue::Bytes([2, 3].into())]);
+        assert_eq!(from_satn(&ty, "(b = [2, 3,], a = 1,)"), Ok(expected.clone()));
+        assert_eq!(from_satn(&ty, " ( 1 = 0x0203 ,\n 0 = 1 ) "), Ok(expected));
+
+        let ty = AlgebraicType::option(AlgebraicType::I32);
+        assert_eq!(from_satn(&ty, "(0 = 7)"), Ok(AlgebraicValue::OptionSome(7i32.into())));
+        assert_eq!(from_satn(&ty, "(none = ())"), Ok(AlgebraicValue::OptionNone()));
+    }
+
+    #[test]
+    fn leading_bom() {
+        let ty = AlgebraicType::product([("a", AlgebraicType::U8)]);
+        let expected = AlgebraicValue::product([1u8.into()]);
+        assert_eq!(from_satn(&ty, "\u{feff}(a = 1)"), Ok(expected.clone()));
+        assert_eq!(from_satn(&ty, "\u{feff}\n  (a = 1)"), Ok(expected));
+        assert_eq!(from_satn(&AlgebraicType::U8, "\u{feff}7"), Ok(7u8.into()));
+        // A BOM is only skipped at the start, and offsets still count it.
+        assert_eq!(from_satn(&ty, "\u{feff}(a = x)").unwrap_err().offset, 8);
+        assert!(from_satn(&ty, "(a = 1)\u{feff}").is_err());
+    }
+
+    #[test]
+    fn surrounding_whitespace() {
+        let ty = AlgebraicType::product([("a", AlgebraicType::U8), ("b", AlgebraicType::String)]);
+        let expected = AlgebraicValue::product([1u8.into(), "x".into()]);
+        assert_eq!(from_satn(&ty, "\t\r\n (a = 1, b = \"x\")"), Ok(expected.clone()));
+        assert_eq!(from_satn(&ty, "(a = 1, b = \"x\") \t\n\n"), Ok(expected));
+        assert_eq!(from_satn(&AlgebraicType::U8, "  7  "), Ok(7u8.into()));
+        assert_eq!(from_satn(&AlgebraicType::String, " \" x \" "), Ok(" x ".into()));
+    }
+
+    #[test]
+    fn final_newline() {
+        let ty = AlgebraicType::product([("a", AlgebraicType::U8), ("b", AlgebraicType::array(AlgebraicType::U8))]);
+        let value = AlgebraicValue::product([1u8.into(), AlgebraicValue::Array([2u8, 3].into())]);
+        // As saved by an editor, e.g., from pretty output.
+        let saved = format!(
+            "{}\n",
+            crate::Typespace::EMPTY
+                .with_type(&ty)
+                .with_value(&value)
+                .to_satn_pretty()
+        );
+        assert_eq!(from_satn(&ty, &saved), Ok(value.clone()));
+        assert_eq!(from_satn(&ty, &saved.replace('\n', "\r\n")), Ok(value.clone()));
+        assert_eq!(from_satn(&AlgebraicType::U8, "7\n"), Ok(7u8.into()));
+        assert_eq!(from_satn(&AlgebraicType::Bool, "true\r\n"), Ok(true.into()));
+    }
+
+    #[test]
+    fn quoted_times() {
+        let ty = AlgebraicType::product([
+            ("at", AlgebraicType::timestamp()),
+            ("took", AlgebraicType::time_duration()),
+        ]);
+        let value = |at: i64, took: i64| {
+            AlgebraicValue::product([
+                AlgebraicValue::product([at.into()]),
+                AlgebraicValue::product([took.into()]),
+            ])
+        };
+        let typespace = crate::Typespace::EMPTY.with_type(&ty);
+        let fmt = SatnFmt::new().time_fmt(TimeFmt::Quoted);
+        let satn = |value: &AlgebraicValue| fmt.format_typed(typespace, value);
+        assert_eq!(
+            satn(&value(1_500_000, -1_500_000)),
+            r#"(at = "1970-01-01T00:00:01.500000Z", took = "-1.500000")"#
+        );
+        // The microseconds are recovered exactly, even at the extremes.
+        for (at, took) in [
+            (0, 0),
+            (-1, 1),
+            (1_700_000_000_123_456, i64::MAX),
+            (-62_135_596_800_000_000, i64::MIN),
+            (253_402_300_799_999_999, 0),
+        ] {
+            let value = value(at, took);
+            assert_eq!(from_satn(&ty, &satn(&value)), Ok(value));
+        }
+        // Timestamps beyond RFC 3339 are printed, and read back, as products.
+        for at in [-62_135_596_800_000_001, 253_402_300_800_000_000, i64::MAX] {
+            let beyond = value(at, 0);
+            assert_eq!(
+                satn(&beyond),
+                format!(r#"(at = (__timestamp_micros_since_unix_epoch__ = {at}), took = "+0.000000")"#)
+            );
+            assert_eq!(from_satn(&ty, &satn(&beyond)), Ok(beyond));
+        }
+
+        // Other offsets and fewer digits are read too.
+        assert_eq!(
+            from_satn(&ty, r#"(at = "1970-01-01T01:00:01+01:00", took = "2.5")"#),
+            Ok(value(1_000_000, 2_500_000))
+        );
+        assert_eq!(
+            from_satn(&ty, r#"(at = "yesterday", took = "1")"#).unwrap_err().message,
+            r#"expected an RFC 3339 timestamp, found "yesterday""#
+        );
+        for took in ["1.0000001", "1.", ".5", "+-1", "1e3"] {
+            let input = format!(r#"(at = "1970-01-01T00:00:00Z", took = "{took}")"#);
+            let err = from_satn(&ty, &input).unwrap_err();
+            assert!(err.message.starts_with("expected a duration in seconds"), "{took}");
+        }
+    }
+
+    #[test]
+    fn escaped_strings() {
+        for s in [
+            "say \"hi\"",
+            r"C:\dir\file",
+            "line\nbreak\ttab\r",
+            "\0\u{7f}",
+            "naïve 日本語 🦀",
+            "",
+        ] {
+            assert_round_trip(&AlgebraicType::String, s.into());
+        }
+        assert_eq!(
+            from_satn(&AlgebraicType::String, r#""\u{41}\u{1F980}""#),
+            Ok("A🦀".into())
+        );
+        assert_eq!(from_satn(&AlgebraicType::String, r#""\q""#).unwrap_err().offset, 1);
+        assert_eq!(
+            from_satn(&AlgebraicType::String, r#""\u{d800}""#).unwrap_err().message,
+            "invalid unicode escape"
+        );
+        assert_eq!(
+            from_satn(&AlgebraicType::String, r#""open"#).unwrap_err().message,
+            "unterminated string"
+        );
+    }
+
+    #[test]
+    fn non_finite_floats() {
+        let ty = AlgebraicType::array(AlgebraicType::F64);
+        let expected = AlgebraicValue::Array([f64::INFINITY, f64::NEG_INFINITY, 1.5].into());
+        assert_eq!(from_satn(&ty, r#"["+inf", -inf, "1.5"]"#), Ok(expected));
+        let nan = from_satn(&AlgebraicType::F32, r#""NaN""#).unwrap();
+        assert_eq!(nan.to_satn(), "NaN");
+    }
+
+    #[test]
+    fn errors() {
+        let ty = AlgebraicType::product([("a", AlgebraicType::U8), ("b", AlgebraicType::Bool)]);
+        let err = |input| from_satn(&ty, input).unwrap_err();
+        assert_eq!(err("(a = 1)").message, "missing field `b`");
+        assert_eq!(err("(a = 1, a = 2, b = true)").offset, 8);
+        assert_eq!(err("(a = 256, b = true)").message, "expected a number, found `256`");
+        assert_eq!(err("(c = 1)").message, "no field `c`");
+        assert_eq!(err("(a = 1, b = true) x").message, "unexpected trailing input");
+        assert_eq!(err("(a = 1 b = true)").message, "expected `)`");
+    }
+}
@@ -93,6 +93,13 @@ pub trait Serializer: Sized {
     /// Serialize a `&str` string slice.
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error>;
 
+    /// Serialize a `char`, for data formats that tell characters apart from strings.
+    ///
+    /// The SATS data model has no characters, so by default, this serializes `v` as a string of it.
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
     /// Serialize a `&[u8]` byte slice.
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error>;
 
@@ -103,6 +110,16 @@ pub trait Serializer: Sized {
     /// The argument is the number of elements in the sequence.
     fn serialize_array(self, len: usize) -> Result<Self::SerializeArray, Self::Error>;
 
+    /// Serialize the array of primitives `array`.
+    ///
+    /// This is used by the `Serialize for [T]` implementations of the primitives in [`PrimitiveArray`]
+    /// to allow data formats a faster path than [`serialize_array`](Serializer::serialize_array),
+    /// which they must be consistent with, and which the default implementation delegates to.
+    #[doc(hidden)]
+    fn __serialize_primitive_array(self, array: PrimitiveArray<'_>) -> Result<Self::Ok, Self::Error> {
+        array.serialize_elements(self)
+    }
+
     /// Begin to serialize a product with unnamed fields.
     /// This call must be followed by zero or more calls to [`SerializeSeqProduct::serialize_element`],
     /// then a call to [`SerializeSeqProduct::end`].
@@ -125,6 +142,15 @@ pub trait Serializer: Sized {
         value: &T,
     ) -> Result<Self::Ok, Self::Error>;
 
+    /// Serialize the unit value, i.e., the empty product `()`.
+    ///
+    /// This is a shortcut for the common case of a product without fields,
+    /// which by default serializes as an empty unnamed product,
+    /// but allows implementations to skip the product machinery.
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        self.serialize_seq_product(0)?.end()
+    }
+
     /// Serialize the given `bsatn` encoded data of type `ty`.
     ///
     /// This is a concession to performance,
@@ -285,6 +311,79 @@ pub trait Serialize {
     }
 }
 
+macro_rules! primitive_array {
+    ($($variant:ident($prim:ty)),* $(,)?) => {
+        /// A slice of fixed-width primitives,
+        /// serialized by [`Serializer::__serialize_primitive_array`].
+        #[doc(hidden)]
+        #[derive(Clone, Copy)]
+        pub enum PrimitiveArray<'a> {
+            $($variant(&'a [$prim]),)*
+        }
+
+        impl PrimitiveArray<'_> {
+            /// Returns the number of elements in the array.
+            pub fn len(&self) -> usize {
+                match self {
+                    $(Self::$variant(vs) => vs.len(),)*
+                }
+            }
+
+            /// Returns whether the array is empty.
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            /// Serializes the array element by element with [`Serializer::serialize_array`].
+            pub fn serialize_elements<S: Serializer>(self, serializer: S) -> Result<S::Ok, S::Error> {
+                fn elements<S: Serializer, T: Serialize>(serializer: S, vs: &[T]) -> Result<S::Ok, S::Error> {
+                    let mut vec = serializer.serialize_array(vs.len())?;
+                    for elem in vs {
+                        vec.serialize_element(elem)?;
+                    }
+                    vec.end()
+                }
+                match self {
+                    $(Self::$variant(vs) => elements(serializer, vs),)*
+                }
+            }
+
+            /// Writes the [`Display`](fmt::Display) of each element to `f`, separated by `sep`.
+            pub fn write_joined(self, f: &mut dyn fmt::Write, sep: &str) -> fmt::Result {
+                fn joined<T: fmt::Display>(f: &mut dyn fmt::Write, sep: &str, vs: &[T]) -> fmt::Result {
+                    let mut vs = vs.iter();
+                    if let Some(first) = vs.next() {
+                        write!(f, "{first}")?;
+                    }
+                    for v in vs {
+                        f.write_str(sep)?;
+                        write!(f, "{v}")?;
+                    }
+                    Ok(())
+                }
+                match self {
+                    $(Self::$variant(vs) => joined(f, sep, vs),)*
+                }
+            }
+        }
+    };
+}
+
+primitive_array! {
+    Bool(bool),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    U128(u128),
+    I128(i128),
+    U256(u256),
+    I256(i256),
+}
+
 /// The base trait serialization error types must implement.
 pub trait Error {
     /// Returns an error derived from `msg: impl Display`.
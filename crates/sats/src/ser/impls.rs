@@ -1,4 +1,4 @@
-use super::{Serialize, SerializeArray, SerializeNamedProduct, SerializeSeqProduct, Serializer};
+use super::{PrimitiveArray, Serialize, SerializeArray, SerializeNamedProduct, SerializeSeqProduct, Serializer};
 use crate::{i256, u256};
 use crate::{AlgebraicType, AlgebraicValue, ArrayValue, ProductValue, SumValue, ValueWithType, F32, F64};
 use core::ops::Bound;
@@ -44,13 +44,36 @@ macro_rules! impl_prim {
     };
 }
 
-impl_serialize!([] (), (self, ser) => ser.serialize_seq_product(0)?.end());
+/// Like `impl_prim!`, but also serializes arrays of the primitive
+/// with [`Serializer::__serialize_primitive_array`] as the `$variant` of [`PrimitiveArray`].
+macro_rules! impl_prim_array {
+    ($(($prim:ty, $method:ident, $variant:ident))*) => {
+        $(impl Serialize for $prim {
+            fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+                ser.$method((*self).into())
+            }
+
+            fn __serialize_array<S: Serializer>(this: &[Self], ser: S) -> Result<S::Ok, S::Error>
+            where
+                Self: Sized,
+            {
+                ser.__serialize_primitive_array(PrimitiveArray::$variant(this))
+            }
+        })*
+    };
+}
+
+impl_serialize!([] (), (self, ser) => ser.serialize_unit());
 
 // `u8` is implemented below as we wish to provide different `__serialize_array` impl (see below).
+impl_prim_array! {
+    (bool, serialize_bool, Bool)
+                                 (u16, serialize_u16, U16) (u32, serialize_u32, U32) (u64, serialize_u64, U64)
+    (i8, serialize_i8, I8)       (i16, serialize_i16, I16) (i32, serialize_i32, I32) (i64, serialize_i64, I64)
+    (u128, serialize_u128, U128) (u256, serialize_u256, U256)
+    (i128, serialize_i128, I128) (i256, serialize_i256, I256)
+}
 impl_prim! {
-    (bool, serialize_bool)
-                       (u16, serialize_u16) (u32, serialize_u32) (u64, serialize_u64) (u128, serialize_u128) (u256, serialize_u256)
-    (i8, serialize_i8) (i16, serialize_i16) (i32, serialize_i32) (i64, serialize_i64) (i128, serialize_i128) (i256, serialize_i256)
     (f32, serialize_f32) (f64, serialize_f64) (str, serialize_str)
 }
 
@@ -119,6 +142,9 @@ impl_serialize!([] AlgebraicValue, (self, ser) => match self {
     Self::Min | Self::Max => panic!("not defined for Min/Max"),
 });
 impl_serialize!([] ProductValue, (self, ser) => {
+    if self.elements.is_empty() {
+        return ser.serialize_unit();
+    }
     let mut tup = ser.serialize_seq_product(self.elements.len())?;
     for elem in &*self.elements {
         tup.serialize_element(elem)?;
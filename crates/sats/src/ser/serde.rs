@@ -81,6 +81,9 @@ impl<S: serde::Serializer> Serializer for SerdeSerializer<S> {
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
         self.ser.serialize_str(v).map_err(SerdeError)
     }
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        self.ser.serialize_char(v).map_err(SerdeError)
+    }
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
         let s = hex::encode(v);
         self.ser.serialize_str(&s).map_err(SerdeError)